@@ -1,6 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use netcat::connection::local::*;
-use netcat::rpp::Rpp;
+use netcat::connection::sim::SimulatedCacheConnector;
+use netcat::rpp::{CacheParams, Rpp};
 
 pub fn rpp_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("RRIME+PROBE benchmark");
@@ -16,5 +17,24 @@ pub fn rpp_bench(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, rpp_bench);
+// With the simulated connector there are no real memory accesses, thus
+// this one measures the CPU overhead of building eviction sets.
+pub fn rpp_sim_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RRIME+PROBE simulated benchmark");
+
+    // 1024 sets of 8 lines, which gives 16 colors
+    let cparams = CacheParams::new(64, 8, 8, 64 * 8 * 1024, 20000).unwrap();
+
+    group.sample_size(10);
+
+    group.bench_function("RPP sim bench", |b| {
+        b.iter(|| {
+            let conn = SimulatedCacheConnector::new(&cparams);
+            let quite = true;
+            let _rpp = Rpp::with_params(conn, quite, cparams);
+        })
+    });
+}
+
+criterion_group!(benches, rpp_sim_bench, rpp_bench);
 criterion_main!(benches);
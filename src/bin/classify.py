@@ -9,17 +9,46 @@ train_data = sys.argv[1]
 lables_file = sys.argv[2]
 data_file = sys.argv[3]
 
-with open(train_data) as f:
-    X = json.load(f)
+
+def load_sessions(f):
+    """Loads tracker output, recorded as JSON Lines. Each session becomes a sample
+    of the timestamps of its measurements, relative to the first one."""
+    sessions = []
+    for line in f:
+        line = line.strip()
+        if not line:
+            continue
+        msg = json.loads(line)
+        if "Session" in msg:
+            sessions.append([])
+        elif "Data" in msg and sessions:
+            sessions[-1].append(msg["Data"]["elapsed"])
+
+    sessions = [[t - s[0] for t in s] for s in sessions if s]
+    # the samples should have the same length
+    n = min(len(s) for s in sessions)
+    return [s[:n] for s in sessions]
+
+
+def load(file_name):
+    """Loads a JSON document or tracker output"""
+    with open(file_name) as f:
+        try:
+            return json.load(f)
+        except json.JSONDecodeError:
+            f.seek(0)
+            return load_sessions(f)
+
+
+X = load(train_data)
 
 with open(lables_file) as f:
     Y = json.load(f)
 
-with open(data_file) as f:
-    data = json.load(f)
+data = load(data_file)
 
 neigh = KNeighborsClassifier(n_neighbors=5)
 
 neigh.fit(X, Y)
 
-print(neigh.predict(data))
\ No newline at end of file
+print(neigh.predict(data))
@@ -1,13 +1,39 @@
 use clap::{crate_authors, crate_version, App, Arg};
+use console::style;
+use indicatif::{ProgressBar, ProgressStyle};
+use netcat::connection::{CacheConnector, PacketSender};
+use netcat::online_tracker::{
+    Arrival, Checkpoint, CheckpointConfig, Measurement, OnlineTracker, OnlineTrackerBuilder,
+    SavedPatterns, SyncPolicy, TrackerStats, TrackingLimit,
+};
+use netcat::output::{Record, SessionFooter};
+use netcat::rpp::{
+    presets, ActivationRule, Aging, ClassifierKind, Contents, DriftConfig, MeasureMode,
+    MeasurePhases, PrimeStrategy, ProbeStrategy,
+};
 use std::net::IpAddr;
+use std::process::exit;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 const DEFAULT_PORT: &str = "9003";
 const DEFAULT_MEASUREMENT_CNT: &str = "1000";
 const DEFAULT_CACHE: &str = "E5_DDIO";
+const DEFAULT_STRATEGY: &str = "seq";
+const DEFAULT_CLASSIFIER: &str = "centroid";
+const DEFAULT_MEASURE_MODE: &str = "single";
+const DEFAULT_CALIBRATION_SAMPLES: usize = 1000;
+const DEFAULT_MIN_PATTERN_SCORE: &str = "0.5";
+const DEFAULT_SYNC_POLICY: &str = "every:3";
+const DEFAULT_WINDOW_SIZE: &str = "10";
+const DEFAULT_CHECKPOINT_EVERY: &str = "1000";
+const DASHBOARD_REFRESH: Duration = Duration::from_millis(200);
 
 static CONN_TYPES: &[&str] = &["rdma", "local"];
-static CACHES: &[&str] = &["E5_DDIO", "E5", "I7", "PLATINUM", "PLATINUM_DDIO", "custom"];
+static CACHES: &[&str] = &["E5_DDIO", "E5", "I7", "PLATINUM", "PLATINUM_DDIO", "custom", "auto"];
 
 fn main() {
     let matches = app_cli_config().get_matches();
@@ -19,6 +45,191 @@ fn main() {
     }
 }
 
+/// Tracking settings, which do not depend on the connection
+#[derive(Clone, Debug, Default)]
+struct Settings {
+    prime: PrimeStrategy,
+    probe: ProbeStrategy,
+    rule: ActivationRule,
+    classifier: ClassifierKind,
+    drift: DriftConfig,
+    measure: MeasurePhases,
+    min_pattern_score: f64,
+    sync: SyncPolicy,
+    window: usize,
+    /// Chains the patterns of all colors into a single RX ring
+    reconstruct: bool,
+    /// Records only the arrivals of victim packets, without sending any
+    passive: bool,
+    /// RX patterns of an earlier session to start from
+    saved: Option<SavedPatterns>,
+    /// File to save the located RX patterns to
+    save_to: Option<String>,
+    /// Where and how often tracking is checkpointed
+    checkpoints: Option<CheckpointConfig>,
+    /// The checkpointed session to continue. It replaces the other settings.
+    resume: Option<Checkpoint>,
+}
+
+impl Settings {
+    fn apply<C, R, S>(self, builder: OnlineTrackerBuilder<C, R, S>) -> OnlineTrackerBuilder<C, R, S>
+    where
+        C: CacheConnector<Item = Contents>,
+        R: Record<Measurement>,
+        S: PacketSender,
+    {
+        let builder = match self.saved {
+            Some(saved) => builder.set_saved_patterns(saved),
+            None => builder,
+        };
+
+        let builder = builder
+            .set_prime_strategy(self.prime)
+            .set_probe_strategy(self.probe)
+            .set_activation_rule(self.rule)
+            .set_classifier(self.classifier)
+            .set_drift(self.drift)
+            .set_measure_phases(self.measure)
+            .set_min_pattern_score(self.min_pattern_score)
+            .set_sync_policy(self.sync)
+            .set_window_size(self.window)
+            .set_reconstruct_ring(self.reconstruct);
+
+        let builder = match self.checkpoints {
+            Some(checkpoints) => builder.set_checkpoints(checkpoints),
+            None => builder,
+        };
+
+        match self.resume {
+            Some(checkpoint) => builder.resume_from(checkpoint),
+            None => builder,
+        }
+    }
+}
+
+/// Sets the stop flag on SIGINT or SIGTERM, so that the tracker stops after
+/// the current measurement and flushes the output. The second signal exits at once.
+fn handle_signals(stop: Arc<AtomicBool>, quite: bool) {
+    let res = ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::SeqCst) {
+            exit(130);
+        }
+        if !quite {
+            eprintln!("Online Tracker: stopping after the current measurement");
+        }
+    });
+
+    if let Err(e) = res {
+        if !quite {
+            eprintln!("Cannot handle signals: {}", e);
+        }
+    }
+}
+
+/// Tells about an error and its causes
+fn error_report(e: &dyn std::error::Error) -> String {
+    let mut report = format!("ERROR: {}", e);
+    let mut cause = e.source();
+    while let Some(e) = cause {
+        report.push_str(&format!(": {}", e));
+        cause = e.source();
+    }
+    report
+}
+
+/// Tracks until the limit, showing the live statistics of the tracker. The tracker
+/// prints the summary of the statistics at the end. Passive tracking records
+/// the arrivals on all located sets.
+fn track_with_dashboard<C, R, S>(
+    tracker: &mut OnlineTracker<C, R, S>,
+    limit: TrackingLimit,
+    passive: bool,
+    quite: bool,
+) -> netcat::Result<SessionFooter>
+where
+    C: CacheConnector<Item = Contents>,
+    R: Record<Measurement> + Record<Arrival>,
+    S: PacketSender,
+{
+    let track = |tracker: &mut OnlineTracker<C, R, S>| {
+        if passive {
+            let sets = tracker.pattern_sets();
+            tracker.track_arrivals(limit, &sets)
+        } else {
+            tracker.track_until(limit)
+        }
+    };
+
+    if quite {
+        return track(tracker);
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let dashboard = show_dashboard(tracker.stats_handle(), limit, Arc::clone(&done));
+    let res = track(tracker);
+    done.store(true, Ordering::SeqCst);
+    let _ = dashboard.join();
+
+    res
+}
+
+// Draws the statistics in a progress bar until `done` is set
+fn show_dashboard(
+    stats: Arc<Mutex<TrackerStats>>,
+    limit: TrackingLimit,
+    done: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let len = match limit {
+            TrackingLimit::Count(cnt) => cnt as u64,
+            TrackingLimit::Duration(duration) => duration.as_millis() as u64,
+        };
+        let pb = ProgressBar::new(len);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("({elapsed}) [{bar:40.cyan/blue}] {percent}% {msg}")
+                .progress_chars("#>-"),
+        );
+
+        while !done.load(Ordering::SeqCst) {
+            let mut snapshot = match stats.lock() {
+                Ok(stats) => stats.clone(),
+                Err(e) => e.into_inner().clone(),
+            };
+            snapshot.update_elapsed();
+
+            pb.set_position(match limit {
+                TrackingLimit::Count(_) => snapshot.measurements as u64,
+                TrackingLimit::Duration(_) => snapshot.elapsed / 1_000_000,
+            });
+            pb.set_message(&dashboard_line(&snapshot));
+            thread::sleep(DASHBOARD_REFRESH);
+        }
+
+        pb.finish_and_clear();
+    })
+}
+
+fn dashboard_line(stats: &TrackerStats) -> String {
+    let activations: Vec<String> = stats
+        .activations
+        .iter()
+        .map(|positions| positions.iter().sum::<usize>().to_string())
+        .collect();
+
+    format!(
+        "{:.0}/s | sync {} {} {} | retries {}/{} | latency {:.0} | rings {}",
+        stats.rate(),
+        style(stats.sync_hits).green(),
+        style(stats.sync_misses).yellow(),
+        style(stats.recover_failures).red(),
+        stats.retries,
+        stats.max_retries,
+        stats.avg_probe_latency(),
+        activations.join("/"),
+    )
+}
+
 fn app_cli_config<'a, 'b>() -> App<'a, 'b> {
     App::new("NetCAT PoC")
         .version(crate_version!())
@@ -41,7 +252,7 @@ fn app_cli_config<'a, 'b>() -> App<'a, 'b> {
                 .short("a")
                 .takes_value(true)
                 .value_name("IP_ADDR")
-                .required(true)
+                .required_unless("convert")
                 .default_value_if("interactive", None, "127.0.0.1")
                 .validator(|x| match IpAddr::from_str(x.as_str()) {
                     Ok(_) => Ok(()),
@@ -75,9 +286,21 @@ fn app_cli_config<'a, 'b>() -> App<'a, 'b> {
                     Err(_) => Err(String::from("MEAUSEMENTS should be a number")),
                 }),
         )
+        .arg(
+            Arg::with_name("duration")
+                .help("Tracks for the given number of seconds instead of taking a fixed number of measurements")
+                .long("duration")
+                .takes_value(true)
+                .conflicts_with("measurements")
+                .value_name("SECONDS")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(()),
+                    _ => Err(String::from("SECONDS should be a non-negative number")),
+                }),
+        )
         .arg(
             Arg::with_name("cache_description")
-                .help("Parameters, describing last level cache of the victim's machine")
+                .help("Parameters, describing last level cache of the victim's machine. `auto` detects the local cache and is only available for the local connection")
                 .long("cache_params")
                 .short("d")
                 .value_name("CACHE")
@@ -95,6 +318,236 @@ fn app_cli_config<'a, 'b>() -> App<'a, 'b> {
                     Err(_) => Err("Invalind cache parameters: should be numbers".to_string())
                 })
         )
+        .arg(
+            Arg::with_name("cache_preset")
+                .help("Takes parameters of the victim's LLC from the bundled preset database. Overrides --cache_params")
+                .long("cache-preset")
+                .value_name("PRESET")
+                .conflicts_with("cache_file")
+                .validator(|s| match presets::preset(&s) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("Unknown preset. Available: {}", presets::names().join(", ")))
+                })
+        )
+        .arg(
+            Arg::with_name("cache_file")
+                .help("Reads parameters of the victim's LLC from a TOML or JSON file. Overrides --cache_params")
+                .long("cache-file")
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::with_name("prime_strategy")
+                .help("Order of accesses while priming an eviction set: seq, repeat:N, zigzag:N or random:N")
+                .long("prime")
+                .value_name("STRATEGY")
+                .default_value(DEFAULT_STRATEGY)
+                .validator(|s| s.parse::<PrimeStrategy>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("probe_strategy")
+                .help("Order of accesses while probing an eviction set: seq, reverse, random or first:K")
+                .long("probe")
+                .value_name("STRATEGY")
+                .default_value(DEFAULT_STRATEGY)
+                .validator(|s| s.parse::<ProbeStrategy>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("classifier")
+                .help("Classifier of access timings: centroid, threshold:N, gmm, otsu or levels:N. The latter treats outliers as invalid")
+                .long("classifier")
+                .value_name("CLASSIFIER")
+                .default_value(DEFAULT_CLASSIFIER)
+                .validator(|s| s.parse::<ClassifierKind>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("aging")
+                .help("Aging of the timings, the classifier is trained on: none, window:N or decay:N")
+                .long("aging")
+                .value_name("AGING")
+                .default_value("none")
+                .validator(|s| s.parse::<Aging>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("recalibrate")
+                .help("Retrains the classifier after each NUM measurements. 0 disables recalibration")
+                .long("recalibrate")
+                .value_name("NUM")
+                .default_value("0")
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(String::from("Should be a number")),
+                })
+        )
+        .arg(
+            Arg::with_name("max_overlap")
+                .help("Raises the drift alarm, when this share (0 to 1) of calibration timings is misclassified")
+                .long("max-overlap")
+                .value_name("SHARE")
+                .default_value("0.1")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(c) if (0.0..=1.0).contains(&c) => Ok(()),
+                    _ => Err(String::from("Should be a number from 0 to 1")),
+                })
+        )
+        .arg(
+            Arg::with_name("min_misses")
+                .help("Minimal number of missed lines in a probed set to count it as activated")
+                .long("min-misses")
+                .value_name("NUM")
+                .default_value("1")
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(String::from("Should be a positive number")),
+                })
+        )
+        .arg(
+            Arg::with_name("min_confidence")
+                .help("Minimal confidence (0 to 1) of the misses in a probed set to count it as activated")
+                .long("min-confidence")
+                .value_name("CONFIDENCE")
+                .default_value("0")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(c) if (0.0..=1.0).contains(&c) => Ok(()),
+                    _ => Err(String::from("Should be a number from 0 to 1")),
+                })
+        )
+        .arg(
+            Arg::with_name("min_pattern_score")
+                .help("Minimal score (0 to 1) of an RX buffer pattern to be tracked")
+                .long("min-pattern-score")
+                .value_name("SCORE")
+                .default_value(DEFAULT_MIN_PATTERN_SCORE)
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(c) if (0.0..=1.0).contains(&c) => Ok(()),
+                    _ => Err(String::from("Should be a number from 0 to 1")),
+                })
+        )
+        .arg(
+            Arg::with_name("sync_policy")
+                .help("When packets are injected to synchronize with the RX buffer: every:N, idle:MS, adaptive:N or never")
+                .long("sync")
+                .value_name("POLICY")
+                .default_value(DEFAULT_SYNC_POLICY)
+                .validator(|s| s.parse::<SyncPolicy>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("window")
+                .help("Number of sets, probed around the current position in the RX buffer")
+                .long("window")
+                .value_name("SIZE")
+                .default_value(DEFAULT_WINDOW_SIZE)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(n) if n >= 3 => Ok(()),
+                    _ => Err(String::from("Should be a number of at least 3")),
+                })
+        )
+        .arg(
+            Arg::with_name("reconstruct")
+                .help("Reconstructs an RX ring, which spans many pages, from the patterns of all colors and tracks it")
+                .long("reconstruct")
+        )
+        .arg(
+            Arg::with_name("passive")
+                .help("Records only the arrival times of victim packets on the located sets. No packets are sent after the initialization")
+                .long("passive")
+        )
+        .arg(
+            Arg::with_name("load_pattern")
+                .help("Starts from the RX patterns, saved by an earlier session. Falls back to the full location if they no longer activate")
+                .long("load-pattern")
+                .value_name("FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("save_pattern")
+                .help("Saves the located RX patterns with their eviction sets to the file")
+                .long("save-pattern")
+                .value_name("FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .help("Saves checkpoints of tracking to the file, from which it can be resumed with --resume")
+                .long("checkpoint")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("output")
+                .conflicts_with("passive")
+        )
+        .arg(
+            Arg::with_name("checkpoint_every")
+                .help("Number of measurements between the checkpoints")
+                .long("checkpoint-every")
+                .value_name("NUM")
+                .default_value(DEFAULT_CHECKPOINT_EVERY)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(String::from("Should be a positive number")),
+                })
+        )
+        .arg(
+            Arg::with_name("resume")
+                .help("Resumes tracking from the checkpoint and continues its output, which cannot be replaced by another one. The settings and the cache parameters are taken from the checkpoint, the measurements are counted from the start of the checkpointed session. Further checkpoints replace it, unless --checkpoint is given")
+                .long("resume")
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with_all(&["passive", "load_pattern", "reconstruct"])
+        )
+        .arg(
+            Arg::with_name("measure_profiling")
+                .help("How accesses are timed while building eviction sets: single, median:N, trimmed:N or adaptive:N")
+                .long("measure-profiling")
+                .value_name("MODE")
+                .default_value(DEFAULT_MEASURE_MODE)
+                .validator(|s| s.parse::<MeasureMode>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("measure_locating")
+                .help("How accesses are timed while locating the RX buffer: single, median:N, trimmed:N or adaptive:N")
+                .long("measure-locating")
+                .value_name("MODE")
+                .default_value(DEFAULT_MEASURE_MODE)
+                .validator(|s| s.parse::<MeasureMode>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("measure_tracking")
+                .help("How accesses are timed while tracking: single, median:N, trimmed:N or adaptive:N")
+                .long("measure-tracking")
+                .value_name("MODE")
+                .default_value(DEFAULT_MEASURE_MODE)
+                .validator(|s| s.parse::<MeasureMode>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("calibrate")
+                .help("Only samples hit and miss timings and reports the quality of the timing channel. The report is dumped to the output as JSON. Use --calibrate=SAMPLES to set the number of samples")
+                .long("calibrate")
+                .require_equals(true)
+                .value_name("SAMPLES")
+                .min_values(0)
+                .max_values(1)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(n) if n > 1 => Ok(()),
+                    _ => Err(String::from("Should be a number greater than 1")),
+                })
+        )
+        .arg(
+            Arg::with_name("binary")
+                .help("Records the output in the compact binary format instead of JSON Lines. Use --binary=gzip to compress it. Compressed output cannot be continued from a checkpoint")
+                .long("binary")
+                .require_equals(true)
+                .value_name("COMPRESSION")
+                .min_values(0)
+                .max_values(1)
+                .possible_values(&["none", "gzip"])
+        )
+        .arg(
+            Arg::with_name("convert")
+                .help("Only converts the tracker output in the file and writes it to the output. Binary output is converted to JSON Lines, JSON Lines to the binary format, compressed as set by --binary. Use --passive for the output of passive tracking")
+                .long("convert")
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with_all(&["resume", "checkpoint", "calibrate"])
+        )
         .arg_from_usage("[quite] -q --quite 'Does not disturb anyone by the output'")
         .arg_from_usage("[output] 'Output file to dump data to'")
 }
@@ -105,45 +558,147 @@ mod uninteractive {
     use netcat::connection::local::{LocalMemoryConnector, LocalPacketSender};
     use netcat::connection::rdma::{RdmaServerConnector, RemotePacketSender};
     use netcat::connection::{CacheConnector, PacketSender};
-    use netcat::online_tracker::{LatsEntry, OnlineTracker, OnlineTrackerBuilder};
-    use netcat::output::{file::JsonRecorder, Record};
+    use netcat::online_tracker::{
+        Arrival, Checkpoint, CheckpointConfig, Measurement, OnlineTracker, OnlineTrackerBuilder,
+        SavedPatterns, SyncPolicy, TrackingLimit,
+    };
+    use netcat::output::binary::{self, BinaryRecorder, Compression};
+    use netcat::output::file::JsonLinesRecorder;
+    use netcat::output::{Event, Record, SessionFooter, SessionInfo};
     use netcat::rpp::params::CacheParams;
     use netcat::rpp::params::*;
-    use netcat::rpp::Contents;
-    use std::fs::File;
-    use std::io::{stdout, BufWriter};
+    use super::{error_report, handle_signals, Settings, DEFAULT_CALIBRATION_SAMPLES};
+    use netcat::rpp::{
+        calibration, detect, presets, ActivationRule, Aging, CalibrationReport, ClassifierKind,
+        Contents, DriftConfig, MeasureMode, MeasurePhases, PrimeStrategy, ProbeStrategy,
+    };
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, stdout, BufReader, BufWriter, Seek, SeekFrom, Write};
+    use std::path::Path;
     use std::process::exit;
+    use std::time::Duration;
+
+    /// The recorder of the chosen output format
+    enum Output<W: Write> {
+        Json(JsonLinesRecorder<W>),
+        Binary(BinaryRecorder<W>),
+    }
+
+    impl<W: Write> Output<W> {
+        /// Records JSON Lines, or the binary format with the given compression
+        fn new(w: W, binary: Option<Compression>) -> netcat::Result<Output<W>> {
+            Ok(match binary {
+                Some(compression) => Output::Binary(BinaryRecorder::new(w, compression)?),
+                None => Output::Json(JsonLinesRecorder::new(w)),
+            })
+        }
+    }
+
+    impl<T: Serialize, W: Write> Record<T> for Output<W> {
+        fn record(&mut self, data: T) -> netcat::Result<()> {
+            match self {
+                Output::Json(r) => r.record(data),
+                Output::Binary(r) => r.record(data),
+            }
+        }
+
+        fn describe(&mut self, info: &SessionInfo) -> netcat::Result<()> {
+            match self {
+                Output::Json(r) => Record::<T>::describe(r, info),
+                Output::Binary(r) => Record::<T>::describe(r, info),
+            }
+        }
+
+        fn event(&mut self, event: &Event) -> netcat::Result<()> {
+            match self {
+                Output::Json(r) => Record::<T>::event(r, event),
+                Output::Binary(r) => Record::<T>::event(r, event),
+            }
+        }
+
+        fn finish(&mut self, footer: &SessionFooter) -> netcat::Result<()> {
+            match self {
+                Output::Json(r) => Record::<T>::finish(r, footer),
+                Output::Binary(r) => Record::<T>::finish(r, footer),
+            }
+        }
+
+        fn flush(&mut self) -> netcat::Result<()> {
+            match self {
+                Output::Json(r) => Record::<T>::flush(r),
+                Output::Binary(r) => Record::<T>::flush(r),
+            }
+        }
+
+        fn offset(&self) -> Option<u64> {
+            match self {
+                Output::Json(r) => Record::<T>::offset(r),
+                Output::Binary(r) => Record::<T>::offset(r),
+            }
+        }
+    }
 
     pub fn run_session(args: ArgMatches) {
         let quite = args.is_present("quite");
-        let port = value_t!(args.value_of("port"), u16).unwrap();
-        let cnt = value_t!(args.value_of("measurements"), usize).unwrap();
-        let output = args.value_of("output");
-
-        let cache_type = args.value_of("cache_description").unwrap();
+        // validated by clap
+        let binary = if args.is_present("binary") {
+            Some(value_t!(args.value_of("binary"), Compression).unwrap_or(Compression::None))
+        } else {
+            None
+        };
 
-        let cparams = match cache_type {
-            "E5" => XEON_E5,
-            "E5_DDIO" => XEON_E5_DDIO,
-            "I7" => CORE_I7,
-            "PLATINUM" => XEON_PLATINUM,
-            "PLATINUM_DDIO" => XEON_PLATINUM_DDIO,
-            "custom" => {
-                let mut vals = args.values_of("custom_cache").unwrap();
-                let bytes_per_line = vals.next().unwrap().parse().unwrap();
-                let lines_per_set = vals.next().unwrap().parse().unwrap();
-                let reachable_lines = vals.next().unwrap().parse().unwrap();
-                let cache_size = vals.next().unwrap().parse().unwrap();
-                let num_addrs = vals.next().unwrap().parse().unwrap();
-                CacheParams::new(
-                    bytes_per_line,
-                    lines_per_set,
-                    reachable_lines,
-                    cache_size,
-                    num_addrs,
-                )
+        if let Some(input) = args.value_of("convert") {
+            let passive = args.is_present("passive");
+            let output = args.value_of("output");
+            match convert(input, output, binary.unwrap_or_default(), passive) {
+                Ok(cnt) if !quite => eprintln!("Converted {} records", cnt),
+                Ok(_) => {}
+                Err(e) => {
+                    if !quite {
+                        panic!("{}", style(error_report(&e)).red());
+                    }
+                    exit(1);
+                }
             }
-            _ => panic!("Unsupported value"),
+            return;
+        }
+
+        let port = value_t!(args.value_of("port"), u16).unwrap();
+        // validated by clap
+        let limit = match args.value_of("duration") {
+            Some(secs) => TrackingLimit::Duration(Duration::from_secs_f64(secs.parse().unwrap())),
+            None => TrackingLimit::Count(value_t!(args.value_of("measurements"), usize).unwrap()),
+        };
+        let resume = args.value_of("resume").map(|file_name| {
+            Checkpoint::from_file(file_name).unwrap_or_else(|e| {
+                if !quite {
+                    panic!("{}", style(error_report(&e)).red());
+                }
+                exit(1);
+            })
+        });
+        let output = match &resume {
+            Some(checkpoint) => Some(
+                resumed_output(args.value_of("output"), checkpoint).unwrap_or_else(|e| {
+                    if !quite {
+                        panic!("{}", style(error_report(&e)).red());
+                    }
+                    exit(1);
+                }),
+            ),
+            None => args.value_of("output").map(String::from),
+        };
+        let output = output.as_deref();
+
+        let cache = get_cache(&args, quite);
+        let settings = get_settings(&args, output, resume);
+        // validated by clap
+        let calibrate = if args.is_present("calibrate") {
+            Some(value_t!(args.value_of("calibrate"), usize).unwrap_or(DEFAULT_CALIBRATION_SAMPLES))
+        } else {
+            None
         };
 
         let ip = args.value_of("address").unwrap();
@@ -152,7 +707,7 @@ mod uninteractive {
         if args.value_of("connection").unwrap() == "rdma" {
             let sender = RemotePacketSender::new((ip, port)).unwrap_or_else(|e| {
                 if !quite {
-                    panic!("{}", style(e).red());
+                    panic!("{}", style(error_report(&e)).red());
                 }
                 exit(1);
             });
@@ -160,98 +715,432 @@ mod uninteractive {
             // these are required for rdma and validated
             let conn = RdmaServerConnector::new((ip, port)).unwrap_or_else(|e| {
                 if !quite {
-                    panic!("{}", style(e).red());
+                    panic!("{}", style(error_report(&e)).red());
                 }
                 exit(1);
             });
 
-            do_measurements(sender, conn, cnt, quite, cparams, output);
+            if let Some(samples) = calibrate {
+                return do_calibration(conn, samples, quite, &cache.0, settings.classifier, output);
+            }
+
+            do_measurements(sender, conn, limit, quite, cache, settings, (output, binary));
         } else {
             let sender = LocalPacketSender::new((ip, port)).unwrap_or_else(|e| {
                 if !quite {
-                    panic!("{}", style(e).red());
+                    panic!("{}", style(error_report(&e)).red());
                 }
                 exit(1);
             });
 
             let conn = LocalMemoryConnector::new();
 
-            do_measurements(sender, conn, cnt, quite, cparams, output);
+            if let Some(samples) = calibrate {
+                return do_calibration(conn, samples, quite, &cache.0, settings.classifier, output);
+            }
+
+            do_measurements(sender, conn, limit, quite, cache, settings, (output, binary));
+        }
+    }
+
+    fn get_settings(args: &ArgMatches, output: Option<&str>, resume: Option<Checkpoint>) -> Settings {
+        let saved = args.value_of("load_pattern").map(|file_name| {
+            SavedPatterns::from_file(file_name).unwrap_or_else(|e| {
+                if !args.is_present("quite") {
+                    panic!("{}", style(error_report(&e)).red());
+                }
+                exit(1);
+            })
+        });
+
+        // validated by clap
+        let checkpoints = args
+            .value_of("checkpoint")
+            .or_else(|| args.value_of("resume"))
+            .map(|file_name| CheckpointConfig {
+                path: file_name.into(),
+                every: value_t!(args.value_of("checkpoint_every"), usize).unwrap(),
+                output: output.map(|output| {
+                    absolute_path(output).unwrap_or_else(|e| {
+                        if !args.is_present("quite") {
+                            panic!("{}", style(error_report(&e)).red());
+                        }
+                        exit(1);
+                    })
+                }),
+            });
+
+        // all of these are validated by clap
+        Settings {
+            prime: value_t!(args.value_of("prime_strategy"), PrimeStrategy).unwrap(),
+            probe: value_t!(args.value_of("probe_strategy"), ProbeStrategy).unwrap(),
+            rule: ActivationRule::new(
+                value_t!(args.value_of("min_misses"), usize).unwrap(),
+                value_t!(args.value_of("min_confidence"), f64).unwrap(),
+            )
+            .unwrap(),
+            classifier: value_t!(args.value_of("classifier"), ClassifierKind).unwrap(),
+            drift: DriftConfig {
+                aging: value_t!(args.value_of("aging"), Aging).unwrap(),
+                recalibrate_every: value_t!(args.value_of("recalibrate"), usize).unwrap(),
+                max_overlap: value_t!(args.value_of("max_overlap"), f64).unwrap(),
+                ..Default::default()
+            },
+            measure: MeasurePhases {
+                profiling: value_t!(args.value_of("measure_profiling"), MeasureMode).unwrap(),
+                locating: value_t!(args.value_of("measure_locating"), MeasureMode).unwrap(),
+                tracking: value_t!(args.value_of("measure_tracking"), MeasureMode).unwrap(),
+            },
+            min_pattern_score: value_t!(args.value_of("min_pattern_score"), f64).unwrap(),
+            sync: value_t!(args.value_of("sync_policy"), SyncPolicy).unwrap(),
+            window: value_t!(args.value_of("window"), usize).unwrap(),
+            reconstruct: args.is_present("reconstruct"),
+            passive: args.is_present("passive"),
+            saved,
+            save_to: args.value_of("save_pattern").map(String::from),
+            checkpoints,
+            resume,
         }
     }
 
+    fn get_cache(args: &ArgMatches, quite: bool) -> (CacheParams, CacheSource) {
+        let fail = |e: String| -> ! {
+            if !quite {
+                panic!("{}", style(e).red());
+            }
+            exit(1);
+        };
+
+        if let Some(file_name) = args.value_of("cache_file") {
+            let cparams =
+                CacheParams::from_file(file_name).unwrap_or_else(|e| fail(error_report(&e)));
+            if !quite {
+                eprintln!("{}", cparams.report());
+            }
+            return (cparams, CacheSource::File(file_name.to_string()));
+        }
+
+        if let Some(name) = args.value_of("cache_preset") {
+            // validated by clap
+            let cparams = presets::preset(name).unwrap();
+            if !quite {
+                eprintln!("{}", cparams.report());
+            }
+            return (cparams, CacheSource::Preset(name.to_string()));
+        }
+
+        let cache_type = args.value_of("cache_description").unwrap();
+
+        let cparams = match cache_type {
+            "E5" => XEON_E5,
+            "E5_DDIO" => XEON_E5_DDIO,
+            "I7" => CORE_I7,
+            "PLATINUM" => XEON_PLATINUM,
+            "PLATINUM_DDIO" => XEON_PLATINUM_DDIO,
+            "custom" => {
+                let mut vals = args.values_of("custom_cache").unwrap();
+                let bytes_per_line = vals.next().unwrap().parse().unwrap();
+                let lines_per_set = vals.next().unwrap().parse().unwrap();
+                let reachable_lines = vals.next().unwrap().parse().unwrap();
+                let cache_size = vals.next().unwrap().parse().unwrap();
+                let num_addrs = vals.next().unwrap().parse().unwrap();
+                let cparams = CacheParams::new(
+                    bytes_per_line,
+                    lines_per_set,
+                    reachable_lines,
+                    cache_size,
+                    num_addrs,
+                )
+                .unwrap_or_else(|e| fail(error_report(&e)));
+                if !quite {
+                    eprintln!("{}", cparams.report());
+                }
+                return (cparams, CacheSource::Custom);
+            }
+            "auto" => {
+                if args.value_of("connection").unwrap() != "local" {
+                    fail(
+                        "ERROR: Cache detection is only available for the local connection"
+                            .to_string(),
+                    );
+                }
+                let detected = detect::detect().unwrap_or_else(|e| fail(error_report(&e)));
+                if !quite {
+                    eprintln!(
+                        "Detected cache: {:?} ({} slices)",
+                        detected.params, detected.slices
+                    );
+                }
+                return (detected.params, CacheSource::Detected(detected));
+            }
+            _ => panic!("Unsupported value"),
+        };
+        if !quite {
+            eprintln!("{}", cparams.report());
+        }
+
+        (cparams, CacheSource::Builtin)
+    }
+
+    fn do_calibration<C: CacheConnector>(
+        mut conn: C,
+        samples: usize,
+        quite: bool,
+        cparams: &CacheParams,
+        classifier: ClassifierKind,
+        output: Option<&str>,
+    ) {
+        let fail = |e: String| -> ! {
+            if !quite {
+                panic!("{}", style(e).red());
+            }
+            exit(1);
+        };
+
+        let samples = calibration::sample(&mut conn, cparams, samples)
+            .unwrap_or_else(|e| fail(error_report(&e)));
+        let report =
+            CalibrationReport::new(&samples, classifier).unwrap_or_else(|e| fail(error_report(&e)));
+
+        if !quite {
+            eprintln!("{}", report);
+        }
+
+        let res = match output {
+            Some(file_name) => {
+                let file = File::create(file_name).unwrap_or_else(|e| fail(error_report(&e)));
+                serde_json::to_writer_pretty(BufWriter::new(file), &report)
+            }
+            None => serde_json::to_writer_pretty(stdout(), &report),
+        };
+        res.unwrap_or_else(|e| fail(error_report(&e)));
+    }
+
     fn do_measurements<S, C>(
         sender: S,
         conn: C,
-        cnt: usize,
+        limit: TrackingLimit,
         quite: bool,
-        cparams: CacheParams,
-        output: Option<&str>,
+        (cparams, cache_source): (CacheParams, CacheSource),
+        settings: Settings,
+        (output, binary): (Option<&str>, Option<Compression>),
     ) where
         S: PacketSender,
         C: CacheConnector<Item = Contents>,
     {
+        let save_to = settings.save_to.clone();
+        let passive = settings.passive;
+        let resumed = settings.resume.as_ref().map(|c| c.output_offset);
+
         if let Some(file_name) = output {
             // The user provided output location
-            let file = File::create(file_name).unwrap_or_else(|e| {
+            let output = open_output(file_name, resumed, binary).unwrap_or_else(|e| {
                 if !quite {
-                    panic!("Error while opening file: {}", style(e).red());
+                    panic!("Error while opening file: {}", style(error_report(&e)).red());
                 }
                 exit(1)
             });
 
-            let output = JsonRecorder::new(BufWriter::new(file));
-            let tracker = OnlineTrackerBuilder::new()
+            // the settings go last, as a resumed checkpoint replaces the cache parameters
+            let builder = OnlineTrackerBuilder::new()
                 .set_conn(conn)
                 .set_sender(sender)
                 .set_quite(quite)
                 .set_cache(cparams)
-                .set_output(output)
+                .set_cache_source(cache_source)
+                .set_output(output);
+            let tracker = settings
+                .apply(builder)
                 .finalize()
                 .unwrap_or_else(|e| {
                     if !quite {
-                        panic!("{}", style(e).red());
+                        panic!("{}", style(error_report(&e)).red());
                     }
                     exit(1);
                 });
 
-            run_tracker(tracker, cnt, quite);
+            run_tracker(tracker, limit, passive, quite, save_to.as_deref());
         } else {
             // The user did not provide output, printing to stdout
-            let output = JsonRecorder::new(BufWriter::new(stdout()));
+            let output = Output::new(BufWriter::new(stdout()), binary).unwrap_or_else(|e| {
+                if !quite {
+                    panic!("{}", style(error_report(&e)).red());
+                }
+                exit(1)
+            });
 
-            let tracker = OnlineTrackerBuilder::new()
+            let tracker = settings
+                .apply(OnlineTrackerBuilder::new())
                 .set_conn(conn)
                 .set_sender(sender)
                 .set_quite(quite)
                 .set_cache(cparams)
+                .set_cache_source(cache_source)
                 .set_output(output)
                 .finalize()
                 .unwrap_or_else(|e| {
                     if !quite {
-                        panic!("{}", style(e).red());
+                        panic!("{}", style(error_report(&e)).red());
                     }
                     exit(1);
                 });
 
-            run_tracker(tracker, cnt, quite);
+            run_tracker(tracker, limit, passive, quite, save_to.as_deref());
+        }
+    }
+
+    // The output of a resumed session, which continues the output of the checkpoint.
+    // Another output cannot be given, as it would be cut to the size of that one.
+    fn resumed_output(given: Option<&str>, checkpoint: &Checkpoint) -> netcat::Result<String> {
+        let own = checkpoint.output.as_deref().ok_or_else(|| {
+            netcat::Error::InvalidData("the checkpoint has no output file to continue".to_string())
+        })?;
+
+        match given {
+            Some(given) if absolute_path(given)? != absolute_path(own)? => {
+                Err(netcat::Error::InvalidConfig(format!(
+                    "the checkpoint continues {}, not {}",
+                    own, given
+                )))
+            }
+            _ => Ok(own.to_string()),
         }
     }
 
-    fn run_tracker<C, R, S>(mut tracker: OnlineTracker<C, R, S>, cnt: usize, quite: bool)
+    // The absolute path of a file, which may not exist yet. The checkpoints note it,
+    // so that tracking can be resumed from another directory.
+    fn absolute_path(file_name: &str) -> io::Result<String> {
+        let path = Path::new(file_name);
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file", file_name))
+        })?;
+
+        Ok(fs::canonicalize(dir)?.join(name).to_string_lossy().into_owned())
+    }
+
+    // Creates the output file. The output of a resumed session is cut to its size at
+    // the checkpoint, dropping what was recorded after it, and continued in its own
+    // format.
+    fn open_output(
+        file_name: &str,
+        resumed: Option<Option<u64>>,
+        binary: Option<Compression>,
+    ) -> netcat::Result<Output<BufWriter<File>>> {
+        let offset = match resumed {
+            Some(Some(offset)) => offset,
+            Some(None) => {
+                return Err(netcat::Error::InvalidData(
+                    "the checkpoint does not tell the size of the output".to_string(),
+                ))
+            }
+            None => return Output::new(BufWriter::new(File::create(file_name)?), binary),
+        };
+
+        let mut file = OpenOptions::new().read(true).write(true).open(file_name)?;
+        if file.metadata()?.len() < offset {
+            return Err(netcat::Error::InvalidData(
+                "the output is shorter than at the checkpoint".to_string(),
+            ));
+        }
+        let is_binary = binary::is_binary(&mut file)?;
+        file.set_len(offset)?;
+        file.seek(SeekFrom::End(0))?;
+
+        let w = BufWriter::new(file);
+        Ok(if is_binary {
+            Output::Binary(BinaryRecorder::with_offset(w, offset))
+        } else {
+            Output::Json(JsonLinesRecorder::with_offset(w, offset))
+        })
+    }
+
+    // Converts the tracker output in the file between JSON Lines and the binary format.
+    // Returns the number of converted records.
+    fn convert(
+        input: &str,
+        output: Option<&str>,
+        compression: Compression,
+        passive: bool,
+    ) -> netcat::Result<usize> {
+        match output {
+            Some(file_name) => {
+                let w = BufWriter::new(File::create(file_name)?);
+                convert_records(input, w, compression, passive)
+            }
+            None => convert_records(input, BufWriter::new(stdout()), compression, passive),
+        }
+    }
+
+    fn convert_records<W: Write>(
+        input: &str,
+        w: W,
+        compression: Compression,
+        passive: bool,
+    ) -> netcat::Result<usize> {
+        if passive {
+            convert_file::<Arrival, W>(input, w, compression)
+        } else {
+            convert_file::<Measurement, W>(input, w, compression)
+        }
+    }
+
+    fn convert_file<T, W>(input: &str, w: W, compression: Compression) -> netcat::Result<usize>
     where
+        T: Serialize + DeserializeOwned,
+        W: Write,
+    {
+        let mut file = File::open(input)?;
+        let is_binary = binary::is_binary(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let r = BufReader::new(file);
+        if is_binary {
+            binary::to_json_lines::<T, _, _>(r, w)
+        } else {
+            binary::from_json_lines::<T, _, _>(r, w, compression)
+        }
+    }
+
+    fn run_tracker<C, R, S>(
+        mut tracker: OnlineTracker<C, R, S>,
+        limit: TrackingLimit,
+        passive: bool,
+        quite: bool,
+        save_to: Option<&str>,
+    ) where
         C: CacheConnector<Item = Contents>,
-        R: Record<LatsEntry>,
+        R: Record<Measurement> + Record<Arrival>,
         S: PacketSender,
     {
-        if let Err(e) = tracker.init() {
-            if !quite {
-                eprintln!("Online Tracker: {}", style(e).red());
+        handle_signals(tracker.stop_flag(), quite);
+
+        match tracker.init() {
+            Ok(()) => {
+                if let Some(file_name) = save_to {
+                    let res = tracker.saved_patterns().and_then(|saved| saved.save(file_name));
+                    if let Err(e) = res {
+                        if !quite {
+                            eprintln!(
+                                "Could not save RX patterns: {}",
+                                style(error_report(&e)).red()
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                if !quite {
+                    eprintln!("Online Tracker: {}", style(error_report(&e)).red());
+                }
             }
         }
-        if let Err(e) = tracker.track(cnt) {
+        if let Err(e) = super::track_with_dashboard(&mut tracker, limit, passive, quite) {
             if !quite {
-                eprintln!("Online Tracker: {}", style(e).red());
+                eprintln!("Online Tracker: {}", style(error_report(&e)).red());
             }
         }
     }
@@ -263,13 +1152,21 @@ mod interactive {
     use netcat::connection::local::{LocalMemoryConnector, LocalPacketSender};
     use netcat::connection::rdma::{RdmaServerConnector, RemotePacketSender};
     use netcat::connection::{CacheConnector, PacketSender};
-    use netcat::online_tracker::{LatsEntry, OnlineTracker, OnlineTrackerBuilder};
-    use netcat::output::{file::JsonRecorder, Record};
-    use netcat::rpp::{params::*, Contents};
+    use netcat::online_tracker::{
+        Arrival, Measurement, OnlineTracker, OnlineTrackerBuilder, SavedPatterns, SyncPolicy,
+        TrackingLimit,
+    };
+    use netcat::output::{file::JsonLinesRecorder, Record};
+    use super::{error_report, handle_signals, Settings};
+    use netcat::rpp::{
+        detect, params::*, presets, ActivationRule, Aging, ClassifierKind, Contents, DriftConfig,
+        MeasureMode, MeasurePhases, PrimeStrategy, ProbeStrategy,
+    };
     use std::fs::File;
     use std::io::{stdout, BufWriter};
     use std::net::{IpAddr, SocketAddr};
     use std::str::FromStr;
+    use std::sync::atomic::Ordering;
 
     pub fn run_session() {
         let conn_selection = Select::with_theme(&ColorfulTheme::default())
@@ -282,20 +1179,20 @@ mod interactive {
         let sock_addr = get_addr();
 
         if super::CONN_TYPES[conn_selection] == "rdma" {
-            let sender =
-                RemotePacketSender::new(sock_addr).unwrap_or_else(|e| panic!("{}", style(e).red()));
+            let sender = RemotePacketSender::new(sock_addr)
+                .unwrap_or_else(|e| panic!("{}", style(error_report(&e)).red()));
 
             let conn = match RdmaServerConnector::new(sock_addr) {
                 Ok(c) => c,
-                Err(e) => panic!("{}", style(e).red()),
+                Err(e) => panic!("{}", style(error_report(&e)).red()),
             };
-            do_measurements(sender, conn);
+            do_measurements(sender, conn, false);
         } else {
-            let sender =
-                LocalPacketSender::new(sock_addr).unwrap_or_else(|e| panic!("{}", style(e).red()));
+            let sender = LocalPacketSender::new(sock_addr)
+                .unwrap_or_else(|e| panic!("{}", style(error_report(&e)).red()));
 
             let conn = LocalMemoryConnector::new();
-            do_measurements(sender, conn);
+            do_measurements(sender, conn, true);
         }
     }
     fn get_ip() -> IpAddr {
@@ -326,7 +1223,69 @@ mod interactive {
         (get_ip(), get_port()).into()
     }
 
+    fn get_detected_cache() -> (CacheParams, CacheSource) {
+        let detected =
+            detect::detect().unwrap_or_else(|e| panic!("{}", style(error_report(&e)).red()));
+        eprintln!(
+            "Detected cache: {:?} ({} slices)",
+            detected.params, detected.slices
+        );
+
+        (detected.params, CacheSource::Detected(detected))
+    }
+
+    fn get_preset_cache() -> (CacheParams, CacheSource) {
+        let presets = presets::presets();
+        let items: Vec<String> = presets
+            .iter()
+            .map(|(name, p)| format!("{} [{}]", name, p.model))
+            .collect();
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Choose CPU preset")
+            .default(0)
+            .items(&items)
+            .interact()
+            .unwrap();
+
+        let (name, preset) = presets.into_iter().nth(selection).unwrap();
+        eprintln!("{}", preset.cache.report());
+        (preset.cache, CacheSource::Preset(name))
+    }
+
+    fn get_file_cache() -> (CacheParams, CacheSource) {
+        loop {
+            let file_name: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("File with cache description (TOML or JSON)")
+                .interact()
+                .unwrap();
+
+            match CacheParams::from_file(&file_name) {
+                Ok(cparams) => {
+                    eprintln!("{}", cparams.report());
+                    return (cparams, CacheSource::File(file_name));
+                }
+                Err(e) => eprintln!(
+                    "Error while loading cache description: {}",
+                    style(error_report(&e)).red()
+                ),
+            }
+        }
+    }
+
     fn get_custom_cache() -> CacheParams {
+        loop {
+            match ask_custom_cache() {
+                Ok(cparams) => {
+                    eprintln!("{}", cparams.report());
+                    return cparams;
+                }
+                Err(e) => eprintln!("{}", style(error_report(&e)).red()),
+            }
+        }
+    }
+
+    fn ask_custom_cache() -> Result<CacheParams, ParamsError> {
         let bytes_per_line: usize = Input::with_theme(&ColorfulTheme::default())
             .with_prompt("Enter number of bytes per cache line")
             .validate_with(|x: &str| match x.parse::<usize>() {
@@ -381,61 +1340,277 @@ mod interactive {
         )
     }
 
-    fn do_measurements<S, C>(sender: S, conn: C)
+    fn do_measurements<S, C>(sender: S, conn: C, local: bool)
     where
         S: PacketSender,
         C: CacheConnector<Item = Contents>,
     {
+        // cache detection only makes sense for the machine we are running on
+        let mut caches: Vec<&str> = super::CACHES
+            .iter()
+            .copied()
+            .filter(|&c| local || c != "auto")
+            .collect();
+        caches.extend(&["preset", "file"]);
+
         let cache_type = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Choose cache")
             .default(0)
-            .items(super::CACHES)
+            .items(&caches)
             .interact()
             .unwrap();
 
-        let cparams = match super::CACHES[cache_type] {
-            "E5_DDIO" => XEON_E5_DDIO,
-            "E5" => XEON_E5,
-            "I7" => CORE_I7,
-            "PLATINUM" => XEON_PLATINUM,
-            "PLATINUM_DDIO" => XEON_PLATINUM_DDIO,
-            "custom" => get_custom_cache(),
+        let (cparams, cache_source) = match caches[cache_type] {
+            "E5_DDIO" => (XEON_E5_DDIO, CacheSource::Builtin),
+            "E5" => (XEON_E5, CacheSource::Builtin),
+            "I7" => (CORE_I7, CacheSource::Builtin),
+            "PLATINUM" => (XEON_PLATINUM, CacheSource::Builtin),
+            "PLATINUM_DDIO" => (XEON_PLATINUM_DDIO, CacheSource::Builtin),
+            "custom" => (get_custom_cache(), CacheSource::Custom),
+            "preset" => get_preset_cache(),
+            "file" => get_file_cache(),
+            "auto" => get_detected_cache(),
             _ => panic!("Unsupported cache"),
         };
 
+        let settings = get_settings();
+        let passive = settings.passive;
+
         let file_name = get_filename();
         if file_name.is_empty() {
             eprintln!(
                 "No filename provided, printing to {}",
                 style("stdout").green()
             );
-            let output = JsonRecorder::new(BufWriter::new(stdout()));
+            let output = JsonLinesRecorder::new(BufWriter::new(stdout()));
 
-            let tracker = OnlineTrackerBuilder::new()
+            let tracker = settings
+                .apply(OnlineTrackerBuilder::new())
                 .set_conn(conn)
                 .set_sender(sender)
                 .set_cache(cparams)
+                .set_cache_source(cache_source)
                 .set_quite(false)
                 .set_output(output)
                 .finalize()
-                .unwrap_or_else(|e| panic!("{}", style(e).red()));
+                .unwrap_or_else(|e| panic!("{}", style(error_report(&e)).red()));
 
-            run_tracker(tracker);
+            run_tracker(tracker, passive);
         } else {
             let file = open_until_can(file_name);
 
-            let output = JsonRecorder::new(BufWriter::new(file));
+            let output = JsonLinesRecorder::new(BufWriter::new(file));
 
-            let tracker = OnlineTrackerBuilder::new()
+            let tracker = settings
+                .apply(OnlineTrackerBuilder::new())
                 .set_conn(conn)
                 .set_sender(sender)
                 .set_cache(cparams)
+                .set_cache_source(cache_source)
                 .set_quite(false)
                 .set_output(output)
                 .finalize()
-                .unwrap_or_else(|e| panic!("{}", style(e).red()));
+                .unwrap_or_else(|e| panic!("{}", style(error_report(&e)).red()));
 
-            run_tracker(tracker);
+            run_tracker(tracker, passive);
+        }
+    }
+
+    fn get_settings() -> Settings {
+        let (prime, probe) = get_strategies();
+
+        let classifier = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Timing classifier (centroid, threshold:N, gmm, otsu, levels:N)")
+            .default(ClassifierKind::default())
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let rule = get_activation_rule();
+
+        let aging = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Aging of the timings (none, window:N, decay:N)")
+            .default(Aging::default())
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let recalibrate_every = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Recalibrate the classifier after each N measurements (0 to disable)")
+            .default(0)
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let min_pattern_score = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Minimal score of an RX buffer pattern (0 to 1)")
+            .default(super::DEFAULT_MIN_PATTERN_SCORE.parse().unwrap())
+            .show_default(true)
+            .validate_with(|x: &str| match x.parse::<f64>() {
+                Ok(c) if (0.0..=1.0).contains(&c) => Ok(()),
+                _ => Err(String::from("Should be a number from 0 to 1")),
+            })
+            .interact()
+            .unwrap();
+
+        let sync = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("When to synchronize with the RX buffer (every:N, idle:MS, adaptive:N, never)")
+            .default(SyncPolicy::default())
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let window = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Number of sets, probed around the current position")
+            .default(super::DEFAULT_WINDOW_SIZE.parse().unwrap())
+            .show_default(true)
+            .validate_with(|x: &str| match x.parse::<usize>() {
+                Ok(n) if n >= 3 => Ok(()),
+                _ => Err(String::from("Should be a number of at least 3")),
+            })
+            .interact()
+            .unwrap();
+
+        let reconstruct = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Reconstruct an RX ring, which spans many pages?")
+            .default(false)
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let passive = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Record only the arrivals of victim packets, without sending any?")
+            .default(false)
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        Settings {
+            prime,
+            probe,
+            rule,
+            classifier,
+            drift: DriftConfig {
+                aging,
+                recalibrate_every,
+                ..Default::default()
+            },
+            measure: get_measure_phases(),
+            min_pattern_score,
+            sync,
+            window,
+            reconstruct,
+            passive,
+            saved: get_saved_patterns(),
+            save_to: None,
+            checkpoints: None,
+            resume: None,
+        }
+    }
+
+    fn get_saved_patterns() -> Option<SavedPatterns> {
+        loop {
+            let file_name: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("File with saved RX patterns [locate anew]")
+                .default(String::new())
+                .show_default(false)
+                .interact()
+                .unwrap();
+
+            if file_name.is_empty() {
+                return None;
+            }
+
+            match SavedPatterns::from_file(file_name) {
+                Ok(saved) => return Some(saved),
+                Err(e) => eprintln!("{}", style(error_report(&e)).red()),
+            }
+        }
+    }
+
+    fn save_patterns<C, R, S>(tracker: &OnlineTracker<C, R, S>)
+    where
+        C: CacheConnector<Item = Contents>,
+        R: Record<Measurement>,
+        S: PacketSender,
+    {
+        loop {
+            let file_name: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("File to save the RX patterns to [skip]")
+                .default(String::new())
+                .show_default(false)
+                .interact()
+                .unwrap();
+
+            if file_name.is_empty() {
+                return;
+            }
+
+            match tracker.saved_patterns().and_then(|saved| saved.save(file_name)) {
+                Ok(()) => return,
+                Err(e) => eprintln!("{}", style(error_report(&e)).red()),
+            }
+        }
+    }
+
+    fn get_measure_phases() -> MeasurePhases {
+        let ask = |phase: &str| {
+            Input::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Timing while {} (single, median:N, trimmed:N, adaptive:N)",
+                    phase
+                ))
+                .default(MeasureMode::default())
+                .show_default(true)
+                .interact()
+                .unwrap()
+        };
+
+        MeasurePhases {
+            profiling: ask("building eviction sets"),
+            locating: ask("locating RX buffer"),
+            tracking: ask("tracking"),
+        }
+    }
+
+    fn get_strategies() -> (PrimeStrategy, ProbeStrategy) {
+        let prime = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Prime strategy (seq, repeat:N, zigzag:N, random:N)")
+            .default(PrimeStrategy::default())
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        let probe = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Probe strategy (seq, reverse, random, first:K)")
+            .default(ProbeStrategy::default())
+            .show_default(true)
+            .interact()
+            .unwrap();
+
+        (prime, probe)
+    }
+
+    fn get_activation_rule() -> ActivationRule {
+        loop {
+            let min_misses = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Minimal number of misses in an activated set")
+                .default(1)
+                .show_default(true)
+                .interact()
+                .unwrap();
+
+            let min_confidence = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Minimal confidence of the misses (0 to 1)")
+                .default(0.0)
+                .show_default(true)
+                .interact()
+                .unwrap();
+
+            match ActivationRule::new(min_misses, min_confidence) {
+                Ok(rule) => return rule,
+                Err(e) => eprintln!("{}", style(error_report(&e)).red()),
+            }
         }
     }
 
@@ -455,23 +1630,27 @@ mod interactive {
             match File::create(file_name) {
                 Ok(file) => return file,
                 Err(e) => {
-                    eprintln!("Error while opening the file: {}", style(e).red());
+                    eprintln!("Error while opening the file: {}", style(error_report(&e)).red());
                 }
             }
             file_name = get_filename();
         }
     }
 
-    fn run_tracker<C, R, S>(mut tracker: OnlineTracker<C, R, S>)
+    fn run_tracker<C, R, S>(mut tracker: OnlineTracker<C, R, S>, passive: bool)
     where
         C: CacheConnector<Item = Contents>,
-        R: Record<LatsEntry>,
+        R: Record<Measurement> + Record<Arrival>,
         S: PacketSender,
     {
+        let stop = tracker.stop_flag();
+        handle_signals(stop.clone(), false);
+
         let mut not_init = true;
         while not_init {
             if let Err(e) = tracker.init() {
-                eprintln!("Online Tracker: {}", style(e).red());
+                eprintln!("Online Tracker: {}", style(error_report(&e)).red());
+                stop.store(false, Ordering::SeqCst);
                 not_init = should_continue();
             } else {
                 break;
@@ -482,12 +1661,17 @@ mod interactive {
             panic!("{}", style("Could not initialize OnlineTracker").red());
         }
 
+        save_patterns(&tracker);
+
         let mut not_done = true;
         while not_done {
             let cnt = get_cnt();
-            if let Err(e) = tracker.track(cnt) {
-                eprintln!("Online Tracker: {}", style(e).red());
+            let limit = TrackingLimit::Count(cnt);
+            if let Err(e) = super::track_with_dashboard(&mut tracker, limit, passive, false) {
+                eprintln!("Online Tracker: {}", style(error_report(&e)).red());
             }
+            // a stopped round does not stop the next one
+            stop.store(false, Ordering::SeqCst);
 
             not_done = should_continue();
         }
@@ -1,8 +1,7 @@
 use crate::connection::{Address, CacheConnector, MemoryConnector, PacketSender, Time};
+use crate::{Error, Result};
 use std::alloc;
 use std::convert::TryInto;
-use std::io::Result;
-use std::io::{Error, ErrorKind};
 use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
 
 pub struct LocalMemoryConnector {
@@ -98,6 +97,11 @@ impl CacheConnector for LocalMemoryConnector {
         Ok(elapsed)
     }
 
+    fn flush(&mut self, addr: Address) -> Result<bool> {
+        flush(unsafe { self.buf.add(addr) });
+        Ok(true)
+    }
+
     #[inline(never)]
     fn cache_all<I: Iterator<Item = Address>>(&mut self, mut addrs: I) -> Result<()> {
         addrs.try_for_each(|addr| self.cache(addr))
@@ -117,24 +121,20 @@ impl LocalPacketSender {
     pub fn new<A: ToSocketAddrs>(addr: A) -> Result<LocalPacketSender> {
         // We do it this way and not by `connection` method be able to send to
         // closed ports (with connection we would get ICMP back and fail next time)
-        let sock_addr = addr.to_socket_addrs()?.next().ok_or_else(|| Error::new(
-            ErrorKind::InvalidData,
-            "ERROR: could not resolve address.",
-        ))?;
+        let unresolved = || Error::Unreachable("could not resolve address".to_string());
+        let sock_addr = addr
+            .to_socket_addrs()
+            .map_err(|_| unresolved())?
+            .next()
+            .ok_or_else(unresolved)?;
 
         // Allow the machine to automatically choose port for us
         let sock = UdpSocket::bind("0.0.0.0:0").map_err(|e| {
-            Error::new(
-                ErrorKind::AddrNotAvailable,
-                format!("ERROR: could not bind to address: {}", e),
-            )
+            Error::Connector(format!("could not bind to address: {}", e))
         })?;
 
         sock.set_broadcast(true).map_err(|e| {
-            Error::new(
-                ErrorKind::ConnectionRefused,
-                format!("ERROR: Could not set to broadcast: {}", e),
-            )
+            Error::Connector(format!("Could not set to broadcast: {}", e))
         })?;
 
         Ok(LocalPacketSender { sock, sock_addr })
@@ -144,7 +144,9 @@ impl LocalPacketSender {
 impl PacketSender for LocalPacketSender {
     #[inline(always)]
     fn send_packet(&mut self) -> Result<()> {
-        self.sock.send_to(&[0], self.sock_addr)?;
+        self.sock
+            .send_to(&[0], self.sock_addr)
+            .map_err(|e| Error::Unreachable(format!("Cannot send packet: {}", e)))?;
         Ok(())
     }
 }
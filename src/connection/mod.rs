@@ -2,7 +2,8 @@
 //! This module provides a number of uniform interfaces for different connections.
 pub mod local;
 pub mod rdma;
-use std::io::Result;
+pub mod sim;
+use crate::Result;
 
 pub type Time = u64;
 pub type Address = usize;
@@ -42,6 +43,12 @@ pub trait CacheConnector {
     /// Times access to the given address
     fn time_access(&mut self, addr: Address) -> Result<Time>;
 
+    /// Evicts the item at the given address from the cache. Returns `false` if the
+    /// connection cannot flush lines, so the caller has to rely on other means.
+    fn flush(&mut self, _addr: Address) -> Result<bool> {
+        Ok(false)
+    }
+
     /// Reserves memory to be used for operations
     fn reserve(&mut self, size: usize);
 }
@@ -2,8 +2,9 @@
 //! This module is responsible for RDMA connections and maintaining overall RDMA state
 #![allow(dead_code)]
 use crate::connection::{Address, CacheConnector, MemoryConnector, PacketSender, Time};
+use crate::{Error, Result};
 use std::convert::TryInto;
-use std::io::{Error, ErrorKind, Result};
+use std::io;
 use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
 use std::sync::Arc;
 use std::time::Instant;
@@ -46,14 +47,11 @@ impl RdmaServerConnector {
         // Get the first device
         let dev = dev_list
             .get(0)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "ERROR: No RDMA devices in list"))?;
+            .ok_or_else(|| Error::Connector("No RDMA devices in list".to_string()))?;
 
         // Here the device is opened. Port (1) and GID are queried automaticaly
         dev.open().map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: aquiring RDMA context failed: {}", e),
-            )
+            Error::Connector(format!("aquiring RDMA context failed: {}", e))
         })
     }
 
@@ -61,38 +59,26 @@ impl RdmaServerConnector {
         // Create a protection domain
         match ctx.alloc_pd() {
             Ok(pd) => Ok(Arc::new(pd)),
-            Err(_) => Err(Error::new(
-                ErrorKind::Other,
-                "ERROR: allocating Protection Domain failed",
-            )),
+            Err(_) => Err(Error::Connector("allocating Protection Domain failed".to_string())),
         }
     }
 
     fn aquire_cq(ctx: Arc<ibverbs::Context>) -> Result<Arc<ibverbs::CompletionQueue>> {
         let dev_attr = ctx.query_device().map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: cannot get device attributes: {}", e),
-            )
+            Error::Connector(format!("cannot get device attributes: {}", e))
         })?;
 
         // Create Complition Queue
         match ctx.create_cq(dev_attr.max_cqe, 0) {
             Ok(cq) => Ok(Arc::new(cq)),
-            Err(e) => Err(Error::new(
-                ErrorKind::Other,
-                format!("ERROR: creating Completion Queue failed: {}", e),
-            )),
+            Err(e) => Err(Error::Connector(format!("creating Completion Queue failed: {}", e))),
         }
     }
 
     fn register_mr(pd: &ibverbs::ProtectionDomain) -> Result<ibverbs::MemoryRegion<RdmaPrimitive>> {
         // here we need to allocate memory and register a memory region just for RDMA porposes
         pd.allocate::<RdmaPrimitive>(LOCAL_BUF_SIZE).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: registering Memory Region failed: {}", e),
-            )
+            Error::Connector(format!("registering Memory Region failed: {}", e))
         })
     }
 
@@ -106,10 +92,7 @@ impl RdmaServerConnector {
         let qp_init = {
             let qp_builder = pd.create_qp(cq, cq, ibverbs::ibv_qp_type::IBV_QPT_RC); // client access flags default to ALLOW_LOCAL_WRITES which is ok
             qp_builder.build().map_err(|e| {
-                Error::new(
-                    ErrorKind::Other,
-                    format!("ERROR: failed to initialize Queue Pair: {}", e),
-                )
+                Error::Connector(format!("failed to initialize Queue Pair: {}", e))
             })?
         };
 
@@ -126,10 +109,7 @@ impl RdmaServerConnector {
                 rkey,
                 raddr,
             }),
-            Err(e) => Err(Error::new(
-                ErrorKind::Other,
-                format!("ERROR: failed to handshake: {}", e),
-            )),
+            Err(e) => Err(Error::Unreachable(format!("failed to handshake: {}", e))),
         }
     }
 
@@ -144,26 +124,17 @@ impl RdmaServerConnector {
         msg.raddr = laddr;
 
         let mut stream = TcpStream::connect(addr).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: failed to connect to server: {}", e),
-            )
+            Error::Unreachable(format!("failed to connect to server: {}", e))
         })?;
 
         // Sending info for RDMA handshake over TcpStream;
         bincode::serialize_into(&mut stream, &msg).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: failed to transmit serealized message: {}", e),
-            )
+            Error::Unreachable(format!("failed to transmit serealized message: {}", e))
         })?;
 
         // Recieving and desirializing info from the server
         let rmsg: ibverbs::EndpointMsg = bincode::deserialize_from(&mut stream).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: failed to recieve data: {}", e),
-            )
+            Error::Unreachable(format!("failed to recieve data: {}", e))
         })?;
 
         Ok(rmsg)
@@ -202,10 +173,7 @@ impl RdmaServerConnector {
 
     fn get_devs() -> Result<ibverbs::DeviceList> {
         ibverbs::devices().map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("ERROR: cannot get device list: {}", e),
-            )
+            Error::Connector(format!("cannot get device list: {}", e))
         })
     }
 
@@ -213,20 +181,17 @@ impl RdmaServerConnector {
         // in case we use fork latter
 
         if unsafe { ibverbs::ffi::ibv_fork_init() } != 0 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "ERROR: could not initialize fork: {}",
-                    Error::last_os_error()
-                ),
-            ));
+            return Err(Error::Connector(format!(
+                "could not initialize fork: {}",
+                io::Error::last_os_error()
+            )));
         }
 
         Ok(())
     }
 
     #[inline(always)]
-    fn post_read(&self, addr: u64) -> Result<()> {
+    fn post_read(&self, addr: u64) -> io::Result<()> {
         unsafe {
             self.iqp
                 .qp
@@ -235,7 +200,7 @@ impl RdmaServerConnector {
     }
 
     #[inline(always)]
-    fn post_write(&self, addr: u64) -> Result<()> {
+    fn post_write(&self, addr: u64) -> io::Result<()> {
         unsafe {
             self.iqp
                 .qp
@@ -244,7 +209,7 @@ impl RdmaServerConnector {
     }
 
     #[inline(always)]
-    fn post_read_buf(&self, addr: u64, n: usize) -> Result<()> {
+    fn post_read_buf(&self, addr: u64, n: usize) -> io::Result<()> {
         unsafe {
             self.iqp
                 .qp
@@ -253,7 +218,7 @@ impl RdmaServerConnector {
     }
 
     #[inline(always)]
-    fn post_write_buf(&self, addr: u64, n: usize) -> Result<()> {
+    fn post_write_buf(&self, addr: u64, n: usize) -> io::Result<()> {
         unsafe {
             self.iqp
                 .qp
@@ -262,7 +227,7 @@ impl RdmaServerConnector {
     }
 
     #[inline(always)]
-    fn poll_cq_is_done(&self, compl: &mut [ibverbs::ffi::ibv_wc]) -> Result<()> {
+    fn poll_cq_is_done(&self, compl: &mut [ibverbs::ffi::ibv_wc]) -> io::Result<()> {
         loop {
             let completed = self.cq.poll(compl).expect("ERROR: Could not poll CQ.");
             if completed.is_empty() {
@@ -275,7 +240,7 @@ impl RdmaServerConnector {
     }
 
     #[inline(always)]
-    fn write_from_mr(&mut self, addr: Address) -> Result<()> {
+    fn write_from_mr(&mut self, addr: Address) -> io::Result<()> {
         let mut completions = [ibverbs::ibv_wc::default()];
         self.post_write(self.iqp.raddr.0 + (addr as u64))?;
         self.poll_cq_is_done(&mut completions)?;
@@ -293,8 +258,9 @@ impl MemoryConnector for RdmaServerConnector {
     #[inline(always)]
     fn read(&self, ofs: usize) -> Result<Self::Item> {
         let mut completions = [ibverbs::ibv_wc::default()];
-        self.post_read(self.iqp.raddr.0 + (ofs as u64))?;
-        self.poll_cq_is_done(&mut completions)?;
+        self.post_read(self.iqp.raddr.0 + (ofs as u64))
+            .and_then(|_| self.poll_cq_is_done(&mut completions))
+            .map_err(|e| Error::Unreachable(format!("Could not read address: {}", e)))?;
 
         Ok(self.mr[0])
     }
@@ -317,6 +283,7 @@ impl MemoryConnector for RdmaServerConnector {
         // the desired value is taken from the memory region
         self.mr[0] = *what;
         self.write_from_mr(addr)
+            .map_err(|e| Error::Unreachable(format!("Could not write address: {}", e)))
     }
 
     #[inline(always)]
@@ -344,21 +311,13 @@ impl CacheConnector for RdmaServerConnector {
         // we do not really care of the contents of the MR
         // as the writen value will not be used
         self.write_from_mr(addr).map_err(|e| {
-            Error::new(
-                ErrorKind::NotConnected,
-                format!("ERROR: Could not cache address: {}", e),
-            )
+            Error::Unreachable(format!("Could not cache address: {}", e))
         })
     }
 
     #[inline(always)]
     fn time_access(&mut self, addr: Address) -> Result<Time> {
-        self.read_timed(addr).map(|(_, t)| t).map_err(|e| {
-            Error::new(
-                ErrorKind::NotConnected,
-                format!("ERROR: Could not time access: {}", e),
-            )
-        })
+        self.read_timed(addr).map(|(_, t)| t)
     }
 }
 
@@ -371,16 +330,16 @@ impl RemotePacketSender {
     pub fn new<A: ToSocketAddrs>(addr: A) -> Result<RemotePacketSender> {
         // We do it this way and not by `connection` method be able to send to
         // closed ports (with connection we would get ICMP back and fail next time)
-        let sock_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
-            Error::new(ErrorKind::InvalidData, "ERROR: could not resolve address.")
-        })?;
+        let unresolved = || Error::Unreachable("could not resolve address".to_string());
+        let sock_addr = addr
+            .to_socket_addrs()
+            .map_err(|_| unresolved())?
+            .next()
+            .ok_or_else(unresolved)?;
 
         // Allow the machine to automatically choose port for us
         let sock = UdpSocket::bind("0.0.0.0:0").map_err(|e| {
-            Error::new(
-                ErrorKind::AddrNotAvailable,
-                format!("ERROR: could not bind to address: {}", e),
-            )
+            Error::Connector(format!("could not bind to address: {}", e))
         })?;
         // MAYBE set nonblocking
 
@@ -392,10 +351,7 @@ impl PacketSender for RemotePacketSender {
     #[inline(always)]
     fn send_packet(&mut self) -> Result<()> {
         self.sock.send_to(&[0], self.sock_addr).map_err(|e| {
-            Error::new(
-                ErrorKind::NotConnected,
-                format!("ERROR: Cannot send packet: {}", e),
-            )
+            Error::Unreachable(format!("Cannot send packet: {}", e))
         })?;
         Ok(())
     }
@@ -0,0 +1,192 @@
+//! # Simulated connection
+//! A cache connector, which simulates a set associative LRU cache. No real memory is touched,
+//! thus it is deterministic (up to the seeded noise) and fast. Useful for benchmarks and tests.
+use crate::connection::{Address, CacheConnector, Time};
+use crate::rpp::params::CacheParams;
+use crate::{Error, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+const HIT_LATENCY: Time = 100;
+const MISS_LATENCY: Time = 300;
+const JITTER: Time = 10;
+const SEED: u64 = 0x6e65_7463_6174;
+
+pub struct SimulatedCacheConnector {
+    // each set holds line numbers, the most recently used one is at the front
+    sets: Vec<VecDeque<usize>>,
+    ways: usize,
+    line_size: usize,
+    buf_size: usize,
+    hit: Time,
+    miss: Time,
+    jitter: Time,
+    spike_prob: f64,
+    spike: Time,
+    reads_allocate: bool,
+    rng: StdRng,
+}
+
+impl SimulatedCacheConnector {
+    /// Simulates a cache, described by `cparams`. Only reachable lines are simulated,
+    /// so a DDIO description gives a cache with the number of ways, available for DDIO.
+    pub fn new(cparams: &CacheParams) -> SimulatedCacheConnector {
+        let ways = cparams.reachable_lines();
+        let line_size = cparams.bytes_per_line();
+        let n_sets = cparams.cache_size() / (cparams.lines_per_set() * line_size);
+
+        SimulatedCacheConnector {
+            sets: vec![VecDeque::with_capacity(ways + 1); n_sets],
+            ways,
+            line_size,
+            buf_size: 0,
+            hit: HIT_LATENCY,
+            miss: MISS_LATENCY,
+            jitter: JITTER,
+            spike_prob: 0.0,
+            spike: 0,
+            reads_allocate: true,
+            rng: StdRng::seed_from_u64(SEED),
+        }
+    }
+
+    /// Sets the latencies, reported for cache hits and misses. Each measurement
+    /// gets uniform noise in `[0, jitter]` added.
+    pub fn set_latencies(&mut self, hit: Time, miss: Time, jitter: Time) {
+        self.hit = hit;
+        self.miss = miss;
+        self.jitter = jitter;
+    }
+
+    /// Adds `spike` to a measurement with the given probability, as a network
+    /// retransmission would do
+    pub fn set_spikes(&mut self, prob: f64, spike: Time) {
+        self.spike_prob = prob;
+        self.spike = spike;
+    }
+
+    /// Tells, whether timed accesses bring lines into the cache. RDMA reads do not,
+    /// so the same line can be timed several times.
+    pub fn set_reads_allocate(&mut self, allocate: bool) {
+        self.reads_allocate = allocate;
+    }
+
+    // Returns the line number and the set, the address belongs to
+    fn locate(&mut self, addr: Address) -> Result<(usize, &mut VecDeque<usize>)> {
+        if addr >= self.buf_size {
+            return Err(Error::Connector(
+                "Address is out of the reserved memory".to_string(),
+            ));
+        }
+
+        let line = addr / self.line_size;
+        let n_sets = self.sets.len();
+        Ok((line, &mut self.sets[line % n_sets]))
+    }
+
+    /// Brings the line into the cache. Returns true if it was already cached.
+    fn touch(&mut self, addr: Address) -> Result<bool> {
+        let ways = self.ways;
+        let (line, set) = self.locate(addr)?;
+
+        let hit = match set.iter().position(|&l| l == line) {
+            Some(pos) => {
+                set.remove(pos);
+                true
+            }
+            None => false,
+        };
+
+        set.push_front(line);
+        set.truncate(ways);
+
+        Ok(hit)
+    }
+
+    /// Drops all cached lines
+    pub fn flush_all(&mut self) {
+        self.sets.iter_mut().for_each(VecDeque::clear);
+    }
+}
+
+impl CacheConnector for SimulatedCacheConnector {
+    type Item = u8;
+
+    fn cache(&mut self, addr: Address) -> Result<()> {
+        self.touch(addr).map(|_| ())
+    }
+
+    fn time_access(&mut self, addr: Address) -> Result<Time> {
+        let cached = if self.reads_allocate {
+            self.touch(addr)?
+        } else {
+            let (line, set) = self.locate(addr)?;
+            set.contains(&line)
+        };
+        let base = if cached { self.hit } else { self.miss };
+        let spiked = self.spike_prob > 0.0 && self.rng.gen_bool(self.spike_prob);
+        let spike = if spiked { self.spike } else { 0 };
+
+        Ok(base + spike + self.rng.gen_range(0, self.jitter + 1))
+    }
+
+    fn flush(&mut self, addr: Address) -> Result<bool> {
+        let (line, set) = self.locate(addr)?;
+        set.retain(|&l| l != line);
+        Ok(true)
+    }
+
+    fn reserve(&mut self, size: usize) {
+        self.buf_size = size;
+    }
+}
+
+/// Shared setup of the tests, which run on the simulated cache
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::{HIT_LATENCY, MISS_LATENCY};
+    use crate::rpp::params::CacheParams;
+    use crate::rpp::timing_classif::{CacheTiming, LatencyClassifier, TimingClassifier};
+
+    /// A small cache with 256 sets and 200 pages of addresses
+    pub(crate) fn cache_params() -> CacheParams {
+        CacheParams::new(64, 4, 4, 64 * 4 * 256, 200).unwrap()
+    }
+
+    /// A classifier, trained on the noiseless latencies of the simulated cache
+    pub(crate) fn trained_classifier() -> TimingClassifier {
+        let mut classifier = TimingClassifier::new();
+        for _ in 0..10 {
+            classifier.record(CacheTiming::Hit(HIT_LATENCY));
+            classifier.record(CacheTiming::Miss(MISS_LATENCY));
+        }
+        classifier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_eviction() {
+        let cparams = CacheParams::new(64, 4, 4, 64 * 4 * 64, 100).unwrap();
+        let mut conn = SimulatedCacheConnector::new(&cparams);
+        conn.reserve(100 * 4096);
+
+        // all of these fall into set 0, as there are 64 sets
+        let congruent: Vec<Address> = (0..5).map(|i| i * 4096).collect();
+
+        conn.cache_all(congruent[..4].iter().copied()).unwrap();
+        assert!(conn.time_access(congruent[0]).unwrap() < MISS_LATENCY, "Expected a hit");
+
+        // evicts the least recently used line, which is `congruent[1]`
+        conn.cache(congruent[4]).unwrap();
+        assert!(conn.time_access(congruent[1]).unwrap() >= MISS_LATENCY, "Expected a miss");
+        assert!(conn.time_access(congruent[0]).unwrap() < MISS_LATENCY, "Expected a hit");
+
+        conn.flush(congruent[0]).unwrap();
+        assert!(conn.time_access(congruent[0]).unwrap() >= MISS_LATENCY, "Expected a miss after flush");
+    }
+}
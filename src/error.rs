@@ -0,0 +1,191 @@
+//! # Errors
+//!
+//! All fallible operations of the crate return `Error`. Its variants tell, what went
+//! wrong, so that callers can react to it (e.g. reconnect to an unreachable victim or
+//! lower the minimal pattern score) without parsing messages. The messages are kept
+//! for the humans.
+
+use crate::online_tracker::RingId;
+use crate::rpp::params::ParamsError;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error of any operation of the crate
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or the output failed
+    Io(io::Error),
+    /// The victim cannot be reached: connecting, handshaking or sending packets fails
+    Unreachable(String),
+    /// The connector fails to set up or to access the memory of the victim
+    Connector(String),
+    /// Too few addresses are left in an address pool to build an eviction set
+    AddrsExhausted {
+        pool: usize,
+        left: usize,
+        needed: usize,
+    },
+    /// An eviction set cannot be built for the given reason
+    SetNotBuilt(&'static str),
+    /// Too few timings are sampled to train the classifier
+    ClassifierUntrained { samples: usize, needed: usize },
+    /// No RX pattern is observed for the given reason
+    PatternNotFound(String),
+    /// A pattern is observed, but it is too noisy to be told from chance
+    PatternAmbiguous {
+        /// What is scored, e.g. a ring or the best candidate
+        subject: String,
+        score: f64,
+        min_score: f64,
+    },
+    /// The initial position in the ring cannot be found
+    NoInitialPosition(RingId),
+    /// The position is lost after a missed synchronization at the given position
+    PositionUnrecoverable(usize),
+    /// Invalid cache parameters
+    Params(ParamsError),
+    /// Invalid settings or arguments
+    InvalidConfig(String),
+    /// Malformed input, e.g. a saved pattern, a checkpoint or a cache description
+    InvalidData(String),
+    /// The tracker is used before `init()`
+    NotInitialized,
+    /// Stopped by the stop flag of the tracker
+    Stopped,
+    /// The receiver of the results is gone
+    Disconnected,
+    /// RX buffers are not located after all the attempts, with the last error
+    LocationFailed(Box<Error>),
+    /// A checkpointed session cannot be resumed, with the reason
+    ResumeFailed(Box<Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Unreachable(msg) => write!(f, "victim is unreachable: {}", msg),
+            Self::Connector(msg) => write!(f, "{}", msg),
+            Self::AddrsExhausted { pool, left, needed } => write!(
+                f,
+                "No addrs left: pool {} has {}, at least {} are needed",
+                pool, left, needed
+            ),
+            Self::SetNotBuilt(reason) => write!(f, "cannot build eviction set: {}", reason),
+            Self::ClassifierUntrained { samples, needed } => write!(
+                f,
+                "{} samples are too few to train the classifier, at least {} are needed",
+                samples, needed
+            ),
+            Self::PatternNotFound(reason) => write!(f, "Cannot decide on pattern. {}", reason),
+            Self::PatternAmbiguous {
+                subject,
+                score,
+                min_score,
+            } => write!(
+                f,
+                "{} scores {:.2}, at least {:.2} is required",
+                subject, score, min_score
+            ),
+            Self::NoInitialPosition(ring) => write!(
+                f,
+                "Cannot determine the initial position in RX ring {}",
+                ring
+            ),
+            Self::PositionUnrecoverable(pos) => {
+                write!(f, "Cannot recover position after {}", pos)
+            }
+            Self::Params(e) => e.fmt(f),
+            Self::InvalidConfig(msg) | Self::InvalidData(msg) => write!(f, "{}", msg),
+            Self::NotInitialized => write!(f, "Online tracker is not initialized. Call init()."),
+            Self::Stopped => write!(f, "Tracking is stopped"),
+            Self::Disconnected => write!(f, "the receiver is disconnected"),
+            Self::LocationFailed(_) => {
+                write!(f, "INITIALIZATION FAILED. Could not locate RX buffer in memory")
+            }
+            Self::ResumeFailed(_) => write!(f, "Cannot resume the checkpointed session"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            // these are shown as they are, thus only the causes of them are left
+            Self::Io(e) => e.source(),
+            Self::Params(e) => e.source(),
+            Self::LocationFailed(e) | Self::ResumeFailed(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ParamsError> for Error {
+    fn from(e: ParamsError) -> Self {
+        Error::Params(e)
+    }
+}
+
+/// Keeps the failures of the underlying writer or reader as `Io`
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            Error::Io(e.into())
+        } else {
+            Error::InvalidData(e.to_string())
+        }
+    }
+}
+
+/// Keeps the failures of the underlying writer or reader as `Io`
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) => Error::Io(e),
+            e => Error::InvalidData(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn error_sources() {
+        let err = Error::LocationFailed(Box::new(Error::PatternAmbiguous {
+            subject: "RX ring 0".to_string(),
+            score: 0.5,
+            min_score: 0.8,
+        }));
+        assert_eq!(
+            err.to_string(),
+            "INITIALIZATION FAILED. Could not locate RX buffer in memory",
+            "Cause is shown with the context"
+        );
+        assert!(
+            matches!(
+                err.source().and_then(|e| e.downcast_ref::<Error>()),
+                Some(Error::PatternAmbiguous { .. })
+            ),
+            "Cause is not the source"
+        );
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            "RX ring 0 scores 0.50, at least 0.80 is required",
+            "Cause is not shown"
+        );
+
+        let err: Error = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert!(matches!(err, Error::InvalidData(_)), "Malformed JSON is not invalid data");
+    }
+}
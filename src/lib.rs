@@ -1,4 +1,8 @@
 pub mod rpp; // Remote PRIME+PROBE
 pub mod connection;
+mod error;
+pub mod offline_extractor;
 pub mod online_tracker;
-pub mod output;
\ No newline at end of file
+pub mod output;
+
+pub use error::{Error, Result};
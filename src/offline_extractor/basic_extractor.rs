@@ -2,5 +2,5 @@ use crate::online_tracker::{Time, SavedLats};
 
 /// Extracts timestamps from the data from Online Tracker
 pub fn extract(data: SavedLats) -> Vec<Time> {
-    data.into_iter().map(|(_, _, t)| t).collect()
+    data.into_iter().map(|m| m.elapsed).collect()
 }
\ No newline at end of file
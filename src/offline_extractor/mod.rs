@@ -1 +1,12 @@
-pub mod basic_extractor;
\ No newline at end of file
+pub mod basic_extractor;
+
+use crate::online_tracker::SavedLats;
+use crate::output::file::JsonLinesReader;
+use crate::Result;
+use std::path::Path;
+
+/// Loads the measurements of all the sessions of a file, recorded by the tracker
+/// as JSON Lines
+pub fn load<P: AsRef<Path>>(path: P) -> Result<SavedLats> {
+    JsonLinesReader::open(path)?.data().collect()
+}
@@ -0,0 +1,164 @@
+//! # Checkpoints
+//!
+//! A long tracking session can be lost to an RDMA error or a restart of the tracker.
+//! `OnlineTracker` periodically saves everything, which is needed to continue it: the
+//! session settings, the patterns with their eviction sets, the trained classifier,
+//! the position and the synchronization state in each ring and the size of the output.
+//! A resumed tracker rebuilds the connection, verifies the patterns and the positions
+//! and continues the same output.
+
+use super::saved::SavedPatterns;
+use super::tracking::TrackingContext;
+use super::Time;
+use crate::output::SessionInfo;
+use crate::rpp::ClassifierState;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the `Checkpoint` format. It changes with each incompatible change
+/// of the fields.
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+/// Where and how often `OnlineTracker` saves checkpoints
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointConfig {
+    /// The file, which each checkpoint replaces
+    pub path: PathBuf,
+    /// Number of measurements between the checkpoints
+    pub every: usize,
+    /// The file, which the measurements are recorded to. It is only noted in the
+    /// checkpoint to continue the same output on resume.
+    pub output: Option<String>,
+}
+
+/// State of a tracking session, from which it can be resumed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Version of the format, see `CHECKPOINT_VERSION`
+    pub version: u32,
+    /// Settings of the session and scores of the tracked patterns
+    pub info: SessionInfo,
+    /// The tracked patterns with their eviction sets
+    pub patterns: SavedPatterns,
+    /// Timings of the trained classifier, if it can be saved. Otherwise it is
+    /// retrained on resume.
+    pub classifier: Option<ClassifierState>,
+    /// The position and the synchronization state of each ring, indexed by ring ID
+    pub contexts: Vec<TrackingContext>,
+    /// Number of measurements, taken up to the checkpoint
+    pub measurements: usize,
+    /// Time since the start of tracking in nanoseconds
+    pub elapsed: Time,
+    /// The output file of the session, if it is known
+    pub output: Option<String>,
+    /// Size of the output in bytes, if the recorder reports it. Anything after it
+    /// is recorded after the checkpoint.
+    pub output_offset: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Parses a checkpoint from a JSON document
+    pub fn from_json(s: &str) -> Result<Checkpoint> {
+        let checkpoint: Checkpoint = serde_json::from_str(s).map_err(|e| {
+            Error::InvalidData(format!("invalid checkpoint: {}", e))
+        })?;
+        checkpoint.validate()?;
+
+        Ok(checkpoint)
+    }
+
+    /// Loads a checkpoint from a JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Checkpoint> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Saves the checkpoint to a JSON file. The file is replaced at once, so that
+    /// a failure while saving keeps the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut file, self)
+            .map_err(|e| Error::InvalidData(e.to_string()))?;
+        file.flush()?;
+        drop(file);
+
+        Ok(fs::rename(tmp, path)?)
+    }
+
+    /// Checks the version, the saved patterns and that there is a valid position
+    /// for each ring
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidData(msg.to_string()));
+
+        if self.version != CHECKPOINT_VERSION {
+            return invalid("unsupported checkpoint version");
+        }
+
+        self.patterns.validate()?;
+
+        let rings = &self.patterns.rings;
+        if self.contexts.len() != rings.len() {
+            return invalid("checkpoint has no position for some of the rings");
+        }
+        if self
+            .contexts
+            .iter()
+            .zip(rings.iter())
+            .any(|(ctx, ring)| ctx.pos() >= ring.pattern.len())
+        {
+            return invalid("checkpoint position is out of the pattern");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::online_tracker::{PatternScore, SavedRing, SyncPolicy};
+    use crate::rpp::params::CacheParams;
+    use crate::rpp::SetCode;
+
+    #[test]
+    fn checkpoint_roundtrip() {
+        let checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            info: SessionInfo::default(),
+            patterns: SavedPatterns {
+                cache: CacheParams::default(),
+                rings: vec![SavedRing {
+                    score: PatternScore::default(),
+                    sets: vec![vec![64], vec![128]],
+                    codes: vec![SetCode(1, 4), SetCode(1, 9)],
+                    pattern: vec![0, 1, 1],
+                }],
+            },
+            classifier: Some(ClassifierState::default()),
+            contexts: vec![TrackingContext::default()],
+            measurements: 1000,
+            elapsed: 5_000_000,
+            output: Some("out.json".to_string()),
+            output_offset: Some(4096),
+        };
+
+        let path = std::env::temp_dir().join(format!("netcat-checkpoint-{}.json", std::process::id()));
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), checkpoint, "Checkpoint does not roundtrip");
+
+        let mut broken = checkpoint.clone();
+        broken.contexts = vec![TrackingContext::new(3, SyncPolicy::default())];
+        assert!(broken.validate().is_err(), "Position out of the pattern is accepted");
+
+        let mut broken = checkpoint;
+        broken.version += 1;
+        assert!(broken.validate().is_err(), "Other version is accepted");
+    }
+}
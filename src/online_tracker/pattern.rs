@@ -1,16 +1,30 @@
-use super::{SetCode, REPEATINGS};
-use crate::rpp::{ProbeResult, ColorCode, ColoredSetCode};
+use super::SetCode;
+use crate::rpp::{ColorCode, ColoredSetCode, Probe, ProbeResult};
+use crate::{Error, Result};
 use custom_derive::custom_derive;
 use newtype_derive::*;
-use std::io::{Error, ErrorKind, Result};
-use std::iter::FromIterator;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::iter::FromIterator;
 
-pub const WINDOW_SIZE: usize = 10;
+pub const DEFAULT_WINDOW_SIZE: usize = 10;
+// Lags, which correlate at least this share of the best lag, may be the period
+const PERIOD_TOLERANCE: f64 = 0.9;
+// Longer lags fit noise too well, as there are too few repetitions to compare
+const MIN_REPETITIONS: usize = 4;
 
 pub type PatternIdx = usize;
 pub type PossiblePatterns = HashMap<ColorCode, Vec<Option<ColoredSetCode>>>;
 
+/// Index of the current position in a window of the given size. The next activation
+/// is expected right after it, so windows should have at least three sets: the center
+/// of an even-sized window is its upper middle.
+#[inline(always)]
+pub fn window_center(size: usize) -> usize {
+    size / 2
+}
 
 custom_derive! {
     #[derive(Debug, Default, Clone, PartialEq, Eq, NewtypeFrom,
@@ -19,6 +33,72 @@ custom_derive! {
     pub struct Pattern(Vec<SetCode>);
 }
 
+/// Tells, how distinctive a detected pattern is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatternScore {
+    /// The color of the pattern sets. A ring, reconstructed across colors,
+    /// has the color of its first set.
+    pub color: ColorCode,
+    /// Estimated length of the ring in probing steps
+    pub period: usize,
+    /// Number of observed repetitions of the ring
+    pub repetitions: usize,
+    /// Mean vote margin of the winning set over the runner-up per position
+    pub margin: f64,
+    /// Mean agreement of the repetitions with the pattern
+    pub consistency: f64,
+    /// Autocorrelation of the observations at the ring length
+    pub periodicity: f64,
+    /// Overall score in `[0, 1]`, the geometric mean of the above three
+    pub score: f64,
+}
+
+impl PatternScore {
+    fn new(
+        color: ColorCode,
+        period: usize,
+        repetitions: usize,
+        margin: f64,
+        consistency: f64,
+        periodicity: f64,
+    ) -> PatternScore {
+        PatternScore {
+            color,
+            period,
+            repetitions,
+            margin,
+            consistency,
+            periodicity,
+            score: (margin * consistency * periodicity).cbrt(),
+        }
+    }
+}
+
+/// Observations, folded by the estimated ring length
+pub(super) struct Folded<T> {
+    /// The winning observation of each position in the ring, if any
+    pub(super) consensus: Vec<Option<T>>,
+    pub(super) period: usize,
+    pub(super) repetitions: usize,
+    pub(super) margin: f64,
+    pub(super) consistency: f64,
+    pub(super) periodicity: f64,
+}
+
+impl<T> Folded<T> {
+    /// Scores the folded observations as a pattern of the given color
+    pub(super) fn score(&self, color: ColorCode) -> PatternScore {
+        PatternScore::new(
+            color,
+            self.period,
+            self.repetitions,
+            self.margin,
+            self.consistency,
+            self.periodicity,
+        )
+    }
+}
+
 impl FromIterator<SetCode> for Pattern {
     fn from_iter<I: IntoIterator<Item = SetCode>>(iter: I) -> Self {
         Pattern(Vec::from_iter(iter))
@@ -26,48 +106,165 @@ impl FromIterator<SetCode> for Pattern {
 }
 
 impl Pattern {
-    #[inline(always)]
-    pub fn find(patterns: PossiblePatterns) -> Result<Self> {
-        let mut fnd_pts = HashMap::with_capacity(1);
+    /// Finds a pattern for each color, which yields one with at least `min_score`.
+    /// Each pattern belongs to a separate RX ring, as multi-queue NICs (e.g. with RSS)
+    /// keep a ring per queue. The patterns are ordered by color, so the index of
+    /// a pattern is the ring ID.
+    ///
+    /// # Fails
+    ///
+    /// Fails if no color yields a pattern with enough score. The error tells the best
+    /// score among the candidates.
+    pub fn find_all(
+        patterns: PossiblePatterns,
+        min_score: f64,
+    ) -> Result<Vec<(Self, PatternScore)>> {
+        let candidates: Vec<(Self, PatternScore)> = patterns
+            .into_iter()
+            .filter_map(|(color_code, pattern)| Self::detect(color_code, &pattern))
+            .collect();
 
-        for (color_code, pattern) in patterns {
-            let record = Self::pattern_to_rec(pattern);
+        let best = candidates
+            .iter()
+            .map(|(_, score)| score.score)
+            .fold(f64::NEG_INFINITY, f64::max);
 
-            let pat = match Self::pat_from_rec(record) {
-                Some(pat) => pat,
-                None => continue,
-            };
+        let mut fnd_pts: Vec<(Self, PatternScore)> = candidates
+            .into_iter()
+            .filter(|(_, score)| score.score >= min_score)
+            .collect();
 
-            fnd_pts.insert(color_code, pat);
+        if fnd_pts.is_empty() {
+            return Err(if best.is_finite() {
+                Error::PatternAmbiguous {
+                    subject: "The best pattern candidate".to_string(),
+                    score: best,
+                    min_score,
+                }
+            } else {
+                Error::PatternNotFound("No repeating activations observed".to_string())
+            });
         }
 
-        // For now, we expect only one pattern to arise. If not, then other methods should be used
-        // NOTE one may add confidence level for each pattern, based on the statistics for each entry in
-        // a pattern
-        if fnd_pts.len() != 1 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "ERROR: Cannot decide on pattern",
-            ));
-        }
+        fnd_pts.sort_by_key(|(_, score)| score.color);
+
+        Ok(fnd_pts)
+    }
 
-        let (color_code, pat) = fnd_pts.into_iter().next().unwrap();
-        let set_code_pat = pat
+    /// Detects a repeating pattern in the observations of a single color. `obs[t]` is
+    /// the set, activated at the step `t`, if any. The ring length is estimated by
+    /// autocorrelation, then the observations are folded by it and each position is
+    /// decided by vote. Positions without votes are left out of the pattern.
+    ///
+    /// Returns `None` if nothing repeats in the observations.
+    pub fn detect(
+        color_code: ColorCode,
+        obs: &[Option<ColoredSetCode>],
+    ) -> Option<(Self, PatternScore)> {
+        let folded = Self::fold(obs)?;
+        let score = folded.score(color_code);
+        let pattern = folded
+            .consensus
             .into_iter()
+            .flatten()
             .map(|colored_set_code| SetCode(color_code, colored_set_code))
             .collect();
 
-        Ok(set_code_pat)
+        Some((pattern, score))
+    }
+
+    /// Folds the observations by the estimated ring length and decides each position
+    /// by vote. Returns `None` if nothing repeats in the observations.
+    pub(super) fn fold<T>(obs: &[Option<T>]) -> Option<Folded<T>>
+    where
+        T: Copy + Ord + Hash,
+    {
+        let (period, periodicity) = Self::estimate_period(obs)?;
+        let record = Self::pattern_to_rec(obs, period);
+
+        let mut consensus = Vec::with_capacity(period);
+        let mut margin = 0.0;
+        for (i, hm) in record.iter().enumerate() {
+            // the number of observations at this position
+            let total = (obs.len() - i + period - 1) / period;
+            let (colored_set_code, pos_margin) = Self::get_max_repeating(hm, total);
+            consensus.push(colored_set_code);
+            margin += pos_margin;
+        }
+        margin /= period as f64;
+
+        // Each repetition is compared with the consensus on the positions, where
+        // either of them has an activation
+        let repetitions = obs.len() / period;
+        let consistency = obs
+            .chunks_exact(period)
+            .map(|chunk| {
+                let active = chunk
+                    .iter()
+                    .zip(consensus.iter())
+                    .filter(|(o, c)| o.is_some() || c.is_some())
+                    .count();
+                let agreed = chunk
+                    .iter()
+                    .zip(consensus.iter())
+                    .filter(|(o, c)| o.is_some() && o == c)
+                    .count();
+                agreed as f64 / std::cmp::max(active, 1) as f64
+            })
+            .sum::<f64>()
+            / repetitions as f64;
+
+        Some(Folded {
+            consensus,
+            period,
+            repetitions,
+            margin,
+            consistency,
+            periodicity,
+        })
+    }
+
+    /// Estimates the period of the observations as the shortest lag, which
+    /// correlates almost as well as the best one. Only lags, which repeat at least
+    /// `MIN_REPETITIONS` times, are considered. Returns the period and its
+    /// autocorrelation, i.e. the share of matching activations at this lag.
+    fn estimate_period<T: PartialEq>(obs: &[Option<T>]) -> Option<(usize, f64)> {
+        let corr: Vec<f64> = (1..=obs.len() / MIN_REPETITIONS)
+            .map(|lag| {
+                let (mut matched, mut active) = (0, 0);
+                for (a, b) in obs.iter().zip(obs[lag..].iter()) {
+                    if a.is_some() || b.is_some() {
+                        active += 1;
+                        matched += (a == b) as usize;
+                    }
+                }
+                if active == 0 {
+                    0.0
+                } else {
+                    matched as f64 / active as f64
+                }
+            })
+            .collect();
+
+        let best = corr.iter().cloned().fold(0.0, f64::max);
+        if best == 0.0 {
+            return None;
+        }
+
+        let idx = corr.iter().position(|&c| c >= best * PERIOD_TOLERANCE)?;
+        Some((idx + 1, corr[idx]))
     }
 
     /// Given a repeated pattern, count which elements repeat on each position
-    fn pattern_to_rec(pattern: Vec<Option<ColoredSetCode>>) -> Vec<HashMap<ColoredSetCode, usize>> {
-        let chunk_len = pattern.len() / REPEATINGS;
-        let mut record = vec![HashMap::new(); chunk_len];
+    fn pattern_to_rec<T: Copy + Eq + Hash>(
+        pattern: &[Option<T>],
+        period: usize,
+    ) -> Vec<HashMap<T, usize>> {
+        let mut record = vec![HashMap::new(); period];
 
         for (i, v) in pattern.iter().enumerate() {
             if let Some(colored_set_code) = v {
-                let cnt = record[i % chunk_len].entry(*colored_set_code).or_insert(0);
+                let cnt = record[i % period].entry(*colored_set_code).or_insert(0);
                 *cnt += 1;
             }
         }
@@ -75,34 +272,41 @@ impl Pattern {
         record
     }
 
-    fn pat_from_rec(rec: Vec<HashMap<ColoredSetCode, usize>>) -> Option<Vec<ColoredSetCode>> {
-        rec.into_iter().map(Self::get_max_repeating).collect()
-    }
-
-    /// Return None if cannot determine most repeating element.
-    fn get_max_repeating(hm: HashMap<ColoredSetCode, usize>) -> Option<ColoredSetCode> {
-        if hm.is_empty() {
-            return None;
-        }
-
-        let (colored_set_code, cnt) = hm.iter().max_by_key(|(_, &cnt)| cnt)?;
+    /// Returns the most repeating element and its vote margin over the runner-up
+    /// among `total` votes. Ties are resolved by the smaller code, but get no margin.
+    fn get_max_repeating<T: Copy + Ord + Hash>(
+        hm: &HashMap<T, usize>,
+        total: usize,
+    ) -> (Option<T>, f64) {
+        let (&colored_set_code, &cnt) =
+            match hm.iter().max_by_key(|(&cc, &cnt)| (cnt, Reverse(cc))) {
+                Some(max) => max,
+                None => return (None, 0.0),
+            };
 
-        // Check uniqueness of the maximum. If not, then we cannot determine that it is a real max
-        if hm
+        let second = hm
             .iter()
-            .any(|(cc, cnt1)| cnt == cnt1 && cc != colored_set_code)
-        {
-            return None;
-        }
-
-        Some(*colored_set_code)
+            .filter(|(&cc, _)| cc != colored_set_code)
+            .map(|(_, &cnt)| cnt)
+            .max()
+            .unwrap_or(0);
+
+        (
+            Some(colored_set_code),
+            (cnt - second) as f64 / total as f64,
+        )
     }
 
-    pub fn window<'a>(&'a self, pos: PatternIdx) -> impl Iterator<Item = &SetCode> + 'a {
+    /// Sets around the position, which is at `window_center(size)` of the window
+    pub fn window<'a>(
+        &'a self,
+        pos: PatternIdx,
+        size: usize,
+    ) -> impl Iterator<Item = &'a SetCode> + 'a {
         let pat_len = self.0.len() as i64;
-        let left = (pos as i64 - WINDOW_SIZE as i64 / 2).rem_euclid(pat_len) as usize;
+        let left = (pos as i64 - window_center(size) as i64).rem_euclid(pat_len) as usize;
 
-        self.0.iter().cycle().skip(left).take(WINDOW_SIZE)
+        self.0.iter().cycle().skip(left).take(size)
     }
 
     #[inline(always)]
@@ -115,48 +319,49 @@ impl Pattern {
         (pos + 1) % self.0.len()
     }
 
-    pub fn recover_next<A>(
+    /// Recovers the position from the probes of the window around `pos`
+    pub fn recover_next(
         &self,
         pos: PatternIdx,
-        probe_res: &[ProbeResult<A>],
+        probe_res: &[ProbeResult<Probe>],
     ) -> Result<PatternIdx> {
-        // First we try to find an activation *right after* the current position.
-        // Here we assume that just an other packet interfered with our
-        // sequence.
-        if let Some(idx) = &probe_res[WINDOW_SIZE / 2..]
+        let center = window_center(probe_res.len());
+        // We look for the most confident activation in the window. Activations
+        // *right after* the current position are preferred on ties. Here we assume
+        // that just an other packet interfered with our sequence. Only then we
+        // look *before* the current position.
+        let after = probe_res[center..]
             .iter()
             .enumerate()
-            .find_map(|(idx, x)| if x.is_stale() { None } else { Some(idx) })
-        {
-            // We have found the index at which the activation *in the window*
-            // was registered. We need to:
-            //
-            // 1. Make it the index of the set in the *whole pattern* by adding **pos**
-            // 2. Make it the **next expected** position by +1
-            return Ok((pos + idx + 1) % self.0.len());
-        }
-        // Here we have found no activation after the current position.
-        // We should try loking for *before*.
-        if let Some(idx) = &probe_res[..WINDOW_SIZE / 2]
+            .map(|(idx, x)| (idx as i64, x));
+        let before = probe_res[..center]
             .iter()
             .enumerate()
-            .find_map(|(idx, x)| if x.is_stale() { None } else { Some(idx) })
-        {
-            // Here we:
-            //
-            // 1. Make the pos the index of the pattern by subtrackting idx
-            // (remember, that we registered activation **behind** the *pos*)
-            // 2. Make it the **next expected** by + 1
-            return Ok((pos as i64 - ((WINDOW_SIZE / 2) as i64 - *idx as i64) + 1)
-                .rem_euclid(self.0.len() as i64) as usize);
+            .map(|(idx, x)| (idx as i64 - center as i64, x));
+
+        let mut best: Option<(i64, f64)> = None;
+        for (ofs, x) in after.chain(before).filter(|(_, x)| x.is_activated()) {
+            let better = match best {
+                Some((_, conf)) => x.confidence() > conf,
+                None => true,
+            };
+            if better {
+                best = Some((ofs, x.confidence()));
+            }
         }
 
-        // We failed to find any of the activations. This is a harsh error, which we cannot
-        // recover from.
-        Err(Error::new(
-            ErrorKind::Other,
-            "ERROR: Cannot recover position",
-        ))
+        match best {
+            // We have found the offset from the current position, at which the
+            // activation *in the window* was registered. We need to make it the
+            // index of the set in the *whole pattern* by adding **pos** (remember,
+            // that the offset is negative for activations **behind** the *pos*).
+            // It becomes the current position, the next activation is expected
+            // right after it.
+            Some((ofs, _)) => Ok((pos as i64 + ofs).rem_euclid(self.0.len() as i64) as usize),
+            // We failed to find any of the activations. This is a harsh error, which we cannot
+            // recover from.
+            None => Err(Error::PositionUnrecoverable(pos)),
+        }
     }
 }
 
@@ -164,6 +369,52 @@ impl Pattern {
 mod tests {
     use super::*;
 
+    fn stale() -> ProbeResult<Probe> {
+        ProbeResult::Stale(Probe::default())
+    }
+
+    fn activated(confidence: f64) -> ProbeResult<Probe> {
+        ProbeResult::Activated(Probe {
+            miss_cnt: 1,
+            confidence,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn pattern_scoring() {
+        let clean: Vec<Option<ColoredSetCode>> =
+            [1, 2, 3].iter().cycle().take(24).map(|&c| Some(c)).collect();
+        let (pattern, score) = Pattern::detect(0, &clean).expect("No pattern detected");
+        assert_eq!(pattern, (1..4).map(|c| SetCode(0, c)).collect::<Pattern>());
+        assert_eq!(score.period, 3, "Wrong ring length");
+        assert_eq!(score.repetitions, 8, "Wrong number of repetitions");
+        assert_eq!(score.score, 1.0, "Clean pattern is not scored perfectly");
+
+        // the ring is not aligned with the color: every fifth step hits a set
+        let sparse: Vec<Option<ColoredSetCode>> = (0..40)
+            .map(|t| if t % 5 == 0 { Some(t / 5 % 2) } else { None })
+            .collect();
+        let (pattern, score) = Pattern::detect(0, &sparse).expect("No pattern detected");
+        assert_eq!(score.period, 10, "Ring length is not estimated");
+        assert_eq!(pattern.len(), 2, "Silent positions are kept");
+
+        // a tie does not discard the pattern, but lowers its margin
+        let mut tied = clean.clone();
+        for &t in &[0, 9, 12, 21] {
+            tied[t] = Some(7);
+        }
+        let (pattern, score) = Pattern::detect(0, &tied).expect("Tie discarded the pattern");
+        assert_eq!(pattern[0], SetCode(0, 1), "Tie is not resolved by the smaller code");
+        assert_eq!(score.period, 3, "Wrong ring length");
+        assert!(score.margin < 1.0, "Tie is not reflected in the margin");
+
+        let noise: Vec<Option<ColoredSetCode>> = (0..24)
+            .map(|t| if t == 5 || t == 17 { Some(t) } else { None })
+            .collect();
+        assert!(Pattern::detect(0, &noise).is_none(), "Pattern detected in noise");
+    }
+
     #[test]
     fn window_test() {
         let pattern: Pattern = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
@@ -171,13 +422,27 @@ mod tests {
             .map(|x| SetCode(x, 1))
             .collect();
 
-        let w: Vec<usize> = pattern.window(2).map(|SetCode(x, _)| *x).collect();
+        let w: Vec<usize> = pattern.window(2, 10).map(|SetCode(x, _)| *x).collect();
 
         assert_eq!(
             w,
             [7, 8, 9, 0, 1, 2, 3, 4, 5, 6],
             "The window is not correct"
         );
+
+        let w: Vec<usize> = pattern.window(2, 5).map(|SetCode(x, _)| *x).collect();
+        assert_eq!(w[window_center(5)], 2, "The position is not centered");
+        assert_eq!(w, [0, 1, 2, 3, 4], "The short window is not correct");
+
+        // the set after the position is left out of a window of two
+        let w: Vec<usize> = pattern.window(2, 2).map(|SetCode(x, _)| *x).collect();
+        assert_eq!(w, [1, 2], "The window of two is not correct");
+        assert_eq!(window_center(2) + 1, w.len(), "The next set is in the window of two");
+
+        let w: Vec<usize> = pattern.window(2, 3).map(|SetCode(x, _)| *x).collect();
+        assert_eq!(w, [1, 2, 3], "The smallest window is not correct");
+        assert_eq!(w[window_center(3)], 2, "The position is not centered");
+        assert_eq!(w[window_center(3) + 1], 3, "The next set is not in the window");
     }
 
     #[test]
@@ -192,34 +457,34 @@ mod tests {
         //                           ^ current_pos
 
         let recoverable_after = vec![
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),     // <- current_pos
-            ProbeResult::Activated(()), // <- corresponds to 3
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
+            stale(),
+            stale(),
+            stale(),
+            stale(),
+            stale(),
+            stale(),        // <- current_pos
+            activated(1.0), // <- corresponds to 3
+            stale(),
+            stale(),
+            stale(),
         ];
         let recoverable_after_expected = 3usize;
 
         let recoverable_before = vec![
-            ProbeResult::Stale(()),
-            ProbeResult::Activated(()), // <- corresponds to 8
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()), // <- current_pos
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
-            ProbeResult::Stale(()),
+            stale(),
+            activated(1.0), // <- corresponds to 8
+            stale(),
+            stale(),
+            stale(),
+            stale(), // <- current_pos
+            stale(),
+            stale(),
+            stale(),
+            stale(),
         ];
         let recoverable_before_expected = 8usize;
 
-        let unrecoverable = vec![ProbeResult::Stale(()); 10];
+        let unrecoverable = vec![stale(); 10];
 
         assert_eq!(
             pattern
@@ -240,4 +505,36 @@ mod tests {
             "Recovery did not result in an error, but should"
         )
     }
+
+    #[test]
+    fn recover_confident_test() {
+        let current_pos = 2;
+        let pattern: Pattern = (0..10).map(|x| SetCode(x, 1)).collect();
+
+        // window is [7, 8, 9, 0, 1, 2, 3, 4, 5, 6]
+        let mut only_before = vec![stale(); 10];
+        only_before[1] = activated(0.9); // <- corresponds to 8
+        let mut only_after = vec![stale(); 10];
+        only_after[7] = activated(0.9); // <- corresponds to 4
+
+        let from_before = pattern.recover_next(current_pos, &only_before).unwrap();
+        let from_after = pattern.recover_next(current_pos, &only_after).unwrap();
+
+        // a noisy activation after the current position loses to a confident one before it
+        let mut probes = only_before.clone();
+        probes[7] = activated(0.2);
+        assert_eq!(
+            pattern.recover_next(current_pos, &probes).unwrap(),
+            from_before,
+            "The less confident activation was chosen"
+        );
+
+        // on ties activations after the current position win
+        probes[7] = activated(0.9);
+        assert_eq!(
+            pattern.recover_next(current_pos, &probes).unwrap(),
+            from_after,
+            "Activation before the current position was chosen on a tie"
+        );
+    }
 }
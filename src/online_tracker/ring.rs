@@ -0,0 +1,147 @@
+//! # RX ring reconstruction
+//!
+//! Real drivers (e.g. ixgbe or igb) keep rings of hundreds or thousands of descriptors
+//! with 2 KiB buffers, which are spread over many pages and thus over many colors.
+//! The patterns, found for single colors, are only fragments of such a ring. The
+//! tracker chains them: it primes all the sets of the fragments, injects a single
+//! packet and records, which of the sets got activated. Folded by the ring length,
+//! the activations give the order of the whole ring.
+//!
+//! Drivers, which recycle buffers by page flipping, alternate each descriptor between
+//! the two halves of its page. Such a ring repeats only after two rounds over its
+//! descriptors, and the second round activates other sets on the same pages, i.e. of
+//! the same colors. A ring over a single color cannot be told from a flipped one,
+//! thus it is never taken as flipped.
+
+use super::pattern::{Pattern, PatternScore};
+use super::SetCode;
+use serde::{Deserialize, Serialize};
+
+// Share of the descriptors, whose rounds should activate sets of the same page
+// for the ring to be taken as flipped
+const FLIP_SHARE: f64 = 0.9;
+
+/// How the descriptors of a reconstructed RX ring map to the eviction sets
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RingLayout {
+    /// Number of descriptors in the ring
+    pub descriptors: usize,
+    /// Whether the buffers are recycled by page flipping
+    pub flipped: bool,
+    /// Number of colors, the ring spans
+    pub colors: usize,
+    /// The sets, which each descriptor activates, in the order of use. A flipped
+    /// descriptor has a set for each half of its page. Descriptors without observed
+    /// activations have no sets.
+    pub sets: Vec<Vec<SetCode>>,
+}
+
+impl RingLayout {
+    /// Infers the layout from the ring, folded by its length
+    fn infer(consensus: &[Option<SetCode>]) -> RingLayout {
+        let mut colors: Vec<_> = consensus.iter().flatten().map(|set_code| set_code.0).collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        let (first, second) = consensus.split_at(consensus.len() / 2);
+        let flipped = consensus.len() % 2 == 0 && colors.len() > 1 && is_flipped(first, second);
+
+        let sets: Vec<Vec<SetCode>> = if flipped {
+            first
+                .iter()
+                .zip(second.iter())
+                .map(|(a, b)| a.iter().chain(b.iter()).copied().collect())
+                .collect()
+        } else {
+            consensus.iter().map(|s| s.iter().copied().collect()).collect()
+        };
+
+        RingLayout {
+            descriptors: sets.len(),
+            flipped,
+            colors: colors.len(),
+            sets,
+        }
+    }
+}
+
+// Checks, that the rounds use other halves of the same pages
+fn is_flipped(first: &[Option<SetCode>], second: &[Option<SetCode>]) -> bool {
+    let pairs: Vec<(SetCode, SetCode)> = first
+        .iter()
+        .zip(second.iter())
+        .filter_map(|(a, b)| Some(((*a)?, (*b)?)))
+        .collect();
+    let same_page = pairs
+        .iter()
+        .filter(|(a, b)| a.0 == b.0 && a.1 != b.1)
+        .count();
+
+    !pairs.is_empty() && same_page as f64 >= pairs.len() as f64 * FLIP_SHARE
+}
+
+/// Reconstructs the ring from the observations, where `obs[t]` is the set, activated
+/// by the `t`-th injected packet, if any. The pattern follows the whole ring, i.e.
+/// both rounds of a flipped one. Returns `None` if nothing repeats in the observations.
+pub(super) fn reconstruct(obs: &[Option<SetCode>]) -> Option<(Pattern, PatternScore, RingLayout)> {
+    let folded = Pattern::fold(obs)?;
+    let first = folded.consensus.iter().flatten().next()?;
+    let score = folded.score(first.0);
+    let layout = RingLayout::infer(&folded.consensus);
+    let pattern = folded.consensus.into_iter().flatten().collect();
+
+    Some((pattern, score, layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Repeats the ring, dropping every seventh activation as if it was missed
+    fn observe(ring: &[SetCode]) -> Vec<Option<SetCode>> {
+        ring.iter()
+            .cycle()
+            .take(ring.len() * 8)
+            .enumerate()
+            .map(|(t, &set_code)| if t % 7 == 3 { None } else { Some(set_code) })
+            .collect()
+    }
+
+    #[test]
+    fn ring_reconstruction() {
+        // two descriptors per page, the pages follow each other
+        let ring = [SetCode(0, 0), SetCode(0, 1), SetCode(1, 0), SetCode(1, 1)];
+        let (pattern, score, layout) = reconstruct(&observe(&ring)).expect("No ring");
+        assert_eq!(pattern, ring.iter().copied().collect(), "Wrong order of the ring");
+        assert_eq!(score.period, 4, "Wrong ring length");
+        assert_eq!(layout.descriptors, 4, "Wrong number of descriptors");
+        assert_eq!(layout.colors, 2, "Wrong number of colors");
+        assert!(!layout.flipped, "Ring is taken as flipped");
+
+        // the second round uses the other halves of the pages
+        let flipped = [
+            SetCode(2, 0),
+            SetCode(5, 0),
+            SetCode(7, 0),
+            SetCode(2, 1),
+            SetCode(5, 1),
+            SetCode(7, 1),
+        ];
+        let (pattern, _, layout) = reconstruct(&observe(&flipped)).expect("No ring");
+        assert_eq!(pattern.len(), 6, "Both rounds are not followed");
+        assert!(layout.flipped, "Page flipping is not detected");
+        assert_eq!(layout.descriptors, 3, "Wrong number of descriptors");
+        assert_eq!(
+            layout.sets[1],
+            vec![SetCode(5, 0), SetCode(5, 1)],
+            "Wrong sets of a descriptor"
+        );
+
+        // a single page cannot be told from a flipped one
+        let single = [SetCode(3, 0), SetCode(3, 1)];
+        let (_, _, layout) = reconstruct(&observe(&single)).expect("No ring");
+        assert!(!layout.flipped, "Single color ring is taken as flipped");
+
+        assert!(reconstruct(&[None; 16]).is_none(), "Ring reconstructed from nothing");
+    }
+}
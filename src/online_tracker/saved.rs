@@ -0,0 +1,195 @@
+//! # Saved patterns
+//!
+//! Located RX ring patterns together with the eviction sets they reference, so that
+//! the location phase can be skipped by the next tracking session against the same
+//! victim.
+
+use super::pattern::{Pattern, PatternScore};
+use super::SetCode;
+use crate::rpp::params::CacheParams;
+use crate::rpp::EvictionSet;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+/// The pattern of a single RX ring with its eviction sets
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedRing {
+    /// The score of the pattern, when it was located
+    pub score: PatternScore,
+    /// Eviction sets of the pattern, each set once
+    pub sets: Vec<EvictionSet>,
+    /// The codes of `sets`, as they were located. They are kept on import, so that
+    /// the measurements of both sessions name the sets the same way.
+    pub codes: Vec<SetCode>,
+    /// The pattern as indices into `sets`
+    pub pattern: Vec<usize>,
+}
+
+/// Patterns of all tracked RX rings
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedPatterns {
+    /// Parameters of the cache, which the eviction sets are built for
+    pub cache: CacheParams,
+    /// The rings, indexed by ring ID
+    pub rings: Vec<SavedRing>,
+}
+
+impl SavedPatterns {
+    /// Parses saved patterns from a JSON document
+    pub fn from_json(s: &str) -> Result<SavedPatterns> {
+        let saved: SavedPatterns = serde_json::from_str(s).map_err(|e| {
+            Error::InvalidData(format!("invalid saved patterns: {}", e))
+        })?;
+        saved.validate()?;
+
+        Ok(saved)
+    }
+
+    /// Loads saved patterns from a JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SavedPatterns> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Saves the patterns to a JSON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|e| Error::InvalidData(e.to_string()))
+    }
+
+    /// Checks, that there is at least one ring, that each pattern is not empty
+    /// and references only the saved sets, and that each set has a single code
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidData(msg.to_string()));
+
+        if self.rings.is_empty() {
+            return invalid("no saved RX rings");
+        }
+
+        let mut known: HashMap<SetCode, &EvictionSet> = HashMap::new();
+        for ring in self.rings.iter() {
+            if ring.pattern.is_empty() || ring.sets.iter().any(|set| set.is_empty()) {
+                return invalid("empty saved pattern or eviction set");
+            }
+            if ring.pattern.iter().any(|&idx| idx >= ring.sets.len()) {
+                return invalid("saved pattern references a missing eviction set");
+            }
+            if ring.codes.len() != ring.sets.len() {
+                return invalid("saved eviction sets and their codes do not match");
+            }
+            for (code, set) in ring.codes.iter().zip(ring.sets.iter()) {
+                if known.insert(*code, set).map_or(false, |other| other != set) {
+                    return invalid("saved set code names different eviction sets");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collects the patterns and the sets they reference
+    pub(super) fn new<'a, F>(
+        cache: CacheParams,
+        patterns: &[Pattern],
+        scores: &[PatternScore],
+        set: F,
+    ) -> SavedPatterns
+    where
+        F: Fn(&SetCode) -> &'a EvictionSet,
+    {
+        let rings = patterns
+            .iter()
+            .zip(scores.iter())
+            .map(|(pattern, &score)| {
+                let mut codes: Vec<SetCode> = Vec::with_capacity(pattern.len());
+                let pattern = pattern
+                    .iter()
+                    .map(|set_code| match codes.iter().position(|c| c == set_code) {
+                        Some(idx) => idx,
+                        None => {
+                            codes.push(*set_code);
+                            codes.len() - 1
+                        }
+                    })
+                    .collect();
+
+                SavedRing {
+                    score,
+                    sets: codes.iter().map(|c| set(c).clone()).collect(),
+                    codes,
+                    pattern,
+                }
+            })
+            .collect();
+
+        SavedPatterns { cache, rings }
+    }
+
+    /// Splits into the eviction sets with their codes, patterns and scores. The sets
+    /// keep the codes, they were located with.
+    pub(super) fn into_parts(
+        self,
+    ) -> (Vec<(SetCode, EvictionSet)>, Vec<Pattern>, Vec<PatternScore>) {
+        let mut sets = Vec::new();
+        let mut patterns = Vec::with_capacity(self.rings.len());
+        let mut scores = Vec::with_capacity(self.rings.len());
+
+        for ring in self.rings.into_iter() {
+            patterns.push(ring.pattern.iter().map(|&idx| ring.codes[idx]).collect());
+            sets.extend(ring.codes.into_iter().zip(ring.sets));
+            scores.push(ring.score);
+        }
+
+        (sets, patterns, scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_patterns_roundtrip() {
+        let mut eviction_sets = HashMap::new();
+        eviction_sets.insert(SetCode(3, 1), vec![64, 128]);
+        eviction_sets.insert(SetCode(3, 7), vec![192, 256]);
+        eviction_sets.insert(SetCode(5, 0), vec![320]);
+
+        let patterns: Vec<Pattern> = vec![
+            vec![SetCode(3, 7), SetCode(3, 1), SetCode(3, 7)].into(),
+            vec![SetCode(5, 0)].into(),
+        ];
+        let scores = vec![PatternScore::default(); 2];
+
+        let saved = SavedPatterns::new(CacheParams::default(), &patterns, &scores, |c| {
+            &eviction_sets[c]
+        });
+        assert_eq!(
+            saved.rings[0].sets,
+            vec![vec![192, 256], vec![64, 128]],
+            "Sets are not deduplicated"
+        );
+        assert_eq!(saved.rings[0].pattern, vec![0, 1, 0], "Wrong saved pattern");
+        assert_eq!(saved.rings[0].codes, vec![SetCode(3, 7), SetCode(3, 1)], "Wrong set codes");
+
+        let json = serde_json::to_string(&saved).unwrap();
+        let parsed = SavedPatterns::from_json(&json).unwrap();
+        assert_eq!(parsed, saved, "JSON roundtrip failed");
+
+        let (sets, restored, _) = parsed.into_parts();
+        assert_eq!(sets[2], (SetCode(5, 0), vec![320]), "Wrong set of the second ring");
+        assert_eq!(restored, patterns, "The set codes are not kept");
+
+        let mut broken = saved.clone();
+        broken.rings[1].pattern = vec![1];
+        assert!(broken.validate().is_err(), "Missing eviction set is accepted");
+
+        let mut broken = saved;
+        broken.rings[1].codes = vec![SetCode(3, 1)];
+        assert!(broken.validate().is_err(), "A code of two eviction sets is accepted");
+    }
+}
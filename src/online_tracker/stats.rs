@@ -0,0 +1,207 @@
+//! # Tracking statistics
+//!
+//! Running statistics of a tracking session, which tell how healthy tracking is:
+//! how fast the measurements are taken, how well the tracker keeps in sync with
+//! the RX rings and which positions of the rings are active.
+
+use super::pattern::PatternIdx;
+use super::{Probe, ProbeResult, RingId, SyncStatus, Time};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Instant;
+
+/// Statistics of a tracking session. They can be watched from another thread while
+/// tracking (see `OnlineTracker::stats_handle`) and are recorded in the session footer.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackerStats {
+    /// Number of taken measurements
+    pub measurements: usize,
+    /// Time since the start of tracking in nanoseconds
+    pub elapsed: Time,
+    /// Synchronizations, which activated the expected set
+    pub sync_hits: usize,
+    /// Synchronizations, which missed the expected set, but the position was recovered
+    pub sync_misses: usize,
+    /// Synchronizations, after which the position could not be recovered
+    pub recover_failures: usize,
+    /// Restarts of measuring after an error
+    pub retries: usize,
+    /// Restarts, after which tracking fails
+    pub max_retries: usize,
+    /// Sum of the probed latencies
+    pub latency_sum: u64,
+    /// Number of the probed latencies
+    pub latency_cnt: u64,
+    /// Activations per pattern position, indexed by ring ID
+    pub activations: Vec<Vec<usize>>,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl TrackerStats {
+    /// Starts the statistics of rings with patterns of the given lengths
+    pub(super) fn start(ring_lens: &[usize], max_retries: usize) -> TrackerStats {
+        TrackerStats {
+            max_retries,
+            activations: ring_lens.iter().map(|&len| vec![0; len]).collect(),
+            started: Some(Instant::now()),
+            ..Default::default()
+        }
+    }
+
+    /// Updates the statistics with a measurement of the window, which starts at
+    /// the position `first` of the ring pattern
+    pub(super) fn record(
+        &mut self,
+        ring: RingId,
+        first: PatternIdx,
+        probe_res: &[ProbeResult<Probe>],
+        status: SyncStatus,
+    ) {
+        self.measurements += 1;
+        match status {
+            SyncStatus::Hit => self.sync_hits += 1,
+            SyncStatus::Miss => self.sync_misses += 1,
+            SyncStatus::NoSync => {}
+        }
+
+        for res in probe_res {
+            self.record_probe(res);
+        }
+
+        let positions = &mut self.activations[ring];
+        let len = positions.len();
+        for (idx, res) in probe_res.iter().enumerate() {
+            if res.is_activated() {
+                positions[(first + idx) % len] += 1;
+            }
+        }
+
+        self.update_elapsed();
+    }
+
+    /// Updates the latencies with a single probe
+    pub(super) fn record_probe(&mut self, res: &ProbeResult<Probe>) {
+        let lats = &res.inner().lats;
+        self.latency_sum += lats.iter().sum::<Time>();
+        self.latency_cnt += lats.len() as u64;
+    }
+
+    /// Updates the statistics with an arrival, observed by passive tracking at
+    /// the position `pos` of the ring pattern
+    pub(super) fn record_arrival(&mut self, ring: RingId, pos: PatternIdx) {
+        self.measurements += 1;
+        self.activations[ring][pos] += 1;
+        self.update_elapsed();
+    }
+
+    /// Brings the elapsed time up to date
+    pub fn update_elapsed(&mut self) {
+        if let Some(started) = self.started {
+            self.elapsed = started.elapsed().as_nanos() as Time;
+        }
+    }
+
+    /// Stops the clock of the finished session
+    pub(super) fn finish(&mut self) {
+        self.update_elapsed();
+        self.started = None;
+    }
+
+    /// Measurements per second
+    pub fn rate(&self) -> f64 {
+        if self.elapsed == 0 {
+            return 0.0;
+        }
+        self.measurements as f64 * 1e9 / self.elapsed as f64
+    }
+
+    /// Share of the synchronizations, which activated the expected set
+    pub fn hit_rate(&self) -> f64 {
+        let syncs = self.sync_hits + self.sync_misses + self.recover_failures;
+        if syncs == 0 {
+            return 0.0;
+        }
+        self.sync_hits as f64 / syncs as f64
+    }
+
+    /// Mean latency of the probed lines
+    pub fn avg_probe_latency(&self) -> f64 {
+        if self.latency_cnt == 0 {
+            return 0.0;
+        }
+        self.latency_sum as f64 / self.latency_cnt as f64
+    }
+}
+
+impl fmt::Display for TrackerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} measurements in {:.1}s ({:.1}/s)",
+            self.measurements,
+            self.elapsed as f64 / 1e9,
+            self.rate()
+        )?;
+        writeln!(
+            f,
+            "sync: {} hits, {} misses, {} failed recoveries ({:.1}% hit)",
+            self.sync_hits,
+            self.sync_misses,
+            self.recover_failures,
+            self.hit_rate() * 100.0
+        )?;
+        writeln!(f, "retries: {}/{}", self.retries, self.max_retries)?;
+        write!(f, "average probe latency: {:.1}", self.avg_probe_latency())?;
+
+        for (ring, positions) in self.activations.iter().enumerate() {
+            let total: usize = positions.iter().sum();
+            write!(f, "\nring {}: {} activations", ring, total)?;
+            let busiest = positions.iter().enumerate().max_by_key(|&(_, cnt)| cnt);
+            if let Some((pos, &cnt)) = busiest.filter(|&(_, &cnt)| cnt > 0) {
+                write!(f, ", busiest position {} ({})", pos, cnt)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(activated: bool, lats: Vec<Time>) -> ProbeResult<Probe> {
+        let probe = Probe {
+            lats,
+            ..Default::default()
+        };
+        if activated {
+            ProbeResult::Activated(probe)
+        } else {
+            ProbeResult::Stale(probe)
+        }
+    }
+
+    #[test]
+    fn stats_recording() {
+        let mut stats = TrackerStats::start(&[4, 2], 100);
+
+        let window = vec![probe(false, vec![10, 20]), probe(true, vec![30, 40])];
+        // the window wraps around the end of the pattern
+        stats.record(0, 3, &window, SyncStatus::Hit);
+        stats.record(0, 3, &window, SyncStatus::Miss);
+        stats.record(1, 0, &window, SyncStatus::NoSync);
+        stats.recover_failures += 1;
+
+        assert_eq!(stats.measurements, 3, "Wrong number of measurements");
+        assert_eq!(stats.activations[0], vec![2, 0, 0, 0], "Wrong ring positions");
+        assert_eq!(stats.activations[1], vec![0, 1], "Wrong ring positions");
+        assert_eq!(stats.avg_probe_latency(), 25.0, "Wrong average latency");
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-9, "Wrong hit rate");
+        assert!(
+            stats.to_string().contains("busiest position 0 (2)"),
+            "No busiest position in the summary"
+        );
+    }
+}
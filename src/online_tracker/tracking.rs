@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 use super::{PatternIdx};
+use crate::rpp::strategy::split_arg;
+use crate::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Weight of the latest synchronization in the recent miss rate
+const MISS_RATE_WEIGHT: f64 = 0.25;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub enum SyncStatus {
@@ -14,22 +22,97 @@ impl Default for SyncStatus {
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
-pub(crate) struct TrackingContext {
+/// Decides, when the tracker injects its own packet to synchronize with the RX ring.
+///
+/// Busy victims fill the ring fast and need frequent synchronization, while idle ones
+/// would be flooded by it. A failed synchronization is always retried with the next
+/// measurement, unless the policy is `Never`.
+///
+/// The policy can be parsed from and printed to a short textual form: `every:N`,
+/// `idle:MS`, `adaptive:N` or `never`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// Injects after the given number of unsynchronized measurements
+    EveryN(usize),
+    /// Injects, if no activation was measured for the given time
+    IdleTimeout(Duration),
+    /// Injects after at most the given number of unsynchronized measurements. The
+    /// more of the recent synchronizations missed, the sooner it injects.
+    Adaptive(usize),
+    /// Never injects, the ring is followed passively
+    Never,
+}
+
+impl Default for SyncPolicy {
+    fn default() -> Self {
+        SyncPolicy::EveryN(3)
+    }
+}
+
+impl fmt::Display for SyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncPolicy::EveryN(n) => write!(f, "every:{}", n),
+            SyncPolicy::IdleTimeout(timeout) => write!(f, "idle:{}", timeout.as_millis()),
+            SyncPolicy::Adaptive(n) => write!(f, "adaptive:{}", n),
+            SyncPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for SyncPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = split_arg(s)?;
+
+        match (name, arg) {
+            ("every", Some(n)) => Ok(SyncPolicy::EveryN(n)),
+            ("idle", Some(ms)) => Ok(SyncPolicy::IdleTimeout(Duration::from_millis(ms as u64))),
+            ("adaptive", Some(n)) => Ok(SyncPolicy::Adaptive(n)),
+            ("never", None) => Ok(SyncPolicy::Never),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown sync policy `{}`. Expected every:N, idle:MS, adaptive:N or never",
+                s
+            ))),
+        }
+    }
+}
+
+/// Position of the tracker in the pattern of a ring and the state of its synchronization
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TrackingContext {
     pos: PatternIdx,
     sync_status: SyncStatus,
     should_send: bool,
     is_injected: bool,
     unsynced: usize,
+    policy: SyncPolicy,
+    // Share of the recent synchronizations, which missed
+    miss_rate: f64,
+    // When the last activation was measured or a packet was injected
+    #[serde(skip)]
+    last_activity: Option<Instant>,
 }
 
 impl TrackingContext {
     #[inline(always)]
-    pub(crate) fn new(init_pos: PatternIdx) -> TrackingContext {
+    pub(crate) fn new(init_pos: PatternIdx, policy: SyncPolicy) -> TrackingContext {
         let mut ctx: TrackingContext = Default::default();
         ctx.pos = init_pos;
+        ctx.policy = policy;
+        ctx.last_activity = Some(Instant::now());
         ctx
     }
+    /// Continues from a saved context, e.g. restored from a checkpoint. Its position
+    /// might be outdated, thus it is verified by synchronizing on the first measurement.
+    #[inline(always)]
+    pub(crate) fn resumed(mut self) -> TrackingContext {
+        self.should_send = true;
+        self.last_activity = Some(Instant::now());
+        self
+    }
     #[inline(always)]
     pub(crate) fn pos(&self) -> PatternIdx {
         self.pos
@@ -40,11 +123,27 @@ impl TrackingContext {
     }
     #[inline(always)]
     pub(crate) fn should_inject(&self) -> bool {
-        self.unsynced > 2 || self.should_send
+        if self.is_injected {
+            return false;
+        }
+
+        match self.policy {
+            SyncPolicy::Never => false,
+            _ if self.should_send => true,
+            SyncPolicy::EveryN(n) => self.unsynced >= n,
+            SyncPolicy::IdleTimeout(timeout) => self
+                .last_activity
+                .map_or(true, |last| last.elapsed() >= timeout),
+            SyncPolicy::Adaptive(n) => {
+                let every = (n as f64 * (1.0 - self.miss_rate)).ceil().max(1.0);
+                self.unsynced as f64 >= every
+            }
+        }
     }
     #[inline(always)]
     pub(crate) fn inject(&mut self) -> &mut Self {
         self.is_injected = true;
+        self.last_activity = Some(Instant::now());
         self
     }
     #[inline(always)]
@@ -58,7 +157,7 @@ impl TrackingContext {
         self.unsynced = 0;
         self.should_send = false;
         self.sync_status = SyncStatus::Hit;
-        self
+        self.synced(false)
     }
 
     #[inline(always)]
@@ -67,15 +166,85 @@ impl TrackingContext {
         self.pos = recovered_pos;
         self.should_send = true;
         self.sync_status = SyncStatus::Miss;
-        self
+        self.synced(true)
     }
 
     #[inline(always)]
-    /// Updates the context corresponding to the missed syncronization
+    /// Updates the context corresponding to the measurement without syncronization
     pub(crate) fn unsynced_meaurement(&mut self) -> &mut Self {
         self.unsynced += 1;
         self.should_send = false;
         self.sync_status = SyncStatus::NoSync;
+        self.last_activity = Some(Instant::now());
         self
     }
+
+    // Finishes the synchronization and updates the recent miss rate.
+    // The injected packet is used up: the following measurements are not
+    // synchronizations, until the policy injects again.
+    fn synced(&mut self, missed: bool) -> &mut Self {
+        let missed = if missed { 1.0 } else { 0.0 };
+        self.miss_rate += MISS_RATE_WEIGHT * (missed - self.miss_rate);
+        self.is_injected = false;
+        self.last_activity = Some(Instant::now());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts the measurements until the context wants to inject
+    fn injects_after(ctx: &mut TrackingContext) -> usize {
+        let mut cnt = 0;
+        while !ctx.should_inject() {
+            ctx.unsynced_meaurement();
+            cnt += 1;
+        }
+        cnt
+    }
+
+    #[test]
+    fn sync_policies() {
+        for s in &["every:3", "idle:250", "adaptive:8", "never"] {
+            let policy: SyncPolicy = s.parse().expect("Policy is not parsed");
+            assert_eq!(&policy.to_string(), s, "Policy does not roundtrip");
+        }
+        assert!("every".parse::<SyncPolicy>().is_err(), "Missing argument is accepted");
+        assert!("never:2".parse::<SyncPolicy>().is_err(), "Extra argument is accepted");
+
+        let mut ctx = TrackingContext::new(0, SyncPolicy::EveryN(3));
+        assert_eq!(injects_after(&mut ctx), 3, "Wrong injection interval");
+        ctx.inject();
+        assert!(!ctx.should_inject(), "Injects twice before synchronization");
+        ctx.sync_miss(4);
+        assert!(!ctx.is_injected(), "Missed synchronization keeps the packet");
+        assert!(ctx.should_inject(), "Missed synchronization is not retried");
+        ctx.inject().sync_hit(5);
+        assert!(!ctx.is_injected(), "Synchronization keeps the packet");
+        ctx.unsynced_meaurement();
+        assert!(!ctx.is_injected(), "Measurement after synchronization is injected");
+        assert_eq!(injects_after(&mut ctx) + 1, 3, "Interval is not restarted");
+
+        let ctx = TrackingContext::new(2, SyncPolicy::EveryN(3)).resumed();
+        assert!(ctx.should_inject(), "Resumed position is not verified");
+
+        let mut ctx = TrackingContext::new(0, SyncPolicy::Adaptive(8));
+        assert_eq!(injects_after(&mut ctx), 8, "Wrong interval without misses");
+        for _ in 0..4 {
+            ctx.inject().sync_miss(0);
+        }
+        ctx.inject().sync_hit(1);
+        assert!(injects_after(&mut ctx) < 8, "Misses do not shorten the interval");
+
+        let ctx = TrackingContext::new(0, SyncPolicy::IdleTimeout(Duration::from_secs(60)));
+        assert!(!ctx.should_inject(), "Injects before the timeout");
+        let ctx = TrackingContext::new(0, SyncPolicy::IdleTimeout(Duration::from_secs(0)));
+        assert!(ctx.should_inject(), "Does not inject after the timeout");
+
+        let mut ctx = TrackingContext::new(0, SyncPolicy::Never);
+        ctx.sync_miss(1);
+        assert!(!ctx.should_inject(), "Passive context injects");
+    }
 }
@@ -0,0 +1,488 @@
+//! # Binary output
+//!
+//! A compact alternative to JSON Lines for long sessions, where the latencies make
+//! up most of the output. A file starts with a header of the magic bytes, the version
+//! of the format and the compression of the rest. The rest is a sequence of frames,
+//! each holding a `Message` serialized with bincode and prefixed with its length
+//! as a little-endian `u32`. Integers are varint-encoded, so that the latencies
+//! mostly take one or three bytes. The first frame is the metadata of the first
+//! session.
+//!
+//! `BinaryReader` reads the frames back one at a time, so that files larger than
+//! the memory can be processed. `to_json_lines` and `from_json_lines` convert
+//! between the formats.
+
+use super::file::JsonLinesRecorder;
+use super::file::JsonLinesReader;
+use super::{Event, Message, Record, SessionFooter, SessionInfo};
+use crate::{Error, Result};
+use bincode::Options;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Version of the binary format. It changes with each incompatible change of
+/// the framing or of the recorded types.
+pub const BINARY_VERSION: u32 = 1;
+
+/// The bytes, which every binary output starts with
+pub const MAGIC: &[u8; 6] = b"NETCAT";
+
+// Frames above this size are considered corrupted
+const MAX_FRAME_LEN: usize = 1 << 30;
+
+/// Compression of the frames of a binary output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// Frames are written as they are. Such output can be continued after a checkpoint.
+    None,
+    /// Frames are compressed with gzip
+    Gzip,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+impl Compression {
+    fn code(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Gzip => 1,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Gzip),
+            _ => Err(Error::InvalidData(format!("unknown compression {}", code))),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown compression `{}`. Expected none or gzip",
+                s
+            ))),
+        }
+    }
+}
+
+/// Tells, whether the reader starts with the magic bytes of a binary output. Reads
+/// at most the magic bytes.
+pub fn is_binary<R: Read>(r: R) -> Result<bool> {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    r.take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+    Ok(magic == MAGIC)
+}
+
+enum Sink<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+/// Outputs each result, session metadata, event and summary as a bincode frame
+pub struct BinaryRecorder<W: Write> {
+    sink: Sink<W>,
+    // bytes written to the underlying writer so far, if they are known
+    offset: Option<u64>,
+    // serialized frame, reused between the records
+    frame: Vec<u8>,
+}
+
+impl<W: Write> BinaryRecorder<W> {
+    /// Writes the header to the writer and wraps it in `BinaryRecorder`
+    pub fn new(mut w: W, compression: Compression) -> Result<BinaryRecorder<W>> {
+        w.write_all(MAGIC)?;
+        w.write_all(&BINARY_VERSION.to_le_bytes())?;
+        w.write_all(&[compression.code()])?;
+
+        let header_len = (MAGIC.len() + 5) as u64;
+        Ok(match compression {
+            Compression::None => Self::with_offset(w, header_len),
+            Compression::Gzip => BinaryRecorder {
+                sink: Sink::Gzip(GzEncoder::new(w, flate2::Compression::default())),
+                offset: None,
+                frame: Vec::new(),
+            },
+        })
+    }
+
+    /// Wraps a writer, which continues an uncompressed output of `offset` bytes,
+    /// e.g. a file, opened for appending. The header is not written again.
+    pub fn with_offset(w: W, offset: u64) -> BinaryRecorder<W> {
+        BinaryRecorder {
+            sink: Sink::Plain(w),
+            offset: Some(offset),
+            frame: Vec::new(),
+        }
+    }
+
+    /// Finishes the compressed stream, if any, and returns the underlying writer
+    pub fn into_inner(self) -> Result<W> {
+        match self.sink {
+            Sink::Plain(w) => Ok(w),
+            Sink::Gzip(encoder) => Ok(encoder.finish()?),
+        }
+    }
+
+    fn write_frame<T: Serialize>(&mut self, msg: &Message<T>) -> Result<()> {
+        self.frame.clear();
+        bincode::DefaultOptions::new().serialize_into(&mut self.frame, msg)?;
+        let len = (self.frame.len() as u32).to_le_bytes();
+
+        let w: &mut dyn Write = match &mut self.sink {
+            Sink::Plain(w) => w,
+            Sink::Gzip(encoder) => encoder,
+        };
+        w.write_all(&len)?;
+        w.write_all(&self.frame)?;
+
+        if let Some(offset) = &mut self.offset {
+            *offset += (len.len() + self.frame.len()) as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Writes bincode-serialized messages. The sessions are separated by their
+/// `Message::Session` frames, so `separate` writes nothing.
+impl<T: Serialize, W: Write> Record<T> for BinaryRecorder<W> {
+    fn record(&mut self, data: T) -> Result<()> {
+        self.write_frame(&Message::Data(data))
+    }
+
+    fn describe(&mut self, info: &SessionInfo) -> Result<()> {
+        self.write_frame(&Message::<T>::Session(Box::new(info.clone())))
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        self.write_frame(&Message::<T>::Event(event.clone()))
+    }
+
+    fn finish(&mut self, footer: &SessionFooter) -> Result<()> {
+        self.write_frame(&Message::<T>::Footer(footer.clone()))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.sink {
+            Sink::Plain(w) => w.flush()?,
+            Sink::Gzip(encoder) => encoder.flush()?,
+        }
+        Ok(())
+    }
+
+    /// The offset is only known without compression
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+}
+
+enum Source<R: Read> {
+    Plain(R),
+    Gzip(MultiGzDecoder<R>),
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::Plain(r) => r.read(buf),
+            Source::Gzip(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Reads the messages, recorded by `BinaryRecorder`, one frame at a time. Reading
+/// stops after the first error.
+pub struct BinaryReader<R: Read, T> {
+    source: Source<R>,
+    compression: Compression,
+    info: SessionInfo,
+    // the first frame, which is read with the header
+    pending: Option<Message<T>>,
+    // serialized frame, reused between the messages
+    frame: Vec<u8>,
+    // the frames cannot be told apart after an error, so reading stops
+    failed: bool,
+}
+
+impl<R: Read, T: DeserializeOwned> BinaryReader<R, T> {
+    /// Reads the header and the metadata of the first session.
+    ///
+    /// # Fails
+    ///
+    /// Fails if the reader does not start with a binary output of a supported
+    /// version, followed by session metadata.
+    pub fn new(mut r: R) -> Result<BinaryReader<R, T>> {
+        let mut header = [0; MAGIC.len() + 5];
+        r.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidData("not a binary output".to_string()));
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + 4]);
+        let version = u32::from_le_bytes(version);
+        if version != BINARY_VERSION {
+            return Err(Error::InvalidData(format!(
+                "unsupported binary format version {}",
+                version
+            )));
+        }
+
+        let compression = Compression::from_code(header[MAGIC.len() + 4])?;
+        let source = match compression {
+            Compression::None => Source::Plain(r),
+            Compression::Gzip => Source::Gzip(MultiGzDecoder::new(r)),
+        };
+
+        let mut reader = BinaryReader {
+            source,
+            compression,
+            info: SessionInfo::default(),
+            pending: None,
+            frame: Vec::new(),
+            failed: false,
+        };
+        match reader.read_frame()? {
+            Some(Message::Session(info)) => {
+                reader.info = (*info).clone();
+                reader.pending = Some(Message::Session(info));
+            }
+            _ => {
+                return Err(Error::InvalidData(
+                    "binary output does not start with session metadata".to_string(),
+                ))
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Metadata of the first session
+    pub fn info(&self) -> &SessionInfo {
+        &self.info
+    }
+
+    /// Compression of the frames
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Skips everything except the data, e.g. to load the measurements of all
+    /// the sessions of a file
+    pub fn data(self) -> impl Iterator<Item = Result<T>> {
+        self.filter_map(|msg| match msg {
+            Ok(Message::Data(data)) => Some(Ok(data)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    // Reads the next frame. The output may only end between the frames.
+    fn read_frame(&mut self) -> Result<Option<Message<T>>> {
+        let mut len = [0; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.source.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(Error::InvalidData(format!("frame of {} bytes is too large", len)));
+        }
+        self.frame.resize(len, 0);
+        self.source.read_exact(&mut self.frame)?;
+
+        Ok(Some(bincode::DefaultOptions::new().deserialize(&self.frame)?))
+    }
+}
+
+impl<T: DeserializeOwned> BinaryReader<BufReader<File>, T> {
+    /// Opens a file, recorded by `BinaryRecorder`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for BinaryReader<R, T> {
+    type Item = Result<Message<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(msg) = self.pending.take() {
+            return Some(Ok(msg));
+        }
+        if self.failed {
+            return None;
+        }
+
+        let res = self.read_frame().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+/// Converts a JSON Lines output of `T`, recorded by `JsonLinesRecorder`, to
+/// the binary format. Returns the number of converted messages.
+pub fn from_json_lines<T, B, W>(input: B, output: W, compression: Compression) -> Result<usize>
+where
+    T: Serialize + DeserializeOwned,
+    B: BufRead,
+    W: Write,
+{
+    let mut recorder = BinaryRecorder::new(output, compression)?;
+    let mut cnt = 0;
+    for msg in JsonLinesReader::<B, T>::new(input) {
+        recorder.write_frame(&msg?)?;
+        cnt += 1;
+    }
+    recorder.into_inner()?.flush()?;
+
+    Ok(cnt)
+}
+
+/// Converts a binary output of `T` to JSON Lines. Returns the number of converted
+/// messages.
+pub fn to_json_lines<T, R, W>(input: R, output: W) -> Result<usize>
+where
+    T: Serialize + DeserializeOwned,
+    R: Read,
+    W: Write,
+{
+    let mut recorder = JsonLinesRecorder::new(output);
+    let mut cnt = 0;
+    for msg in BinaryReader::<R, T>::new(input)? {
+        recorder.write_line(&msg?)?;
+        cnt += 1;
+    }
+    recorder.into_inner()?.flush()?;
+
+    Ok(cnt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::StopReason;
+    use super::*;
+    use crate::online_tracker::{Measurement, SetCode, SyncStatus, MEASUREMENT_VERSION};
+    use crate::rpp::{Calibration, Probe, ProbeResult};
+
+    fn measurement(elapsed: u64) -> Measurement {
+        Measurement {
+            version: MEASUREMENT_VERSION,
+            timestamp: 1_600_000_000_000_000_000 + elapsed,
+            elapsed,
+            ring: 0,
+            pos: 3,
+            sets: (0..10).map(|i| SetCode(0, i)).collect(),
+            injected: false,
+            sync: SyncStatus::NoSync,
+            probes: (0..10)
+                .map(|i| {
+                    ProbeResult::Stale(Probe {
+                        lats: (0..20).map(|l| 200 + i * 20 + l).collect(),
+                        misses: vec![false; 20],
+                        ..Default::default()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    // Records two sessions of a few measurements
+    fn record_sessions<R: Record<Measurement>>(recorder: &mut R) {
+        let footer = SessionFooter {
+            measurements: 3,
+            elapsed: 30,
+            stop: StopReason::Completed,
+            stats: Default::default(),
+        };
+        for _ in 0..2 {
+            recorder.separate().unwrap();
+            recorder.describe(&SessionInfo::default()).unwrap();
+            for elapsed in 0..3 {
+                recorder.record(measurement(elapsed)).unwrap();
+            }
+            recorder.event(&Event::Recalibrated(Calibration::default())).unwrap();
+            recorder.finish(&footer).unwrap();
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let mut json = JsonLinesRecorder::new(Vec::new());
+        record_sessions(&mut json);
+        let json = json.into_inner().unwrap();
+        let expected: Vec<Message<Measurement>> = JsonLinesReader::new(json.as_slice())
+            .collect::<Result<_>>()
+            .unwrap();
+
+        for &compression in &[Compression::None, Compression::Gzip] {
+            let mut recorder = BinaryRecorder::new(Vec::new(), compression).unwrap();
+            record_sessions(&mut recorder);
+            let offset = Record::<Measurement>::offset(&recorder);
+            let binary = recorder.into_inner().unwrap();
+
+            assert!(binary.len() < json.len() / 2, "Binary output is not compact");
+            assert!(is_binary(binary.as_slice()).unwrap(), "Magic is not recognized");
+            match compression {
+                Compression::None => assert_eq!(offset, Some(binary.len() as u64), "Wrong offset"),
+                Compression::Gzip => assert_eq!(offset, None, "Offset of compressed output"),
+            }
+
+            let reader = BinaryReader::<_, Measurement>::new(binary.as_slice()).unwrap();
+            assert_eq!(reader.compression(), compression, "Wrong compression");
+            let messages: Vec<_> = reader.collect::<Result<_>>().unwrap();
+            assert_eq!(messages, expected, "Binary output does not roundtrip");
+        }
+
+        let mut binary = Vec::new();
+        let cnt = from_json_lines::<Measurement, _, _>(json.as_slice(), &mut binary, Compression::Gzip)
+            .unwrap();
+        assert_eq!(cnt, expected.len(), "Wrong number of converted messages");
+        let mut back = Vec::new();
+        to_json_lines::<Measurement, _, _>(binary.as_slice(), &mut back).unwrap();
+        assert_eq!(back, json, "Conversion does not roundtrip");
+
+        assert!(!is_binary(json.as_slice()).unwrap(), "JSON is taken for binary");
+        assert!(
+            BinaryReader::<_, Measurement>::new(json.as_slice()).is_err(),
+            "JSON is read as binary"
+        );
+        let truncated = &binary[..binary.len() - 10];
+        let res: Vec<_> = BinaryReader::<_, Measurement>::new(truncated).unwrap().collect();
+        assert!(res.last().unwrap().is_err(), "Truncated output is not reported");
+    }
+}
@@ -2,7 +2,94 @@
 //!
 //! Provides functionality for saving measurements, gathered by `OnlineTracker`
 
-use std::io::Result;
+use crate::connection::Time;
+use crate::online_tracker::{PatternScore, RingLayout, SyncPolicy, TrackerStats};
+use crate::rpp::measure::MeasurePhases;
+use crate::rpp::params::{CacheParams, CacheSource};
+use crate::rpp::probe::ActivationRule;
+use crate::rpp::strategy::{PrimeStrategy, ProbeStrategy};
+use crate::rpp::timing_classif::{ClassifierKind, DriftConfig};
+use crate::rpp::Calibration;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a tracking session, which is recorded before the measurements
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Parameters of the victim's LLC
+    pub cache: CacheParams,
+    /// Where the cache parameters come from
+    pub cache_source: CacheSource,
+    /// The order of accesses while priming
+    pub prime: PrimeStrategy,
+    /// The order of accesses while probing
+    pub probe: ProbeStrategy,
+    /// The rule, which decides whether a probed set is activated
+    pub activation: ActivationRule,
+    /// The classifier of access timings
+    pub classifier: ClassifierKind,
+    /// How the classifier follows the latency drift
+    pub drift: DriftConfig,
+    /// How accesses are timed in each phase
+    pub measure: MeasurePhases,
+    /// The minimal score of a tracked RX pattern
+    pub min_pattern_score: f64,
+    /// Scores of the tracked RX ring patterns, indexed by ring ID
+    pub patterns: Vec<PatternScore>,
+    /// When packets are injected to synchronize with the RX rings
+    pub sync: SyncPolicy,
+    /// Number of sets, probed around the current position in a ring
+    pub window: usize,
+    /// Layout of the RX ring, if it is reconstructed across colors
+    pub layout: Option<RingLayout>,
+}
+
+/// Notable events of a tracking session, which are recorded between the measurements
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    /// The timing classifier has been retrained
+    Recalibrated(Calibration),
+    /// Hit and miss timings overlap more than allowed, measurements are unreliable
+    DriftAlarm(Calibration),
+}
+
+/// Why a tracking session has ended
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The requested number of measurements is taken or the time is up
+    Completed,
+    /// The session is stopped from outside, e.g. by a signal
+    Stopped,
+    /// Tracking failed with the given error
+    Failed(String),
+}
+
+/// Summary of a tracking session, which is recorded after the measurements
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionFooter {
+    /// Number of recorded measurements
+    pub measurements: usize,
+    /// Duration of the session in nanoseconds
+    pub elapsed: Time,
+    pub stop: StopReason,
+    /// Statistics of the session
+    #[serde(default)]
+    pub stats: TrackerStats,
+}
+
+/// Everything, what a tracking session produces, in the order of appearance
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Message<T> {
+    /// Session metadata, which comes before the measurements
+    Session(Box<SessionInfo>),
+    /// A single measurement
+    Data(T),
+    /// A session event between the measurements
+    Event(Event),
+    /// Session summary, which comes after the measurements
+    Footer(SessionFooter),
+}
 
 /// Provides interface for recording a result in the underlying storage
 pub trait Record<T> {
@@ -13,58 +100,329 @@ pub trait Record<T> {
     fn separate(&mut self) -> Result<()> {
         Ok(())
     }
+
+    /// Saves the session metadata. Recorders, which do not store metadata, may ignore it.
+    fn describe(&mut self, _info: &SessionInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// Saves a session event. Recorders, which do not store events, may ignore it.
+    fn event(&mut self, _event: &Event) -> Result<()> {
+        Ok(())
+    }
+
+    /// Saves the session summary. Recorders, which do not store summaries, may ignore it.
+    fn finish(&mut self, _footer: &SessionFooter) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes sure, that everything recorded so far reaches the underlying storage
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Number of bytes, recorded to the underlying storage so far, if it is known.
+    /// A resumed session continues the output from there.
+    fn offset(&self) -> Option<u64> {
+        None
+    }
 }
 
+pub mod binary;
+
 pub mod file {
-    //! Functionality for saving results into a file
+    //! Functionality for saving results into a file and reading them back
+    //!
+    //! `JsonLinesRecorder` writes a session as JSON Lines: one `Message` per line,
+    //! starting with `Message::Session` and ending with `Message::Footer`, so that
+    //! the sessions of a file are told apart by the records themselves.
+    //! `JsonLinesReader` streams the messages or only the data back.
 
-    use super::Record;
+    use super::{Event, Message, Record, SessionFooter, SessionInfo};
+    use crate::{Error, Result};
+    use serde::de::DeserializeOwned;
     use serde::ser::Serialize;
     use serde_json::to_writer;
-    use std::io::{Error, ErrorKind, Result, Write};
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::marker::PhantomData;
+    use std::path::Path;
+
+    // A writer, which counts the bytes written through it
+    struct CountingWriter<W: Write> {
+        inner: W,
+        // bytes written so far
+        offset: u64,
+    }
+
+    impl<W: Write> Write for CountingWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let written = self.inner.write(buf)?;
+            self.offset += written as u64;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
 
-    /// Outputs data in JSON format to the underlying writer
-    pub struct JsonRecorder<W: Write>(W);
+    /// Outputs each result, session metadata, event and summary as a `Message` on
+    /// its own line
+    pub struct JsonLinesRecorder<W: Write> {
+        inner: CountingWriter<W>,
+    }
+
+    impl<W: Write> JsonLinesRecorder<W> {
+        /// Wraps provided value in `JsonLinesRecorder`
+        pub fn new(w: W) -> JsonLinesRecorder<W> {
+            Self::with_offset(w, 0)
+        }
 
-    impl<W: Write> JsonRecorder<W> {
-        /// Wraps provided value in `JsonRecorder`
-        pub fn new(w: W) -> JsonRecorder<W> {
-            JsonRecorder(w)
+        /// Wraps a writer, which continues an output of `offset` bytes, e.g. a file,
+        /// opened for appending
+        pub fn with_offset(w: W, offset: u64) -> JsonLinesRecorder<W> {
+            JsonLinesRecorder {
+                inner: CountingWriter { inner: w, offset },
+            }
         }
 
-        /// Unwraps this `JsonRecorder<W>`, returns the underlying writer.
-        pub fn into_inner(self) -> Result<W> {
-            Ok(self.0)
+        /// Unwraps this `JsonLinesRecorder<W>`, returns the underlying writer.
+        pub fn into_inner(self) -> io::Result<W> {
+            Ok(self.inner.inner)
+        }
+
+        pub(super) fn write_line<T: Serialize>(&mut self, msg: &Message<T>) -> Result<()> {
+            to_writer(&mut self.inner, msg)?;
+            self.inner.write_all(b"\n")?;
+            Ok(())
         }
     }
 
-    impl<W: Write> Write for JsonRecorder<W> {
-        // IDEA maybe add json validation
-        fn write(&mut self, buf: &[u8]) -> Result<usize> {
-            self.0.write(buf)
+    /// Writes json-serialized messages, one per line. The sessions are separated by
+    /// their `Message::Session` records, so `separate` writes nothing.
+    impl<T: Serialize, W: Write> Record<T> for JsonLinesRecorder<W> {
+        fn record(&mut self, data: T) -> Result<()> {
+            self.write_line(&Message::Data(data))
+        }
+
+        fn describe(&mut self, info: &SessionInfo) -> Result<()> {
+            self.write_line(&Message::<T>::Session(Box::new(info.clone())))
+        }
+
+        fn event(&mut self, event: &Event) -> Result<()> {
+            self.write_line(&Message::<T>::Event(event.clone()))
+        }
+
+        fn finish(&mut self, footer: &SessionFooter) -> Result<()> {
+            self.write_line(&Message::<T>::Footer(footer.clone()))
         }
 
         fn flush(&mut self) -> Result<()> {
-            self.0.flush()
+            Ok(Write::flush(&mut self.inner)?)
+        }
+
+        fn offset(&self) -> Option<u64> {
+            Some(self.inner.offset)
+        }
+    }
+
+    /// Reads the messages, recorded by `JsonLinesRecorder`, one line at a time.
+    /// Empty lines are skipped.
+    pub struct JsonLinesReader<B, T> {
+        inner: B,
+        line: String,
+        // number of the last read line
+        line_no: usize,
+        _data: PhantomData<T>,
+    }
+
+    impl<B: BufRead, T: DeserializeOwned> JsonLinesReader<B, T> {
+        /// Wraps provided reader in `JsonLinesReader`
+        pub fn new(inner: B) -> JsonLinesReader<B, T> {
+            JsonLinesReader {
+                inner,
+                line: String::new(),
+                line_no: 0,
+                _data: PhantomData,
+            }
+        }
+
+        /// Skips everything except the data, e.g. to load the measurements of all
+        /// the sessions of a file
+        pub fn data(self) -> impl Iterator<Item = Result<T>> {
+            self.filter_map(|msg| match msg {
+                Ok(Message::Data(data)) => Some(Ok(data)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+        }
+    }
+
+    impl<T: DeserializeOwned> JsonLinesReader<BufReader<File>, T> {
+        /// Opens a file, recorded by `JsonLinesRecorder`
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+            Ok(Self::new(BufReader::new(File::open(path)?)))
+        }
+    }
+
+    impl<B: BufRead, T: DeserializeOwned> Iterator for JsonLinesReader<B, T> {
+        type Item = Result<Message<T>>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                self.line.clear();
+                match self.inner.read_line(&mut self.line) {
+                    Ok(0) => return None,
+                    Ok(_) => self.line_no += 1,
+                    Err(e) => return Some(Err(e.into())),
+                }
+
+                let line = self.line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                return Some(serde_json::from_str(line).map_err(|e| {
+                    Error::InvalidData(format!("line {}: {}", self.line_no, e))
+                }));
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::StopReason;
+        use super::*;
+        use crate::rpp::Calibration;
+
+        #[test]
+        fn json_lines_roundtrip() {
+            let event = Event::Recalibrated(Calibration::default());
+            let footer = SessionFooter {
+                measurements: 2,
+                elapsed: 10,
+                stop: StopReason::Completed,
+                stats: Default::default(),
+            };
+
+            let mut recorder = JsonLinesRecorder::new(Vec::new());
+            for session in 0..2 {
+                Record::<u32>::separate(&mut recorder).unwrap();
+                Record::<u32>::describe(&mut recorder, &SessionInfo::default()).unwrap();
+                recorder.record(session).unwrap();
+                Record::<u32>::event(&mut recorder, &event).unwrap();
+                recorder.record(session + 10).unwrap();
+                Record::<u32>::finish(&mut recorder, &footer).unwrap();
+            }
+            let offset = Record::<u32>::offset(&recorder);
+            let output = recorder.into_inner().unwrap();
+            assert_eq!(offset, Some(output.len() as u64), "Wrong offset");
+            assert_eq!(
+                output.iter().filter(|&&b| b == b'\n').count(),
+                10,
+                "Not a message per line"
+            );
+
+            let messages: Vec<Message<u32>> = JsonLinesReader::new(output.as_slice())
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(messages[0], Message::Session(Box::default()), "No session start");
+            assert_eq!(messages[2], Message::Event(event), "Wrong event");
+            assert_eq!(messages[4], Message::Footer(footer), "No session end");
+
+            let data: Vec<u32> = JsonLinesReader::new(output.as_slice())
+                .data()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(data, vec![0, 10, 1, 11], "Wrong data");
+
+            let broken = b"{\"Data\":1}\n\n{\"Data\":";
+            let res: Vec<Result<Message<u32>>> = JsonLinesReader::new(&broken[..]).collect();
+            assert!(res[0].is_ok(), "Valid line is rejected");
+            assert!(
+                matches!(&res[1], Err(Error::InvalidData(msg)) if msg.starts_with("line 3")),
+                "Truncated line is not reported"
+            );
         }
     }
+}
 
-    /// Writes json-serialized data
-    impl<T: Serialize, W: Write> Record<T> for JsonRecorder<W> {
+pub mod channel {
+    //! Hands the results to another thread
+
+    use super::{Event, Message, Record, SessionFooter, SessionInfo};
+    use crate::{Error, Result};
+    use std::sync::mpsc::Sender;
+
+    fn disconnected<E>(_: E) -> Error {
+        Error::Disconnected
+    }
+
+    /// Sends each result as a message. Fails with `Disconnected` as soon as the
+    /// receiver is dropped, which stops the tracking.
+    impl<T> Record<T> for Sender<Message<T>> {
         fn record(&mut self, data: T) -> Result<()> {
-            to_writer(self, &data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            self.send(Message::Data(data)).map_err(disconnected)
         }
 
-        fn separate(&mut self) -> Result<()> {
-            self.write_all(b"\n------------------------------------------------------------------------------------\n")?;
-            Ok(())
+        fn describe(&mut self, info: &SessionInfo) -> Result<()> {
+            self.send(Message::Session(Box::new(info.clone()))).map_err(disconnected)
+        }
+
+        fn event(&mut self, event: &Event) -> Result<()> {
+            self.send(Message::Event(event.clone())).map_err(disconnected)
+        }
+
+        fn finish(&mut self, footer: &SessionFooter) -> Result<()> {
+            self.send(Message::Footer(footer.clone())).map_err(disconnected)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::StopReason;
+        use super::*;
+        use crate::rpp::Calibration;
+        use std::sync::mpsc::channel;
+
+        #[test]
+        fn channel_recording() {
+            let (mut tx, rx) = channel();
+            let event = Event::Recalibrated(Calibration::default());
+            let footer = SessionFooter {
+                measurements: 1,
+                elapsed: 10,
+                stop: StopReason::Stopped,
+                stats: Default::default(),
+            };
+
+            tx.describe(&SessionInfo::default()).unwrap();
+            tx.record(1).unwrap();
+            tx.event(&event).unwrap();
+            tx.finish(&footer).unwrap();
+
+            let received: Vec<Message<i32>> = rx.try_iter().collect();
+            assert_eq!(
+                received,
+                vec![
+                    Message::Session(Box::default()),
+                    Message::Data(1),
+                    Message::Event(event),
+                    Message::Footer(footer)
+                ],
+                "Wrong messages received"
+            );
+
+            drop(rx);
+            let err = tx.record(2).unwrap_err();
+            assert!(matches!(err, Error::Disconnected), "Disconnection is not reported");
         }
     }
 }
 
 pub mod vec {
     use super::Record;
-    use std::io::Result;
+    use crate::Result;
 
     /// Just pushes the data to a vector
     impl<T> Record<T> for Vec<T> {
@@ -0,0 +1,301 @@
+//! # Calibration report
+//!
+//! Tells, whether the timing channel through a connection is good enough for tracking,
+//! before building eviction sets. Hit and miss latencies are sampled through a
+//! `CacheConnector` and summarized in a `CalibrationReport`, which can be saved as
+//! JSON or printed as a text histogram.
+
+use super::params::{CacheParams, PAGE_SIZE};
+use super::timing_classif::{CacheTiming, ClassifierKind};
+use crate::connection::{Address, CacheConnector, Time};
+use crate::{Error, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const BINS: usize = 40;
+const BAR_WIDTH: usize = 30;
+// Bhattacharyya coefficient, below which the distributions are considered disjoint
+const MIN_COEFFICIENT: f64 = 1e-12;
+
+/// Latencies of known hits and known misses. `hits[i]` and `misses[i]` are measured
+/// for the same line.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalibrationSamples {
+    pub hits: Vec<Time>,
+    pub misses: Vec<Time>,
+}
+
+/// Samples up to `samples` hit and miss latencies through the connection. The lines are
+/// taken from distinct pages of a buffer, sized for the given cache. If the connection
+/// can flush lines, each line is flushed before timing a miss, otherwise a line is
+/// expected to be uncached on the first access.
+///
+/// Unlike training, samples with a hit slower than a miss are kept, as they show
+/// the quality of the channel.
+pub fn sample<C: CacheConnector>(
+    conn: &mut C,
+    cparams: &CacheParams,
+    samples: usize,
+) -> Result<CalibrationSamples> {
+    let mut rng = rand::thread_rng();
+    let pages = cparams.addr_num();
+    let lines_per_page = PAGE_SIZE / cparams.bytes_per_line();
+
+    conn.reserve(pages * PAGE_SIZE);
+
+    let page_nums: Vec<usize> = (0..pages).collect();
+    let addrs: Vec<Address> = page_nums
+        .choose_multiple(&mut rng, samples)
+        .map(|&page| page * PAGE_SIZE + rng.gen_range(0, lines_per_page) * cparams.bytes_per_line())
+        .collect();
+
+    let mut res = CalibrationSamples {
+        hits: Vec::with_capacity(addrs.len()),
+        misses: Vec::with_capacity(addrs.len()),
+    };
+
+    for addr in addrs {
+        conn.flush(addr)?;
+        res.misses.push(conn.time_access(addr)?);
+
+        conn.cache(addr)?;
+        res.hits.push(conn.time_access(addr)?);
+    }
+
+    Ok(res)
+}
+
+/// Hit and miss counts in bins of equal width
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histograms {
+    /// The lower bound of the first bin
+    pub low: Time,
+    /// The width of each bin
+    pub width: Time,
+    pub hits: Vec<u64>,
+    pub misses: Vec<u64>,
+}
+
+impl Histograms {
+    fn new(samples: &CalibrationSamples, bins: usize) -> Histograms {
+        let all = || samples.hits.iter().chain(samples.misses.iter()).copied();
+        let low = all().min().unwrap_or_default();
+        let high = all().max().unwrap_or_default();
+        let width = std::cmp::max((high - low) / bins as Time + 1, 1);
+        let n = ((high - low) / width) as usize + 1;
+
+        let count = |lats: &[Time]| {
+            let mut counts = vec![0; n];
+            for &t in lats {
+                counts[((t - low) / width) as usize] += 1;
+            }
+            counts
+        };
+
+        Histograms {
+            low,
+            width,
+            hits: count(&samples.hits),
+            misses: count(&samples.misses),
+        }
+    }
+
+    /// Bhattacharyya coefficient of the hit and miss distributions. It is 1 for the
+    /// same distributions and 0 for disjoint ones.
+    pub fn bhattacharyya_coefficient(&self) -> f64 {
+        let total = |v: &[u64]| v.iter().sum::<u64>() as f64;
+        let (hits, misses) = (total(&self.hits), total(&self.misses));
+        if hits == 0.0 || misses == 0.0 {
+            return 0.0;
+        }
+
+        self.hits
+            .iter()
+            .zip(self.misses.iter())
+            .map(|(&h, &m)| (h as f64 / hits * m as f64 / misses).sqrt())
+            .sum()
+    }
+}
+
+/// Summary of the timing channel quality
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationReport {
+    /// Number of sampled hit and miss pairs
+    pub samples: usize,
+    pub histograms: Histograms,
+    /// Median hit and miss latencies
+    pub hit_median: Time,
+    pub miss_median: Time,
+    /// The classifier, the misclassification rate is estimated for
+    pub classifier: ClassifierKind,
+    /// Hit and miss centroids of the trained classifier, if it reports them
+    pub centroids: Option<(f64, f64)>,
+    /// Share of misclassified timings. The classifier is trained on one half
+    /// of the samples and tested on the other.
+    pub misclassification: f64,
+    /// Bhattacharyya distance between the hit and miss distributions. The larger,
+    /// the better the channel. Disjoint distributions get a large finite value.
+    pub bhattacharyya: f64,
+}
+
+impl CalibrationReport {
+    /// Builds the report for the given samples.
+    ///
+    /// # Fails
+    ///
+    /// Fails if there are less than two samples.
+    pub fn new(samples: &CalibrationSamples, kind: ClassifierKind) -> Result<CalibrationReport> {
+        let n = std::cmp::min(samples.hits.len(), samples.misses.len());
+        if n < 2 {
+            return Err(Error::ClassifierUntrained {
+                samples: n,
+                needed: 2,
+            });
+        }
+
+        // train on even samples and test on odd ones
+        let mut classifier = kind.build();
+        for i in (0..n).step_by(2) {
+            classifier.record(CacheTiming::Hit(samples.hits[i]));
+            classifier.record(CacheTiming::Miss(samples.misses[i]));
+        }
+        classifier.fit();
+
+        let (mut wrong, mut tested) = (0, 0);
+        for i in (1..n).step_by(2) {
+            wrong += classifier.is_miss(samples.hits[i]) as usize;
+            wrong += classifier.is_hit(samples.misses[i]) as usize;
+            tested += 2;
+        }
+
+        let histograms = Histograms::new(samples, BINS);
+        let coefficient = histograms.bhattacharyya_coefficient().max(MIN_COEFFICIENT);
+
+        Ok(CalibrationReport {
+            samples: n,
+            hit_median: median(&samples.hits),
+            miss_median: median(&samples.misses),
+            classifier: kind,
+            centroids: classifier.centroids(),
+            misclassification: wrong as f64 / tested as f64,
+            bhattacharyya: -coefficient.ln(),
+            histograms,
+        })
+    }
+}
+
+fn median(lats: &[Time]) -> Time {
+    let mut lats = lats.to_vec();
+    lats.sort();
+    lats[(lats.len() - 1) / 2]
+}
+
+/// Prints a summary and a text histogram, where `#` stands for hits and `*` for misses
+impl fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Samples:            {}", self.samples)?;
+        writeln!(
+            f,
+            "Median hit/miss:    {} / {}",
+            self.hit_median, self.miss_median
+        )?;
+        if let Some((hit, miss)) = self.centroids {
+            writeln!(f, "Centroids:          {:.1} / {:.1}", hit, miss)?;
+        }
+        writeln!(
+            f,
+            "Misclassification:  {:.2}% ({})",
+            self.misclassification * 100.0,
+            self.classifier
+        )?;
+        writeln!(f, "Bhattacharyya dist: {:.3}", self.bhattacharyya)?;
+
+        let h = &self.histograms;
+        let max = h
+            .hits
+            .iter()
+            .chain(h.misses.iter())
+            .copied()
+            .max()
+            .unwrap_or(1);
+        let bar = |cnt: u64, c: &str| {
+            c.repeat((cnt as usize * BAR_WIDTH + max as usize - 1) / max as usize)
+        };
+
+        for (i, (&hits, &misses)) in h.hits.iter().zip(h.misses.iter()).enumerate() {
+            writeln!(
+                f,
+                "{:>8} |{:<width$}|{}",
+                h.low + i as Time * h.width,
+                bar(hits, "#"),
+                bar(misses, "*"),
+                width = BAR_WIDTH
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::sim::{fixtures, SimulatedCacheConnector};
+
+    #[test]
+    fn sim_calibration() {
+        let cparams = fixtures::cache_params();
+        let mut conn = SimulatedCacheConnector::new(&cparams);
+
+        let samples = sample(&mut conn, &cparams, 100).unwrap();
+        assert_eq!(samples.hits.len(), 100, "Wrong number of samples");
+        assert!(samples.hits.iter().all(|&t| t < 200), "Hit is too slow");
+        assert!(samples.misses.iter().all(|&t| t >= 300), "Miss is too fast");
+
+        let report = CalibrationReport::new(&samples, ClassifierKind::Centroid).unwrap();
+        assert_eq!(
+            report.misclassification, 0.0,
+            "Separated timings misclassified"
+        );
+        assert!(
+            report.bhattacharyya > 10.0,
+            "Separated timings are not distant"
+        );
+        assert!(report.centroids.is_some(), "No centroids reported");
+
+        let text = report.to_string();
+        assert!(
+            text.contains('#') && text.contains('*'),
+            "No histogram printed"
+        );
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: CalibrationReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed.histograms, report.histograms,
+            "JSON roundtrip failed"
+        );
+    }
+
+    #[test]
+    fn overlapping_report() {
+        let samples = CalibrationSamples {
+            hits: (0..100).map(|i| 100 + i).collect(),
+            misses: (0..100).map(|i| 150 + i).collect(),
+        };
+
+        let report = CalibrationReport::new(&samples, ClassifierKind::Centroid).unwrap();
+        assert!(report.misclassification > 0.1, "Overlap is not detected");
+        assert!(
+            report.bhattacharyya < 1.0,
+            "Overlapping timings are too distant"
+        );
+
+        let few = CalibrationSamples {
+            hits: vec![100],
+            misses: vec![300],
+        };
+        assert!(CalibrationReport::new(&few, ClassifierKind::Centroid).is_err());
+    }
+}
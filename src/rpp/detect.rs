@@ -0,0 +1,241 @@
+//! # Cache detection
+//!
+//! Derives `CacheParams` of the local last level cache. CPUID (leafs 4 and 0xB)
+//! is queried first, `/sys/devices/system/cpu` is used as a fallback.
+//! Only useful with a local connector, as it describes the machine we run on.
+
+use super::params::{CacheParams, PAGE_SIZE};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::num::ParseIntError;
+use std::path::Path;
+
+// We want this many congruent addresses per set in excess of the set size
+const ADDR_SLACK: usize = 4;
+const MIN_ADDR_NUM: usize = 5000;
+const SYSFS_CPU: &str = "/sys/devices/system/cpu";
+
+/// Where the description of the cache came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectionMethod {
+    Cpuid,
+    Sysfs,
+}
+
+/// Result of the local cache detection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedCache {
+    pub params: CacheParams,
+    /// Estimated number of LLC slices (one per physical core on Intel)
+    pub slices: usize,
+    pub method: DetectionMethod,
+}
+
+/// Raw description of the last level cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct LlcInfo {
+    line_size: usize,
+    ways: usize,
+    sets: usize,
+    cores: usize,
+}
+
+impl LlcInfo {
+    fn size(&self) -> usize {
+        self.line_size * self.ways * self.sets
+    }
+
+    fn into_detected(self, method: DetectionMethod) -> Result<DetectedCache> {
+        if self.line_size == 0 || self.ways == 0 || self.sets == 0 {
+            return Err(Error::InvalidData(
+                "incomplete description of the last level cache".to_string(),
+            ));
+        }
+
+        let size = self.size();
+        // each page covers `PAGE_SIZE / line_size` sets, the rest is given by the page color
+        let colors = std::cmp::max(size / (self.ways * PAGE_SIZE), 1);
+        let addr_num = std::cmp::max(colors * (self.ways + ADDR_SLACK), MIN_ADDR_NUM);
+
+        Ok(DetectedCache {
+            params: CacheParams::new(self.line_size, self.ways, self.ways, size, addr_num)?,
+            slices: std::cmp::max(self.cores, 1),
+            method,
+        })
+    }
+}
+
+/// Detects the last level cache of the local machine
+pub fn detect() -> Result<DetectedCache> {
+    match cpuid_llc() {
+        Some(llc) => llc.into_detected(DetectionMethod::Cpuid),
+        None => sysfs_llc(SYSFS_CPU)?.into_detected(DetectionMethod::Sysfs),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpuid_llc() -> Option<LlcInfo> {
+    use core::arch::x86_64::__cpuid_count;
+
+    #[allow(unused_unsafe)]
+    let cpuid = |leaf, subleaf| unsafe { __cpuid_count(leaf, subleaf) };
+
+    let max_leaf = cpuid(0, 0).eax;
+    if max_leaf < 4 {
+        return None;
+    }
+
+    // Leaf 4: deterministic cache parameters, one subleaf per cache
+    let mut caches = Vec::new();
+    for subleaf in 0.. {
+        let r = cpuid(4, subleaf);
+        let cache_type = r.eax & 0x1f;
+        if cache_type == 0 {
+            break;
+        }
+        // we are not interested in instruction caches
+        if cache_type == 2 {
+            continue;
+        }
+        let level = (r.eax >> 5) & 0x7;
+        let info = LlcInfo {
+            line_size: ((r.ebx & 0xfff) + 1) as usize,
+            ways: (((r.ebx >> 22) & 0x3ff) + 1) as usize,
+            sets: (r.ecx as usize + 1) * (((r.ebx >> 12) & 0x3ff) + 1) as usize,
+            cores: 0,
+        };
+        caches.push((level, info));
+    }
+    let (_, mut info) = caches.into_iter().max_by_key(|(level, _)| *level)?;
+
+    // Leaf 0xB: extended topology. Level 0 is SMT, level 1 is core.
+    if max_leaf >= 0xb {
+        let threads = cpuid(0xb, 0).ebx & 0xffff;
+        let logical = cpuid(0xb, 1).ebx & 0xffff;
+        info.cores = logical.checked_div(threads).unwrap_or(0) as usize;
+    }
+
+    Some(info)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cpuid_llc() -> Option<LlcInfo> {
+    None
+}
+
+/// Reads the description of the highest level cache of `cpu0`
+fn sysfs_llc<P: AsRef<Path>>(root: P) -> Result<LlcInfo> {
+    let cpu0 = root.as_ref().join("cpu0");
+    let mut caches = Vec::new();
+
+    for entry in fs::read_dir(cpu0.join("cache"))? {
+        let dir = entry?.path();
+        let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if !name.starts_with("index") {
+            continue;
+        }
+
+        if read_trimmed(dir.join("type"))? == "Instruction" {
+            continue;
+        }
+        let level = read_num(dir.join("level"))?;
+        let info = LlcInfo {
+            line_size: read_num(dir.join("coherency_line_size"))?,
+            ways: read_num(dir.join("ways_of_associativity"))?,
+            sets: read_num(dir.join("number_of_sets"))?,
+            cores: 0,
+        };
+        let shared = read_trimmed(dir.join("shared_cpu_list")).unwrap_or_default();
+
+        caches.push((level, info, shared));
+    }
+
+    let (_, mut info, shared) = caches
+        .into_iter()
+        .max_by_key(|(level, _, _)| *level)
+        .ok_or_else(|| Error::InvalidData("no caches described in sysfs".to_string()))?;
+
+    // Each physical core sharing the LLC contributes one slice
+    let threads = read_trimmed(cpu0.join("topology/thread_siblings_list"))
+        .map(|s| cpu_list_len(&s))
+        .unwrap_or(1);
+    info.cores = cpu_list_len(&shared) / std::cmp::max(threads, 1);
+
+    Ok(info)
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_num<P: AsRef<Path>>(path: P) -> Result<usize> {
+    read_trimmed(path)?
+        .parse()
+        .map_err(|e: ParseIntError| Error::InvalidData(e.to_string()))
+}
+
+/// Counts CPUs in a list like `0-3,8-11`
+fn cpu_list_len(list: &str) -> usize {
+    list.split(',')
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            let mut bounds = r.splitn(2, '-').map(|b| b.trim().parse::<usize>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(lo)), Some(Ok(hi))) if hi >= lo => hi - lo + 1,
+                (Some(Ok(_)), None) => 1,
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_list_test() {
+        assert_eq!(cpu_list_len("0-3,8-11"), 8, "Ranges are counted wrong");
+        assert_eq!(cpu_list_len("0,4"), 2, "Single CPUs are counted wrong");
+        assert_eq!(cpu_list_len(""), 0, "Empty list is not empty");
+    }
+
+    #[test]
+    fn sysfs_test() {
+        let root = std::env::temp_dir().join(format!("netcat-sysfs-{}", std::process::id()));
+        let write = |path: &str, val: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, val).unwrap();
+        };
+
+        for (idx, level, ty, ways, sets) in &[
+            ("index0", "1", "Data", "8", "64"),
+            ("index1", "1", "Instruction", "8", "64"),
+            ("index3", "3", "Unified", "16", "8192"),
+        ] {
+            let dir = format!("cpu0/cache/{}/", idx);
+            write(&(dir.clone() + "level"), level);
+            write(&(dir.clone() + "type"), ty);
+            write(&(dir.clone() + "ways_of_associativity"), ways);
+            write(&(dir.clone() + "number_of_sets"), sets);
+            write(&(dir.clone() + "coherency_line_size"), "64\n");
+            write(&(dir + "shared_cpu_list"), "0-7\n");
+        }
+        write("cpu0/topology/thread_siblings_list", "0,4\n");
+
+        let detected = sysfs_llc(&root)
+            .expect("Failed to read sysfs")
+            .into_detected(DetectionMethod::Sysfs)
+            .expect("Failed to derive params");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            detected.params,
+            CacheParams::new(64, 16, 16, 8_388_608, 5000).unwrap(),
+            "Detected params are wrong"
+        );
+        assert_eq!(detected.slices, 4, "Slice estimate is wrong");
+    }
+}
@@ -0,0 +1,184 @@
+//! # Measurement modes
+//!
+//! A single timing may be spoiled by network jitter. Measurement modes allow to
+//! trade speed for accuracy by timing an access several times and aggregating
+//! the results. The mode is chosen per phase: profiling of eviction sets, RX buffer
+//! location and tracking.
+//!
+//! | Mode         | Meaning                                                    |
+//! |--------------|------------------------------------------------------------|
+//! | `single`     | each access is timed once                                  |
+//! | `median:N`   | median of `N` timings                                      |
+//! | `trimmed:N`  | mean of `N` timings without the lowest and highest quarter |
+//! | `adaptive:N` | only ambiguous timings are repeated, up to `N` in total    |
+//!
+//! While profiling, the whole eviction test is repeated. While probing, only the timed
+//! read of a line is repeated, which is meaningful only if timed reads do not bring
+//! lines into the cache, as with RDMA reads.
+
+use super::strategy::split_arg;
+use super::timing_classif::LatencyClassifier;
+use crate::connection::Time;
+use crate::median;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Timings classified with less certainty are ambiguous
+const AMBIGUOUS_CERTAINTY: f64 = 0.5;
+
+/// Describes, how many times an access is timed and how the timings are aggregated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasureMode {
+    /// Each access is timed once
+    Single,
+    /// Median of the given number of timings
+    Median(usize),
+    /// Mean of the given number of timings without the lowest and the highest quarter
+    TrimmedMean(usize),
+    /// Timings between the classes (or invalid ones) are repeated until their median
+    /// is no longer ambiguous, up to the given number of timings in total
+    Adaptive(usize),
+}
+
+impl Default for MeasureMode {
+    fn default() -> Self {
+        MeasureMode::Single
+    }
+}
+
+impl MeasureMode {
+    /// Times an access with `time` as many times as the mode requires and returns the
+    /// aggregated latency. The classifier is used to find ambiguous timings.
+    pub fn measure<F>(&self, classifier: &dyn LatencyClassifier, mut time: F) -> Result<Time>
+    where
+        F: FnMut() -> Result<Time>,
+    {
+        match *self {
+            MeasureMode::Single => time(),
+            MeasureMode::Median(n) => Ok(median! {n; { time()? }}),
+            MeasureMode::TrimmedMean(n) => {
+                let lats = (0..n).map(|_| time()).collect::<Result<Vec<Time>>>()?;
+                Ok(trimmed_mean(lats))
+            }
+            MeasureMode::Adaptive(n) => {
+                let is_ambiguous = |t: Time| {
+                    classifier.is_invalid(t) || classifier.certainty(t) < AMBIGUOUS_CERTAINTY
+                };
+
+                let mut lats = vec![time()?];
+                while lats.len() < n && is_ambiguous(median_of(&mut lats)) {
+                    lats.push(time()?);
+                }
+
+                Ok(median_of(&mut lats))
+            }
+        }
+    }
+}
+
+fn median_of(lats: &mut [Time]) -> Time {
+    lats.sort();
+    lats[(lats.len() - 1) / 2]
+}
+
+fn trimmed_mean(mut lats: Vec<Time>) -> Time {
+    lats.sort();
+    let trim = lats.len() / 4;
+    let kept = &lats[trim..lats.len() - trim];
+
+    kept.iter().sum::<Time>() / kept.len() as Time
+}
+
+impl fmt::Display for MeasureMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeasureMode::Single => write!(f, "single"),
+            MeasureMode::Median(n) => write!(f, "median:{}", n),
+            MeasureMode::TrimmedMean(n) => write!(f, "trimmed:{}", n),
+            MeasureMode::Adaptive(n) => write!(f, "adaptive:{}", n),
+        }
+    }
+}
+
+impl FromStr for MeasureMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = split_arg(s)?;
+
+        match (name, arg) {
+            ("single", None) => Ok(MeasureMode::Single),
+            ("median", Some(n)) => Ok(MeasureMode::Median(n)),
+            ("trimmed", Some(n)) => Ok(MeasureMode::TrimmedMean(n)),
+            ("adaptive", Some(n)) => Ok(MeasureMode::Adaptive(n)),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown measurement mode `{}`. Expected single, median:N, trimmed:N or adaptive:N",
+                s
+            ))),
+        }
+    }
+}
+
+/// Measurement modes for each phase of an attack
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeasurePhases {
+    /// Building of eviction sets
+    pub profiling: MeasureMode,
+    /// Location of the RX buffer
+    pub locating: MeasureMode,
+    /// Online tracking
+    pub tracking: MeasureMode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::sim::fixtures::trained_classifier;
+
+    // Measures with the given timings, returns the latency and the number of timings taken
+    fn measure(mode: MeasureMode, lats: &[Time]) -> (Time, usize) {
+        let classifier = trained_classifier();
+        let mut it = lats.iter();
+        let mut calls = 0;
+
+        let lat = mode
+            .measure(&classifier, || {
+                calls += 1;
+                Ok(*it.next().unwrap())
+            })
+            .unwrap();
+
+        (lat, calls)
+    }
+
+    #[test]
+    fn measure_modes() {
+        let (lat, _) = measure(MeasureMode::Median(3), &[300, 100, 100]);
+        assert_eq!(lat, 100, "Spike is not filtered by median");
+
+        let (lat, _) = measure(MeasureMode::TrimmedMean(4), &[100, 104, 108, 1000]);
+        assert_eq!(lat, 106, "Wrong trimmed mean");
+
+        let (_, calls) = measure(MeasureMode::Adaptive(5), &[110, 200, 300]);
+        assert_eq!(calls, 1, "Clear timing was repeated");
+
+        let (lat, calls) = measure(MeasureMode::Adaptive(5), &[200, 300, 310, 320, 330]);
+        assert_eq!(calls, 3, "Ambiguous timing was not repeated until clear");
+        assert_eq!(lat, 300, "Wrong adaptive timing");
+    }
+
+    #[test]
+    fn mode_parsing() {
+        for s in &["single", "median:5", "trimmed:8", "adaptive:3"] {
+            let mode: MeasureMode = s.parse().unwrap();
+            assert_eq!(&mode.to_string(), s, "Measurement mode roundtrip failed");
+        }
+
+        assert!("median".parse::<MeasureMode>().is_err(), "Missing count accepted");
+        assert!("single:2".parse::<MeasureMode>().is_err(), "Extra argument accepted");
+        assert!("mean:3".parse::<MeasureMode>().is_err(), "Unknown mode accepted");
+    }
+}
@@ -2,21 +2,37 @@
 //! This module is responsible for implementing PRIME+PROBE method of cache activity tracking.
 //! The method is described in _NetCAT: Practical Cache Attacks from the Network_.
 
+pub mod calibration;
+pub mod detect;
+pub mod measure;
 pub mod params;
-mod timing_classif;
+mod pool;
+pub mod presets;
+pub mod probe;
+pub mod strategy;
+pub mod timing_classif;
 
 use crate::connection::{Address, CacheConnector, Time};
+use crate::{Error, Result};
 use console::style;
 pub use params::*;
+use pool::AddrPool;
 use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
-use std::io::Result;
-use std::io::{Error, ErrorKind};
-use timing_classif::{CacheTiming, TimingClassifier};
+use std::collections::HashMap;
+pub use calibration::CalibrationReport;
+pub use measure::{MeasureMode, MeasurePhases};
+pub use probe::{ActivationRule, Probe};
+pub use strategy::{PrimeStrategy, ProbeStrategy};
+pub use timing_classif::{
+    Aging, ClassifierKind, ClassifierState, DriftConfig, LatencyClassifier, Level,
+};
+use timing_classif::CacheTiming;
 
 const TIMINGS_INIT_FILL: usize = 150;
 const TIMING_REFRESH_FILL: usize = 50;
 const RETRY_CNT: usize = 10;
+const MIN_TRAINING_ADDRS: usize = 500;
 const CTL_BIT: usize = 6; // 6 - 12 (lower bits - lower val)
 
 pub type Contents = u8;
@@ -25,8 +41,12 @@ pub type Contents = u8;
 macro_rules! median {
     ($blk:block) => {{
         const STABILIZE_CNT: usize = 10;
-        let mut vec = Vec::with_capacity(STABILIZE_CNT);
-        for _ in 0..STABILIZE_CNT {
+        $crate::median! {STABILIZE_CNT; $blk}
+    }};
+    ($cnt:expr; $blk:block) => {{
+        let cnt: usize = $cnt;
+        let mut vec = Vec::with_capacity(cnt);
+        for _ in 0..cnt {
             vec.push($blk);
         }
         vec.sort();
@@ -44,7 +64,9 @@ pub type ColorCode = usize;
 /// A custom code, representing one page color
 pub type ColoredSetCode = usize;
 
-#[derive(Copy, Clone, Default, Debug, PartialOrd, PartialEq, Eq, Ord, Serialize, Deserialize)]
+#[derive(
+    Copy, Clone, Default, Debug, PartialOrd, PartialEq, Eq, Ord, Hash, Serialize, Deserialize,
+)]
 pub struct SetCode(pub ColorCode, pub ColoredSetCode);
 
 type EvictionSets = Vec<EvictionSet>;
@@ -54,6 +76,15 @@ type EvictionSets = Vec<EvictionSet>;
 // for page offset
 type ColoredSets = Vec<EvictionSets>;
 
+/// State of the classifier after recalibration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Number of recorded hit and miss pairs
+    pub recorded: usize,
+    /// Share of misclassified training timings, if the classifier reports it
+    pub overlap: Option<f64>,
+}
+
 /// Probe results with wraped data
 #[derive(Clone, Debug, PartialOrd, PartialEq, Eq, Ord, Serialize, Deserialize)]
 pub enum ProbeResult<T> {
@@ -73,6 +104,27 @@ impl<T> ProbeResult<T> {
     pub fn is_stale(&self) -> bool {
         !self.is_activated()
     }
+
+    /// Returns the wrapped data
+    pub fn inner(&self) -> &T {
+        match self {
+            ProbeResult::Activated(x) | ProbeResult::Stale(x) => x,
+        }
+    }
+}
+
+impl ProbeResult<Probe> {
+    /// Confidence of the misses in the probe
+    #[inline(always)]
+    pub fn confidence(&self) -> f64 {
+        self.inner().confidence
+    }
+
+    /// Number of lines, classified as misses
+    #[inline(always)]
+    pub fn miss_cnt(&self) -> usize {
+        self.inner().miss_cnt
+    }
 }
 
 pub type Latencies = Vec<Time>;
@@ -83,8 +135,14 @@ pub struct Rpp<C> {
     params: RppParams,
     conn: C,
     colored_sets: ColoredSets,    // maps a color code to sets
-    addrs: Vec<Vec<Address>>, // adress pools for each of the values of bits 12-6 of virtual addresses
-    classifier: TimingClassifier, // we will be using this to dynamically scale threshold
+    addrs: Vec<AddrPool>,         // adress pools for each of the values of bits 12-6 of virtual addresses
+    classifier: Box<dyn LatencyClassifier>, // we will be using this to dynamically scale threshold
+    prime_strategy: PrimeStrategy,
+    probe_strategy: ProbeStrategy,
+    rule: ActivationRule,
+    last_pass: HashMap<SetCode, EvictionSet>, // the last priming pass of each set, if needed for probing
+    profiling_mode: MeasureMode,
+    probe_mode: MeasureMode,
     quite: bool,
 }
 
@@ -99,61 +157,195 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     /// Creates a new instance with the provided params and starts building eviction sets
     /// `quite` tells, whether the progress should be reported on the screen
     pub fn with_params(conn: C, quite: bool, cparams: CacheParams) -> Rpp<C> {
-        let classifier = TimingClassifier::new();
-        let params: RppParams = cparams.into();
+        Self::with_classifier(conn, quite, cparams, ClassifierKind::default().build())
+    }
 
-        // Fill in the address table (64 values of bits 12-6)
-        let mut addrs = Vec::with_capacity(64);
-        for i in 0..64 {
-            addrs.push(
-                (0usize..params.v_buf / PAGE_SIZE)
-                    .map(|x| (x * PAGE_SIZE) ^ (i << 6))
-                    .collect(),
-            );
+    /// Creates a new instance with the provided params and timing classifier and starts
+    /// building eviction sets. The classifier is trained while building sets.
+    /// `quite` tells, whether the progress should be reported on the screen
+    pub fn with_classifier(
+        conn: C,
+        quite: bool,
+        cparams: CacheParams,
+        classifier: Box<dyn LatencyClassifier>,
+    ) -> Rpp<C> {
+        Self::with_profiling(conn, quite, cparams, classifier, MeasureMode::default())
+    }
+
+    /// Creates a new instance with the provided params and timing classifier and starts
+    /// building eviction sets. Each eviction test is measured according to `profiling`.
+    /// `quite` tells, whether the progress should be reported on the screen
+    pub fn with_profiling(
+        conn: C,
+        quite: bool,
+        cparams: CacheParams,
+        classifier: Box<dyn LatencyClassifier>,
+        profiling: MeasureMode,
+    ) -> Rpp<C> {
+        let mut rpp = Self::unprofiled(conn, quite, cparams, classifier, profiling);
+        rpp.build_sets();
+
+        rpp
+    }
+
+    /// Creates a new instance with the eviction sets, built earlier for the same
+    /// connection and cache parameters, under the codes they were built with. The codes
+    /// without a set are left empty. The sets are not checked, but the classifier is
+    /// trained on the lines, which are not a part of any of them.
+    /// `quite` tells, whether the progress should be reported on the screen
+    pub fn with_sets(
+        conn: C,
+        quite: bool,
+        cparams: CacheParams,
+        classifier: Box<dyn LatencyClassifier>,
+        profiling: MeasureMode,
+        sets: Vec<(SetCode, EvictionSet)>,
+    ) -> Rpp<C> {
+        let mut rpp = Self::with_trained_sets(conn, quite, cparams, classifier, profiling, sets);
+        rpp.train_classifier(TIMINGS_INIT_FILL);
+
+        rpp
+    }
+
+    /// Same as `with_sets`, but the classifier is taken as already trained,
+    /// e.g. restored from a `ClassifierState`
+    pub fn with_trained_sets(
+        conn: C,
+        quite: bool,
+        cparams: CacheParams,
+        classifier: Box<dyn LatencyClassifier>,
+        profiling: MeasureMode,
+        sets: Vec<(SetCode, EvictionSet)>,
+    ) -> Rpp<C> {
+        let mut rpp = Self::unprofiled(conn, quite, cparams, classifier, profiling);
+        rpp.conn.reserve(rpp.params.v_buf);
+
+        for (SetCode(color, idx), set) in sets.into_iter().filter(|(_, set)| !set.is_empty()) {
+            // all lines of a set share the page offset, thus belong to the same pool
+            let pool = (set[0] % PAGE_SIZE) >> CTL_BIT;
+            rpp.remove_used_addrs(&set, pool);
+
+            if rpp.colored_sets.len() <= color {
+                rpp.colored_sets.resize(color + 1, EvictionSets::new());
+            }
+            let color_sets = &mut rpp.colored_sets[color];
+            if color_sets.len() <= idx {
+                color_sets.resize(idx + 1, EvictionSet::new());
+            }
+            color_sets[idx] = set;
         }
 
-        let mut rpp = Rpp {
+        rpp
+    }
+
+    fn unprofiled(
+        conn: C,
+        quite: bool,
+        cparams: CacheParams,
+        classifier: Box<dyn LatencyClassifier>,
+        profiling: MeasureMode,
+    ) -> Rpp<C> {
+        let params: RppParams = cparams.into();
+
+        Rpp {
             colored_sets: ColoredSets::with_capacity(params.n_colors),
             conn,
-            addrs, // here we collect page aligned (e.i. at the begining of the page) addresses
+            addrs: Self::fresh_addrs(&params), // here we collect page aligned (e.i. at the begining of the page) addresses
             classifier,
+            prime_strategy: Default::default(),
+            probe_strategy: Default::default(),
+            rule: Default::default(),
+            last_pass: HashMap::new(),
+            profiling_mode: profiling,
+            probe_mode: Default::default(),
             quite,
             params,
-        };
-        rpp.build_sets();
+        }
+    }
 
-        rpp
+    // Fill in the address table (64 values of bits 12-6)
+    fn fresh_addrs(params: &RppParams) -> Vec<AddrPool> {
+        (0..64)
+            .map(|i| AddrPool::new(params.v_buf / PAGE_SIZE, i << CTL_BIT))
+            .collect()
+    }
+
+    /// Drops all eviction sets and builds them from scratch
+    pub fn rebuild_sets(&mut self) {
+        self.colored_sets.clear();
+        self.last_pass.clear();
+        self.addrs = Self::fresh_addrs(&self.params);
+        self.build_sets();
+    }
+
+    /// Sets the order, in which the lines are accessed while priming
+    pub fn set_prime_strategy(&mut self, strategy: PrimeStrategy) {
+        self.prime_strategy = strategy;
+    }
+
+    /// Sets the order, in which the lines are timed while probing
+    pub fn set_probe_strategy(&mut self, strategy: ProbeStrategy) {
+        self.probe_strategy = strategy;
+        self.last_pass.clear();
+    }
+
+    /// Sets the rule, which decides whether a probe shows an activation
+    pub fn set_activation_rule(&mut self, rule: ActivationRule) {
+        self.rule = rule;
+    }
+
+    /// Sets, how each line is timed while probing
+    pub fn set_probe_mode(&mut self, mode: MeasureMode) {
+        self.probe_mode = mode;
     }
 
     /// Primes the given set of addresses
     pub fn prime(&mut self, set_code: &SetCode) -> Result<()> {
-        self.conn
-            .cache_all(self.colored_sets[set_code.0][set_code.1].iter().copied())
+        let set = &self.colored_sets[set_code.0][set_code.1];
+        let order = self.prime_strategy.order(set, &mut rand::thread_rng());
+
+        self.conn.cache_all(order.iter().copied())?;
+
+        if self.probe_strategy.needs_prime_order() {
+            let mut last_pass: Vec<Address> = order.iter().rev().take(set.len()).copied().collect();
+            last_pass.reverse();
+            self.last_pass.insert(*set_code, last_pass);
+        }
+
+        Ok(())
     }
 
-    /// Probes the given set of addresses. Returns true if a set activation detected
-    /// Returns `Activated(lats)`, where `lats` is a vector of latencies for addresses in
-    /// the given set, if the cache activation has been measured, or `Stale(lats)` otherwise
-    pub fn probe(&mut self, set_code: &SetCode) -> Result<ProbeResult<Latencies>> {
+    /// Probes the given set of addresses.
+    /// Returns `Activated(probe)`, where `probe` holds latencies for addresses in the given set
+    /// and their classification, if the activation rule holds for them, or `Stale(probe)` otherwise.
+    /// Latencies are given in the order of the lines in the set, whatever the order they were
+    /// timed in. Lines with invalid timings are skipped and do not count towards an activation.
+    pub fn probe(&mut self, set_code: &SetCode) -> Result<ProbeResult<Probe>> {
         use ProbeResult::*;
 
-        let set: Vec<usize> = self.colored_sets[set_code.0][set_code.1]
-            .iter()
-            .copied()
-            .collect();
-        let lats = set
-            .into_iter()
-            .map(|x| self.conn.time_access(x))
-            .collect::<Result<Vec<Time>>>()?;
+        let set = &self.colored_sets[set_code.0][set_code.1];
+        // if the set was not primed, then the default order is the last pass
+        let last_pass = self.last_pass.get(set_code).unwrap_or(set);
+        let order = self
+            .probe_strategy
+            .order(set, last_pass, &mut rand::thread_rng());
+
+        let (conn, classifier, mode) = (&mut self.conn, self.classifier.as_ref(), self.probe_mode);
+        // each strategy times a permutation of the first lines of the set
+        let mut lats: Vec<Time> = vec![0; order.len()];
+        for x in order {
+            let idx = set.iter().position(|&line| line == x).expect("Timed line is not in the set");
+            lats[idx] = mode.measure(classifier, || conn.time_access(x))?;
+        }
 
-        let miss = lats.iter().find(|&&lat| self.classifier.is_miss(lat));
+        let probe = Probe::classify(lats, self.classifier.as_ref());
 
         // We test whether an activation
-        if miss.is_some() {
-            return Ok(Activated(lats));
+        if self.rule.is_activation(&probe) {
+            return Ok(Activated(probe));
         }
 
-        Ok(Stale(lats))
+        Ok(Stale(probe))
     }
 
     /// Primes all sets in a vector
@@ -162,39 +354,70 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     }
 
     /// Probes all sets in a vector
-    pub fn probe_all(&mut self, set_codes: &[SetCode]) -> Result<Vec<ProbeResult<Latencies>>> {
+    pub fn probe_all(&mut self, set_codes: &[SetCode]) -> Result<Vec<ProbeResult<Probe>>> {
         set_codes.iter().map(|x| self.probe(x)).collect()
     }
 
     fn train_classifier(&mut self, sampls_num: usize) {
-        // we assume that the memory region is not cached
+        self.calibrate(sampls_num)
+            .expect("Failed to time memory access while training");
+    }
+
+    /// Retrains the classifier on the lines, which are not a part of any eviction set,
+    /// to follow the latency drift. Returns the state of the classifier after retraining.
+    /// Eviction sets should be primed again afterwards.
+    pub fn recalibrate(&mut self, sampls_num: usize) -> Result<Calibration> {
+        let recorded = self.calibrate(sampls_num)?;
+
+        Ok(Calibration {
+            recorded,
+            overlap: self.classifier.overlap(),
+        })
+    }
+
+    /// Saved state of the trained classifier, if it supports saving
+    pub fn classifier_state(&self) -> Option<ClassifierState> {
+        self.classifier.state()
+    }
+
+    // Congruent addresses are cleaned up only from the first pool, thus the largest one is taken.
+    fn calibration_pool(addrs: &[AddrPool]) -> &AddrPool {
+        addrs
+            .iter()
+            .max_by_key(|pool| pool.len())
+            .expect("No address pools")
+    }
+
+    // Records pairs of timings for known flushed and known cached lines.
+    // Returns the number of recorded pairs.
+    fn calibrate(&mut self, sampls_num: usize) -> Result<usize> {
         let mut rng = rand::thread_rng();
+        let mut recorded = 0;
+
+        // Addresses from the pool are not used by eviction sets, thus are expected to be
+        // out of the cache. If the connection can flush lines, then we know it for sure.
+        let pool = Self::calibration_pool(&self.addrs);
+        for &ofs in pool.as_slice().choose_multiple(&mut rng, sampls_num) {
+            self.conn.flush(ofs)?;
 
-        for &ofs in self.addrs[0]
-            .as_slice()
-            .choose_multiple(&mut rng, sampls_num)
-        {
             // here we read from the main memory
-            let miss_time = self
-                .conn
-                .time_access(ofs)
-                .expect("Failed to time memory access");
+            let miss_time = self.conn.time_access(ofs)?;
 
             // here we cache the address and read again from cache
-            self.conn
-                .cache(ofs)
-                .expect("Failed to cache addr in while training");
-            let hit_time = self
-                .conn
-                .time_access(ofs)
-                .expect("Failed to time cache access");
+            self.conn.cache(ofs)?;
+            let hit_time = self.conn.time_access(ofs)?;
 
             // we expect the latency from main memory to be bigger that from LLC
             if hit_time < miss_time {
                 self.classifier.record(CacheTiming::Hit(hit_time));
                 self.classifier.record(CacheTiming::Miss(miss_time));
+                recorded += 1;
             }
         }
+
+        self.classifier.fit();
+
+        Ok(recorded)
     }
 
     fn build_sets(&mut self) {
@@ -237,9 +460,8 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
                 }
                 Err(e) => {
                     if !self.quite {
-                        match e.kind() {
-                            ErrorKind::UnexpectedEof => panic!("{}", style(e).red()),
-                            ErrorKind::InvalidInput => (),
+                        match e {
+                            Error::AddrsExhausted { .. } => panic!("{}", style(e).red()),
                             _ => pb.set_message(style(e).red().to_string().as_str()),
                         }
                     }
@@ -247,7 +469,7 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
             }
 
             // stop training if the num of addrs is too small
-            if self.addrs.len() > 500 {
+            if Self::calibration_pool(&self.addrs).len() > MIN_TRAINING_ADDRS {
                 self.train_classifier(TIMING_REFRESH_FILL);
             }
         }
@@ -258,28 +480,48 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     }
 
     /// Checks, whether the given set evicts an address
-    fn check_evicts<I: Iterator<Item = Address>>(&mut self, set: I, addr: Address) -> Result<bool> {
-        // bring `addr` into cache
-        self.conn.cache(addr)?;
-
-        // bring addrs from the `set` into cache, which shold cause eviction of addr
-        self.conn.cache_all(set)?;
+    fn check_evicts<I>(&mut self, set: I, addr: Address) -> Result<bool>
+    where
+        I: Iterator<Item = Address> + Clone,
+    {
+        Self::evicts(&mut self.conn, self.classifier.as_ref(), self.profiling_mode, set, addr)
+    }
 
-        // time access to `addr`
-        let lat = self.conn.time_access(addr)?;
+    // Takes the fields separately, so that the address pools can be iterated in place
+    fn evicts<I>(
+        conn: &mut C,
+        classifier: &dyn LatencyClassifier,
+        mode: MeasureMode,
+        set: I,
+        addr: Address,
+    ) -> Result<bool>
+    where
+        I: Iterator<Item = Address> + Clone,
+    {
+        // the whole test is repeated, if the mode requires
+        let lat = mode.measure(classifier, || {
+            // bring `addr` into cache
+            conn.cache(addr)?;
+
+            // bring addrs from the `set` into cache, which shold cause eviction of addr
+            conn.cache_all(set.clone())?;
+
+            // time access to `addr`
+            conn.time_access(addr)
+        })?;
 
         // it should be a miss
-        if self.classifier.is_miss(lat) {
-            return Ok(true);
-        }
-
-        Ok(false)
+        Ok(classifier.is_miss(lat))
     }
 
     fn build_initial_set(&mut self) -> Result<EvictionSet> {
-        let addr = *self.addrs[0]
+        let addr = self.addrs[0]
             .choose(&mut rand::thread_rng())
-            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "ERROR: No addrs left"))?;
+            .ok_or(Error::AddrsExhausted {
+                pool: 0,
+                left: 0,
+                needed: 1,
+            })?;
         let set = self.build_set_for_idx_addr(0, addr)?;
         self.cleanup_congruent(&set, 0)?;
         Ok(set)
@@ -315,10 +557,7 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
                 break;
             }
             if sets.len() != i + 1 {
-                return Err(Error::new(
-                    ErrorKind::NotFound,
-                    "Error: could not derive sets",
-                ));
+                return Err(Error::SetNotBuilt("could not derive sets"));
             }
         }
 
@@ -364,7 +603,7 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
         let idx = set
             .iter()
             .next()
-            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Eviction set is empty"))?
+            .ok_or(Error::SetNotBuilt("the eviction set is empty"))?
             & (0b111111 << CTL_BIT);
 
         // We need to check sets from vector inside `colored_sets` vector with the
@@ -404,15 +643,25 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
         let total_addrs = self.addrs[idx].len();
 
         if total_addrs < self.params.n_lines + 1 {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "ERROR: No addrs left"));
+            return Err(Error::AddrsExhausted {
+                pool: idx,
+                left: total_addrs,
+                needed: self.params.n_lines + 1,
+            });
         }
         let mut n = std::cmp::max(total_addrs / 10, self.params.n_lines + 1);
 
         while n <= total_addrs {
-            let sub_set: Vec<Address> = self.addrs[idx][..n - 1].to_vec();
-
-            if self.check_evicts(sub_set.iter().copied(), addr)? {
-                return Ok(sub_set);
+            let sub_set = &self.addrs[idx].as_slice()[..n - 1];
+
+            if Self::evicts(
+                &mut self.conn,
+                self.classifier.as_ref(),
+                self.profiling_mode,
+                sub_set.iter().copied(),
+                addr,
+            )? {
+                return Ok(sub_set.to_vec());
             }
 
             n += 1;
@@ -420,19 +669,15 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
         
         // Here we excided the number of addrs, but registered no eviction
         // We remove the faulty address not to cause more issues
-        Err(Error::new(
-            ErrorKind::Other,
-            "ERROR: cannot build set for the chosen address.",
-        ))
+        Err(Error::SetNotBuilt("no set evicts the chosen address"))
     }
 
     // Here we assume that set `s` truly evicts address `x`
     // here we use the approach proposed by Vila et al.
     fn backward_selection(&mut self, s: &mut EvictionSet, x: Address) -> Result<()> {
         if s.len() < self.params.n_lines {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "ERROR: the initial set for backwards selection is too narrow.",
+            return Err(Error::SetNotBuilt(
+                "the initial set for backwards selection is too narrow",
             ));
         }
         if s.len() == self.params.n_lines {
@@ -481,25 +726,26 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     #[inline(always)]
     fn remove_used_addrs(&mut self, s: &[Address], idx: usize) {
         // Remove addrs in set `S` from global addr pool
-        self.addrs[idx].retain(|x| !s.contains(x));
+        self.addrs[idx].remove_all(s);
     }
 
     fn cleanup_congruent(&mut self, s: &[Address], idx: usize) -> Result<()> {
-        // We will be iterating over the set and removing from it. Rust does not allow that, thus making a copy
-        let addrs: Vec<usize> = self.addrs[idx].clone();
-
-        // We will have to manually update index to take removed values into account.
-        let mut i = 0;
-        for x in addrs {
+        // First we collect all of the addresses, evicted by our new cache set,
+        // and then remove them from the pool at once
+        let mut congruent = Vec::new();
+        for &x in self.addrs[idx].as_slice() {
             // If `x` is evicted by our new cache set, then we do not need it anymore
-            if self.check_evicts(s.iter().copied(), x)? {
-                // Remove value at index. We do not need to update `i` as the next val will have the same
-                // index as we removed the current.
-                self.addrs[idx].remove(i);
-            } else {
-                i += 1;
+            if Self::evicts(
+                &mut self.conn,
+                self.classifier.as_ref(),
+                self.profiling_mode,
+                s.iter().copied(),
+                x,
+            )? {
+                congruent.push(x);
             }
         }
+        self.addrs[idx].remove_all(&congruent);
 
         Ok(())
     }
@@ -528,6 +774,11 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     ) -> impl Iterator<Item = ColoredSetCode> + 'a {
         0..self.colored_sets[color_code].len()
     }
+    /// Returns the eviction set for the given `SetCode`
+    pub fn eviction_set(&self, set_code: &SetCode) -> &EvictionSet {
+        &self.colored_sets[set_code.0][set_code.1]
+    }
+
     /// Returns an iterator over all proviled `SetCode`s
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = SetCode> + 'a {
         self.colored_sets
@@ -541,7 +792,8 @@ impl<C: CacheConnector<Item = Contents>> Rpp<C> {
     }
 }
 
-/// Test whether an activation has been observed in the provided Probe Results
+/// Test whether an activation has been observed in the provided Probe Results.
+/// Probes are labeled as activated by the `ActivationRule`, set for the `Rpp`.
 #[inline(always)]
 pub fn has_activation<T>(probes: &[ProbeResult<T>]) -> bool {
     probes.iter().any(ProbeResult::is_activated)
@@ -549,12 +801,168 @@ pub fn has_activation<T>(probes: &[ProbeResult<T>]) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::connection::sim::{fixtures, SimulatedCacheConnector};
+
     #[test]
     fn new_rpp_test() {
         let conn = crate::connection::local::LocalMemoryConnector::new();
         super::Rpp::new(conn, false);
     }
 
+    #[test]
+    fn sim_rpp_test() {
+        // 256 sets of 4 lines, which gives 4 colors
+        let cparams = fixtures::cache_params();
+        let conn = SimulatedCacheConnector::new(&cparams);
+        let rpp = Rpp::with_params(conn, true, cparams);
+
+        assert_eq!(rpp.colors_len(), 4, "Wrong number of colors");
+        for set_code in rpp.iter() {
+            let set = &rpp.colored_sets[set_code.0][set_code.1];
+            assert_eq!(set.len(), 4, "Wrong eviction set length");
+
+            let cache_set = |addr: &Address| (addr / 64) % 256;
+            assert!(
+                set.iter().all(|x| cache_set(x) == cache_set(&set[0])),
+                "Addresses of {:?} are not congruent",
+                set_code
+            );
+        }
+    }
+
+    #[test]
+    fn sim_saved_sets_test() {
+        let cparams = fixtures::cache_params();
+        let built = Rpp::with_params(SimulatedCacheConnector::new(&cparams), true, cparams);
+        let set_code = SetCode(2, 5);
+        let saved = built.eviction_set(&set_code).clone();
+
+        let conn = SimulatedCacheConnector::new(&cparams);
+        let classifier = ClassifierKind::default().build();
+        let sets = vec![(set_code, saved.clone())];
+        let mut rpp = Rpp::with_sets(conn, true, cparams, classifier, MeasureMode::Single, sets);
+        assert_eq!(rpp.colors_len(), 3, "Wrong number of colors");
+        assert_eq!(rpp.eviction_set(&set_code), &saved, "Wrong eviction set");
+        assert!(
+            rpp.addrs.iter().all(|pool| saved.iter().all(|x| !pool.as_slice().contains(x))),
+            "Lines of the saved set are left in the pools"
+        );
+
+        rpp.prime(&set_code).unwrap();
+        assert!(rpp.probe(&set_code).unwrap().is_stale(), "False activation");
+
+        // a congruent line, which is not a part of the set, evicts one of its lines
+        let cache_set = |addr: Address| (addr / 64) % 256;
+        let target = cache_set(saved[0]);
+        let congruent = rpp
+            .addrs
+            .iter()
+            .flat_map(|pool| pool.as_slice().iter().copied())
+            .find(|&x| cache_set(x) == target)
+            .unwrap();
+        rpp.prime(&set_code).unwrap();
+        rpp.conn.cache(congruent).unwrap();
+        assert!(rpp.probe(&set_code).unwrap().is_activated(), "Activation is not detected");
+    }
+
+    #[test]
+    fn sim_strategies_test() {
+        let cparams = fixtures::cache_params();
+        let conn = SimulatedCacheConnector::new(&cparams);
+        let mut rpp = Rpp::with_params(conn, true, cparams);
+        let set_code = SetCode(0, 0);
+
+        for &(prime, probe) in &[
+            (PrimeStrategy::Sequential, ProbeStrategy::Sequential),
+            (PrimeStrategy::ZigZag(2), ProbeStrategy::ReversePrime),
+            (PrimeStrategy::Random(3), ProbeStrategy::ReversePrime),
+            (PrimeStrategy::Repeated(2), ProbeStrategy::Random),
+        ] {
+            rpp.set_prime_strategy(prime);
+            rpp.set_probe_strategy(probe);
+            rpp.prime(&set_code).unwrap();
+            let res = rpp.probe(&set_code).unwrap();
+            assert!(res.is_stale(), "False activation with {} / {}", prime, probe);
+        }
+
+        rpp.set_probe_strategy(ProbeStrategy::FirstK(2));
+        rpp.prime(&set_code).unwrap();
+        match rpp.probe(&set_code).unwrap() {
+            ProbeResult::Stale(probe) => {
+                assert_eq!(probe.lats.len(), 2, "Wrong number of timed lines")
+            }
+            ProbeResult::Activated(_) => panic!("False activation"),
+        }
+
+        // the latencies stay at the lines, whatever order they are timed in
+        let last = rpp.eviction_set(&set_code).len() - 1;
+        for &probe in &[ProbeStrategy::ReversePrime, ProbeStrategy::Random] {
+            rpp.set_probe_strategy(probe);
+            rpp.prime(&set_code).unwrap();
+            let line = rpp.eviction_set(&set_code)[last];
+            rpp.conn.flush(line).unwrap();
+            let res = rpp.probe(&set_code).unwrap();
+            let misses: Vec<usize> = (0..=last).filter(|&i| res.inner().misses[i]).collect();
+            assert_eq!(misses, vec![last], "Latency is not at its line with {}", probe);
+        }
+    }
+
+    #[test]
+    fn sim_recalibration_test() {
+        let cparams = fixtures::cache_params();
+        let conn = SimulatedCacheConnector::new(&cparams);
+        let classifier = ClassifierKind::Centroid.build_aged(Aging::Window(100));
+        let mut rpp = Rpp::with_classifier(conn, true, cparams, classifier);
+
+        let calibration = rpp.recalibrate(20).unwrap();
+        assert_eq!(calibration.recorded, 20, "Flushed lines should always be misses");
+        assert_eq!(calibration.overlap, Some(0.0), "Simulated timings overlap");
+
+        // the network got slower, and hits are now as slow as misses used to be
+        rpp.conn.set_latencies(300, 500, 10);
+        for _ in 0..5 {
+            rpp.recalibrate(20).unwrap();
+        }
+        rpp.prime(&SetCode(0, 0)).unwrap();
+        assert!(
+            rpp.probe(&SetCode(0, 0)).unwrap().is_stale(),
+            "The classifier did not follow the drift"
+        );
+    }
+
+    #[test]
+    fn sim_noise_filtering_test() {
+        let cparams = fixtures::cache_params();
+        let mut conn = SimulatedCacheConnector::new(&cparams);
+        conn.set_reads_allocate(false);
+        let classifier = ClassifierKind::default().build();
+        let mut rpp = Rpp::with_profiling(conn, true, cparams, classifier, MeasureMode::Median(3));
+        let set_code = SetCode(0, 0);
+
+        // one in ten timings is delayed by a retransmission
+        rpp.conn.set_spikes(0.1, 400);
+        let false_activations = |rpp: &mut Rpp<SimulatedCacheConnector>, mode| {
+            rpp.set_probe_mode(mode);
+            (0..50)
+                .filter(|_| {
+                    rpp.prime(&set_code).unwrap();
+                    rpp.probe(&set_code).unwrap().is_activated()
+                })
+                .count()
+        };
+        let single = false_activations(&mut rpp, MeasureMode::Single);
+        let median = false_activations(&mut rpp, MeasureMode::Median(5));
+        assert!(single > 0, "Spikes did not cause false activations");
+        assert!(median < single, "Median did not filter spikes");
+
+        // real activations are still seen
+        rpp.prime(&set_code).unwrap();
+        let line = rpp.colored_sets[0][0][0];
+        rpp.conn.flush(line).unwrap();
+        assert!(rpp.probe(&set_code).unwrap().is_activated(), "Activation is lost");
+    }
+
     #[test]
     fn macro_test() {
         let med = median! {
@@ -1,3 +1,11 @@
+use super::detect::DetectedCache;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
 pub const PAGE_SIZE: usize = 4096; // 4 KiB
 
 pub static XEON_E5: CacheParams = CacheParams {
@@ -49,9 +57,71 @@ pub static XEON_PLATINUM_DDIO: CacheParams = CacheParams {
     addr_num: 5000,
 };
 
+/// Describes, why the given cache parameters cannot be used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamsError {
+    /// The named parameter is zero
+    Zero(&'static str),
+    /// Cache lines should be a power of two, which is not larger than a page
+    BadLineSize(usize),
+    /// More lines are reachable than there are in a set
+    TooManyReachable { reachable: usize, lines_per_set: usize },
+    /// The cache size is not a multiple of the set size
+    SizeNotDivisible { cache_size: usize, set_size: usize },
+    /// The sets cannot be split evenly into page colors
+    SetsNotDivisible { sets: usize, sets_per_page: usize },
+    /// The pages cannot contain enough congruent addresses for every color
+    TooFewAddrs { addr_num: usize, required: usize },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Zero(name) => write!(f, "`{}` should not be zero", name),
+            Self::BadLineSize(line) => write!(
+                f,
+                "line size {} is not a power of two up to {}",
+                line, PAGE_SIZE
+            ),
+            Self::TooManyReachable {
+                reachable,
+                lines_per_set,
+            } => write!(
+                f,
+                "{} reachable lines do not fit into a {}-way set",
+                reachable, lines_per_set
+            ),
+            Self::SizeNotDivisible {
+                cache_size,
+                set_size,
+            } => write!(
+                f,
+                "cache size {} is not a multiple of the set size {}",
+                cache_size, set_size
+            ),
+            Self::SetsNotDivisible {
+                sets,
+                sets_per_page,
+            } => write!(
+                f,
+                "{} sets cannot be split into colors of {} sets",
+                sets, sets_per_page
+            ),
+            Self::TooFewAddrs { addr_num, required } => write!(
+                f,
+                "{} pages cannot hold enough congruent addresses, need at least {}",
+                addr_num, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
 /// Parameters for Remote PRIME+PROBE.
 /// Describes the last level cache of the targeted prosessor
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawCacheParams")]
 pub struct CacheParams {
     bytes_per_line: usize,
     lines_per_set: usize,
@@ -60,15 +130,180 @@ pub struct CacheParams {
     addr_num: usize,
 }
 
+// Unvalidated form of `CacheParams`, used for deserialization
+#[derive(Deserialize)]
+struct RawCacheParams {
+    bytes_per_line: usize,
+    lines_per_set: usize,
+    reachable_lines: usize,
+    cache_size: usize,
+    addr_num: usize,
+}
+
+impl TryFrom<RawCacheParams> for CacheParams {
+    type Error = ParamsError;
+
+    fn try_from(raw: RawCacheParams) -> std::result::Result<Self, Self::Error> {
+        CacheParams::new(
+            raw.bytes_per_line,
+            raw.lines_per_set,
+            raw.reachable_lines,
+            raw.cache_size,
+            raw.addr_num,
+        )
+    }
+}
+
 impl CacheParams {
-    /// `addr_num` - number of adresses needed for successfull building of cache sets
-    pub fn new(bytes_per_line: usize, lines_per_set: usize, reachable_lines: usize, cache_size: usize, addr_num: usize) -> CacheParams {
-        CacheParams {
+    /// `addr_num` - number of adresses needed for successfull building of cache sets.
+    /// Fails if the parameters cannot describe a cache. Whether `addr_num` pages are
+    /// enough is only warned about in the `report`, see `check_addrs`.
+    pub fn new(
+        bytes_per_line: usize,
+        lines_per_set: usize,
+        reachable_lines: usize,
+        cache_size: usize,
+        addr_num: usize,
+    ) -> std::result::Result<CacheParams, ParamsError> {
+        let cparams = CacheParams {
             bytes_per_line,
             lines_per_set,
             reachable_lines,
             cache_size,
             addr_num,
+        };
+        cparams.validate()?;
+
+        Ok(cparams)
+    }
+
+    /// Checks the invariants, which `RppParams` rely on
+    pub fn validate(&self) -> std::result::Result<(), ParamsError> {
+        for &(name, val) in &[
+            ("bytes_per_line", self.bytes_per_line),
+            ("lines_per_set", self.lines_per_set),
+            ("reachable_lines", self.reachable_lines),
+            ("cache_size", self.cache_size),
+            ("addr_num", self.addr_num),
+        ] {
+            if val == 0 {
+                return Err(ParamsError::Zero(name));
+            }
+        }
+
+        if !self.bytes_per_line.is_power_of_two() || self.bytes_per_line > PAGE_SIZE {
+            return Err(ParamsError::BadLineSize(self.bytes_per_line));
+        }
+
+        if self.reachable_lines > self.lines_per_set {
+            return Err(ParamsError::TooManyReachable {
+                reachable: self.reachable_lines,
+                lines_per_set: self.lines_per_set,
+            });
+        }
+
+        let set_size = self.lines_per_set * self.bytes_per_line;
+        if self.cache_size % set_size != 0 {
+            return Err(ParamsError::SizeNotDivisible {
+                cache_size: self.cache_size,
+                set_size,
+            });
+        }
+
+        let sets = self.cache_size / set_size;
+        let sets_per_page = PAGE_SIZE / self.bytes_per_line;
+        if sets < sets_per_page || sets % sets_per_page != 0 {
+            return Err(ParamsError::SetsNotDivisible {
+                sets,
+                sets_per_page,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks, whether `addr_num` pages are expected to hold enough congruent addresses
+    /// to build eviction sets for every color. The addresses are spread among colors
+    /// by the physical mapping of the pages, thus it is only an estimate.
+    pub fn check_addrs(&self) -> std::result::Result<(), ParamsError> {
+        let p: RppParams = (*self).into();
+
+        // Every page gives one address per page offset. Those spread evenly among
+        // colors, and we need one more congruent address than an eviction set holds.
+        let required = p.n_colors * (self.reachable_lines + 1);
+        if self.addr_num < required {
+            return Err(ParamsError::TooFewAddrs {
+                addr_num: self.addr_num,
+                required,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Size of a cache line in bytes
+    pub fn bytes_per_line(&self) -> usize {
+        self.bytes_per_line
+    }
+
+    /// Associativity of the cache
+    pub fn lines_per_set(&self) -> usize {
+        self.lines_per_set
+    }
+
+    /// Number of lines in a set, which can be reached (e.g. via DDIO)
+    pub fn reachable_lines(&self) -> usize {
+        self.reachable_lines
+    }
+
+    /// Size of the cache in bytes
+    pub fn cache_size(&self) -> usize {
+        self.cache_size
+    }
+
+    /// Number of pages to build eviction sets from
+    pub fn addr_num(&self) -> usize {
+        self.addr_num
+    }
+
+    /// Returns a summary of the values, derived from these parameters
+    pub fn report(&self) -> ParamsReport {
+        let p: RppParams = (*self).into();
+
+        ParamsReport {
+            sets: p.n_sets,
+            sets_per_page: p.n_sets_per_page,
+            colors: p.n_colors,
+            eviction_set_len: p.n_lines,
+            buf_size: p.v_buf,
+            addrs_per_set: self.addr_num / std::cmp::max(p.n_colors, 1),
+            warning: self.check_addrs().err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Parses cache parameters from a TOML document
+    pub fn from_toml(s: &str) -> Result<CacheParams> {
+        toml::from_str(s).map_err(|e| {
+            Error::InvalidData(format!("invalid cache description: {}", e))
+        })
+    }
+
+    /// Parses cache parameters from a JSON document
+    pub fn from_json(s: &str) -> Result<CacheParams> {
+        serde_json::from_str(s).map_err(|e| {
+            Error::InvalidData(format!("invalid cache description: {}", e))
+        })
+    }
+
+    /// Loads cache parameters from a file. Files with `.json` extension are
+    /// parsed as JSON, everything else is expected to be TOML.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<CacheParams> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            _ => Self::from_toml(&contents),
         }
     }
 }
@@ -79,6 +314,62 @@ impl Default for CacheParams {
     }
 }
 
+/// Sanity report on the values, derived from `CacheParams`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamsReport {
+    /// Total number of cache sets
+    pub sets: usize,
+    /// Number of sets, covered by one page
+    pub sets_per_page: usize,
+    /// Number of page colors
+    pub colors: usize,
+    /// Number of addresses in one eviction set
+    pub eviction_set_len: usize,
+    /// Size of the buffer in bytes, which the victim has to expose
+    pub buf_size: usize,
+    /// Expected number of congruent addresses for each set
+    pub addrs_per_set: usize,
+    /// Why the eviction sets are likely to fail to build, if they are
+    pub warning: Option<String>,
+}
+
+impl fmt::Display for ParamsReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Cache sets:             {}", self.sets)?;
+        writeln!(f, "Sets per page:          {}", self.sets_per_page)?;
+        writeln!(f, "Colors:                 {}", self.colors)?;
+        writeln!(f, "Eviction set length:    {}", self.eviction_set_len)?;
+        writeln!(f, "Buffer size:            {} bytes", self.buf_size)?;
+        write!(f, "Addresses per set:      {}", self.addrs_per_set)?;
+        if let Some(warning) = &self.warning {
+            write!(f, "\nWarning: {}", warning)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tells, where the `CacheParams` of a session come from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheSource {
+    /// One of the parameter sets, compiled into the crate
+    Builtin,
+    /// A preset from the bundled database with the given name
+    Preset(String),
+    /// A file with the given path
+    File(String),
+    /// Given by the user directly
+    Custom,
+    /// Detected on the local machine
+    Detected(DetectedCache),
+}
+
+impl Default for CacheSource {
+    fn default() -> Self {
+        CacheSource::Builtin
+    }
+}
+
 #[derive(Clone, Default)]
 pub(super) struct RppParams {
     // number of lines per eviction set
@@ -148,4 +439,103 @@ mod tests {
         assert_eq!(rpp_params.n_sets_per_page, 64, "Number of sets is wrong");
         assert_eq!(rpp_params.n_colors, 256, "Number of sets is wrong");
     }
+
+    #[test]
+    fn params_from_toml() {
+        let toml = r#"
+            bytes_per_line = 64
+            lines_per_set = 12
+            reachable_lines = 12
+            cache_size = 6291456
+            addr_num = 5000
+        "#;
+        let cache_params = CacheParams::from_toml(toml).expect("Failed to parse TOML");
+
+        assert_eq!(cache_params, CORE_I7, "Parsed params are wrong");
+
+        let invalid = toml.replace("reachable_lines = 12", "reachable_lines = 13");
+        assert!(
+            CacheParams::from_toml(&invalid).is_err(),
+            "Invalid params were accepted"
+        );
+    }
+
+    #[test]
+    fn builtin_params_valid() {
+        for cache_params in &[
+            XEON_E5,
+            XEON_E5_DDIO,
+            CORE_I7,
+            CORE_I7_DDIO,
+            XEON_PLATINUM,
+            XEON_PLATINUM_DDIO,
+        ] {
+            assert_eq!(cache_params.validate(), Ok(()), "{:?} is invalid", cache_params);
+        }
+    }
+
+    #[test]
+    fn params_validation() {
+        assert_eq!(
+            CacheParams::new(64, 12, 0, 6_291_456, 5000),
+            Err(ParamsError::Zero("reachable_lines"))
+        );
+        assert_eq!(
+            CacheParams::new(48, 12, 12, 6_291_456, 5000),
+            Err(ParamsError::BadLineSize(48))
+        );
+        assert_eq!(
+            CacheParams::new(64, 12, 13, 6_291_456, 5000),
+            Err(ParamsError::TooManyReachable {
+                reachable: 13,
+                lines_per_set: 12
+            })
+        );
+        assert_eq!(
+            CacheParams::new(64, 12, 12, 6_291_457, 5000),
+            Err(ParamsError::SizeNotDivisible {
+                cache_size: 6_291_457,
+                set_size: 768
+            })
+        );
+        assert_eq!(
+            CacheParams::new(64, 12, 12, 768 * 96, 5000),
+            Err(ParamsError::SetsNotDivisible {
+                sets: 96,
+                sets_per_page: 64
+            })
+        );
+    }
+
+    #[test]
+    fn params_report() {
+        let report = CORE_I7.report();
+
+        assert_eq!(report.sets, 8192, "Number of sets is wrong");
+        assert_eq!(report.colors, 128, "Number of colors is wrong");
+        assert_eq!(report.buf_size, 5000 * PAGE_SIZE, "Buffer size is wrong");
+        assert_eq!(report.addrs_per_set, 39, "Addresses per set are wrong");
+        assert_eq!(report.warning, None, "Enough addresses are warned about");
+
+        let report = XEON_E5.report();
+        assert_eq!(
+            report.warning,
+            Some(
+                ParamsError::TooFewAddrs {
+                    addr_num: 5000,
+                    required: 5376
+                }
+                .to_string()
+            ),
+            "Too few addresses are not warned about"
+        );
+    }
+
+    #[test]
+    fn params_json_roundtrip() {
+        let json = serde_json::to_string(&XEON_E5_DDIO).expect("Failed to serialize params");
+        let cache_params = CacheParams::from_json(&json).expect("Failed to parse JSON");
+
+        assert_eq!(cache_params, XEON_E5_DDIO, "Parsed params are wrong");
+    }
 }
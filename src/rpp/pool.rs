@@ -0,0 +1,98 @@
+//! # Address pool
+//!
+//! Bookkeeping of the addresses, which are still free for building eviction sets.
+//! All addresses in a pool share the page offset, thus each of them is identified
+//! by its page number. Removed pages are tracked in a bitset, so removing `m`
+//! addresses from a pool of `n` costs `O(n + m)`.
+
+use super::params::PAGE_SIZE;
+use crate::connection::Address;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const WORD_BITS: usize = 64;
+
+/// Addresses with the same page offset (bits 0-11), which are not yet used
+#[derive(Clone, Debug, Default)]
+pub(super) struct AddrPool {
+    // free addresses in the initial order
+    addrs: Vec<Address>,
+    // one bit per page, set if the page was removed from the pool
+    removed: Vec<u64>,
+}
+
+impl AddrPool {
+    /// Creates a pool of addresses at the given offset of each of the `pages` pages
+    pub(super) fn new(pages: usize, ofs: usize) -> AddrPool {
+        AddrPool {
+            addrs: (0..pages).map(|x| (x * PAGE_SIZE) ^ ofs).collect(),
+            removed: vec![0; pages / WORD_BITS + 1],
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    /// Free addresses in the pool
+    #[inline(always)]
+    pub(super) fn as_slice(&self) -> &[Address] {
+        &self.addrs
+    }
+
+    pub(super) fn choose<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Address> {
+        self.addrs.choose(rng).copied()
+    }
+
+    #[inline(always)]
+    fn bit(addr: Address) -> (usize, u64) {
+        let page = addr / PAGE_SIZE;
+        (page / WORD_BITS, 1 << (page % WORD_BITS))
+    }
+
+    /// Removes all given addresses from the pool
+    pub(super) fn remove_all(&mut self, addrs: &[Address]) {
+        if addrs.is_empty() {
+            return;
+        }
+
+        for &addr in addrs {
+            let (word, mask) = Self::bit(addr);
+            if let Some(w) = self.removed.get_mut(word) {
+                *w |= mask;
+            }
+        }
+
+        let removed = &self.removed;
+        self.addrs.retain(|&x| {
+            let (word, mask) = Self::bit(x);
+            removed[word] & mask == 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_removal() {
+        let mut pool = AddrPool::new(200, 3 << 6);
+        assert_eq!(pool.len(), 200, "Wrong initial size");
+        assert_eq!(pool.as_slice()[5], 5 * PAGE_SIZE + (3 << 6), "Wrong address");
+
+        let to_remove: Vec<Address> = pool.as_slice().iter().copied().step_by(2).collect();
+        pool.remove_all(&to_remove);
+
+        assert_eq!(pool.len(), 100, "Wrong size after removal");
+        assert!(
+            pool.as_slice().iter().all(|x| !to_remove.contains(x)),
+            "Address not removed"
+        );
+        assert!(
+            pool.as_slice().windows(2).all(|w| w[0] < w[1]),
+            "Order is not preserved"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! # Cache presets
+//!
+//! Bundled database of last level cache descriptions for known victim CPUs.
+//! The database is kept in `presets.toml`, so a new CPU only needs a new table there.
+//! CPUs, which are not in the database, may be described with a separate file,
+//! see `CacheParams::from_file`.
+
+use super::params::CacheParams;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+static PRESETS_TOML: &str = include_str!("presets.toml");
+
+/// A single entry of the preset database
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    /// Human readable CPU model
+    pub model: String,
+    /// Description of the LLC of the model
+    #[serde(flatten)]
+    pub cache: CacheParams,
+}
+
+/// Preset database, keyed by the preset name
+pub type Presets = BTreeMap<String, Preset>;
+
+/// Returns the whole bundled preset database
+pub fn presets() -> Presets {
+    toml::from_str(PRESETS_TOML).expect("Bundled cache preset database is malformed")
+}
+
+/// Returns the names of all bundled presets
+pub fn names() -> Vec<String> {
+    presets().keys().cloned().collect()
+}
+
+/// Looks up cache parameters by the preset name (e.g. `skylake-sp-ddio`).
+/// The lookup is case insensitive.
+pub fn preset(name: &str) -> Option<CacheParams> {
+    let name = name.to_lowercase();
+    presets().remove(&name).map(|p| p.cache)
+}
+
+/// Returns all presets, whose CPU model contains the given string (e.g. `8180`).
+/// The search is case insensitive.
+pub fn find_by_model(model: &str) -> Vec<(String, Preset)> {
+    let model = model.to_lowercase();
+    presets()
+        .into_iter()
+        .filter(|(_, p)| p.model.to_lowercase().contains(&model))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpp::params::*;
+
+    #[test]
+    fn presets_parse() {
+        let presets = presets();
+
+        for name in &[
+            "skylake-sp",
+            "cascade-lake",
+            "ice-lake-sp",
+            "sapphire-rapids",
+            "broadwell-ep",
+        ] {
+            assert!(presets.contains_key(*name), "Missing preset {}", name);
+            let ddio = format!("{}-ddio", name);
+            assert!(presets.contains_key(&ddio), "Missing preset {}", ddio);
+        }
+    }
+
+    #[test]
+    fn legacy_presets_match() {
+        assert_eq!(preset("xeon-e5"), Some(XEON_E5), "E5 preset differs");
+        assert_eq!(preset("XEON-E5-DDIO"), Some(XEON_E5_DDIO), "E5 DDIO preset differs");
+        assert_eq!(preset("core-i7"), Some(CORE_I7), "I7 preset differs");
+        assert_eq!(preset("xeon-platinum"), Some(XEON_PLATINUM), "Platinum preset differs");
+        assert_eq!(preset("unknown"), None, "Unknown preset found");
+    }
+
+    #[test]
+    fn model_lookup() {
+        let found = find_by_model("8180");
+
+        assert_eq!(found.len(), 2, "Expected Skylake-SP and its DDIO variant");
+        assert!(found.iter().all(|(name, _)| name.starts_with("skylake-sp")));
+    }
+}
@@ -0,0 +1,197 @@
+//! # Probe classification
+//!
+//! A single slow access is not enough to tell an activation from noise. Each probe
+//! keeps per-line labels together with a confidence score, and `ActivationRule`
+//! decides, whether they amount to an activation. Lines with invalid timings
+//! (e.g. delayed by a retransmission) are skipped: timing them again would only
+//! show a hit, as the probe itself brings them into the cache.
+
+use super::timing_classif::{CacheTiming, LatencyClassifier};
+use super::Latencies;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Latencies of a probed eviction set together with their classification
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Probe {
+    /// Latencies in the order of the lines in the set
+    pub lats: Latencies,
+    /// Per-line labels, `true` if the line was classified as a miss
+    pub misses: Vec<bool>,
+    /// Number of lines, classified as misses
+    pub miss_cnt: usize,
+    /// Per-line labels, `true` if the timing of the line was invalid and is skipped
+    #[serde(default)]
+    pub invalid: Vec<bool>,
+    /// Number of skipped lines
+    #[serde(default)]
+    pub invalid_cnt: usize,
+    /// Mean certainty of the miss labels, from 0 (at the decision boundary of the classifier)
+    /// to 1 (a typical miss or slower). It is 0 if there are no misses.
+    pub confidence: f64,
+}
+
+impl Probe {
+    /// Classifies each of the latencies
+    pub(super) fn classify(lats: Latencies, classifier: &dyn LatencyClassifier) -> Probe {
+        let timings: Vec<CacheTiming> = lats.iter().map(|&t| classifier.classify(t)).collect();
+        let misses: Vec<bool> = timings.iter().map(CacheTiming::is_miss).collect();
+        let invalid: Vec<bool> = timings.iter().map(CacheTiming::is_invalid).collect();
+        let miss_cnt = misses.iter().filter(|&&m| m).count();
+        let invalid_cnt = invalid.iter().filter(|&&i| i).count();
+
+        let confidence = if miss_cnt == 0 {
+            0.0
+        } else {
+            lats.iter()
+                .zip(misses.iter())
+                .filter(|(_, &m)| m)
+                .map(|(&t, _)| classifier.certainty(t))
+                .sum::<f64>()
+                / miss_cnt as f64
+        };
+
+        Probe {
+            lats,
+            misses,
+            miss_cnt,
+            invalid,
+            invalid_cnt,
+            confidence,
+        }
+    }
+}
+
+/// Decides, whether a probe shows an activation of the set
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActivationRule {
+    min_misses: usize,
+    min_confidence: f64,
+}
+
+impl Default for ActivationRule {
+    /// Any single miss is an activation
+    fn default() -> Self {
+        ActivationRule {
+            min_misses: 1,
+            min_confidence: 0.0,
+        }
+    }
+}
+
+impl ActivationRule {
+    /// Requires at least `min_misses` lines to be classified as misses, with the
+    /// confidence of at least `min_confidence`.
+    ///
+    /// # Fails
+    ///
+    /// Fails if `min_misses` is zero or `min_confidence` is not in `[0, 1]`.
+    pub fn new(min_misses: usize, min_confidence: f64) -> Result<ActivationRule> {
+        if min_misses == 0 {
+            return Err(Error::InvalidConfig(
+                "an activation requires at least one miss".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&min_confidence) {
+            return Err(Error::InvalidConfig(
+                "confidence threshold should be in [0, 1]".to_string(),
+            ));
+        }
+
+        Ok(ActivationRule {
+            min_misses,
+            min_confidence,
+        })
+    }
+
+    #[inline(always)]
+    pub fn min_misses(&self) -> usize {
+        self.min_misses
+    }
+
+    #[inline(always)]
+    pub fn min_confidence(&self) -> f64 {
+        self.min_confidence
+    }
+
+    /// Tests, whether the probe satisfies the rule
+    #[inline(always)]
+    pub fn is_activation(&self, probe: &Probe) -> bool {
+        probe.miss_cnt >= self.min_misses && probe.confidence >= self.min_confidence
+    }
+}
+
+impl fmt::Display for ActivationRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "at least {} misses with confidence {:.2}",
+            self.min_misses, self.min_confidence
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::timing_classif::LevelClassifier;
+    use super::*;
+    use crate::connection::sim::fixtures::trained_classifier;
+
+    #[test]
+    fn probe_classification() {
+        let classifier = trained_classifier();
+
+        let probe = Probe::classify(vec![100, 310, 250, 90], &classifier);
+        assert_eq!(probe.misses, vec![false, true, true, false], "Wrong labels");
+        assert_eq!(probe.miss_cnt, 2, "Wrong miss count");
+        // certainties are 1.0 and 0.5
+        assert!((probe.confidence - 0.75).abs() < 1e-9, "Wrong confidence");
+
+        let stale = Probe::classify(vec![100, 120], &classifier);
+        assert_eq!(stale.miss_cnt, 0, "Wrong miss count");
+        assert_eq!(stale.confidence, 0.0, "Stale probe should have no confidence");
+    }
+
+    #[test]
+    fn invalid_lines_skipped() {
+        let mut classifier = LevelClassifier::new(2);
+        for t in 0..10 {
+            classifier.record(CacheTiming::Hit(95 + t));
+            classifier.record(CacheTiming::Miss(295 + t));
+        }
+        classifier.fit();
+
+        let probe = Probe::classify(vec![100, 5000, 300, 100], &classifier);
+        assert_eq!(probe.invalid, vec![false, true, false, false], "Wrong invalid labels");
+        assert_eq!(probe.invalid_cnt, 1, "Wrong invalid count");
+        assert_eq!(probe.miss_cnt, 1, "Invalid line counted as a miss");
+
+        let spurious = Probe::classify(vec![100, 5000, 100, 100], &classifier);
+        assert!(
+            !ActivationRule::default().is_activation(&spurious),
+            "Invalid line caused an activation"
+        );
+    }
+
+    #[test]
+    fn activation_rules() {
+        let classifier = trained_classifier();
+        let noisy = Probe::classify(vec![100, 210, 100, 100], &classifier);
+        let clear = Probe::classify(vec![300, 320, 100, 100], &classifier);
+
+        let default = ActivationRule::default();
+        assert!(default.is_activation(&noisy), "Default rule should accept one miss");
+
+        let two_misses = ActivationRule::new(2, 0.0).unwrap();
+        assert!(!two_misses.is_activation(&noisy), "Single miss accepted");
+        assert!(two_misses.is_activation(&clear), "Two misses rejected");
+
+        let confident = ActivationRule::new(1, 0.5).unwrap();
+        assert!(!confident.is_activation(&noisy), "Unconfident miss accepted");
+        assert!(confident.is_activation(&clear), "Confident misses rejected");
+
+        assert!(ActivationRule::new(0, 0.0).is_err(), "Zero misses accepted");
+        assert!(ActivationRule::new(1, 1.5).is_err(), "Bad confidence accepted");
+    }
+}
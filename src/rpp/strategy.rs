@@ -0,0 +1,300 @@
+//! # Prime and probe strategies
+//!
+//! The order, in which the lines of an eviction set are accessed, matters for
+//! caches with PLRU or QLRU replacement policies. Walking a set once in order
+//! may leave some of the lines out of the cache or evict them while probing,
+//! which is seen as a false activation. Strategies allow to choose an order,
+//! which works for a given victim.
+//!
+//! Both strategies can be parsed from and printed to a short textual form:
+//!
+//! | Prime        | Probe      |
+//! |--------------|------------|
+//! | `seq`        | `seq`      |
+//! | `repeat:N`   | `reverse`  |
+//! | `zigzag:N`   | `random`   |
+//! | `random:N`   | `first:K`  |
+
+use crate::connection::Address;
+use crate::{Error, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Describes, how the lines of an eviction set are accessed while priming
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", try_from = "RawPrimeStrategy")]
+pub enum PrimeStrategy {
+    /// Walks the set once in order
+    Sequential,
+    /// Walks the set in order for the given number of passes
+    Repeated(usize),
+    /// Walks the set forward and backward by turns for the given number of passes
+    ZigZag(usize),
+    /// Walks the set in a new random order for each of the given number of passes
+    Random(usize),
+}
+
+// Unvalidated form of `PrimeStrategy`, used for deserialization
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RawPrimeStrategy {
+    Sequential,
+    Repeated(usize),
+    ZigZag(usize),
+    Random(usize),
+}
+
+impl TryFrom<RawPrimeStrategy> for PrimeStrategy {
+    type Error = Error;
+
+    fn try_from(raw: RawPrimeStrategy) -> Result<Self> {
+        let strategy = match raw {
+            RawPrimeStrategy::Sequential => PrimeStrategy::Sequential,
+            RawPrimeStrategy::Repeated(n) => PrimeStrategy::Repeated(n),
+            RawPrimeStrategy::ZigZag(n) => PrimeStrategy::ZigZag(n),
+            RawPrimeStrategy::Random(n) => PrimeStrategy::Random(n),
+        };
+        strategy.validate()?;
+
+        Ok(strategy)
+    }
+}
+
+impl Default for PrimeStrategy {
+    fn default() -> Self {
+        PrimeStrategy::Sequential
+    }
+}
+
+impl PrimeStrategy {
+    /// Checks, that the strategy makes at least one pass
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            PrimeStrategy::Repeated(0) | PrimeStrategy::ZigZag(0) | PrimeStrategy::Random(0) => {
+                Err(Error::InvalidConfig(format!(
+                    "prime strategy `{}` should make at least one pass",
+                    self
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the addresses of the `set` in the order they should be accessed
+    pub fn order<R: Rng + ?Sized>(&self, set: &[Address], rng: &mut R) -> Vec<Address> {
+        use PrimeStrategy::*;
+
+        let passes = match *self {
+            Sequential => 1,
+            Repeated(n) | ZigZag(n) | Random(n) => n,
+        };
+        let mut order = Vec::with_capacity(set.len() * passes);
+
+        for pass in 0..passes {
+            match *self {
+                Sequential | Repeated(_) => order.extend_from_slice(set),
+                ZigZag(_) if pass % 2 == 1 => order.extend(set.iter().rev()),
+                ZigZag(_) => order.extend_from_slice(set),
+                Random(_) => {
+                    let start = order.len();
+                    order.extend_from_slice(set);
+                    order[start..].shuffle(rng);
+                }
+            }
+        }
+
+        order
+    }
+}
+
+impl fmt::Display for PrimeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrimeStrategy::Sequential => write!(f, "seq"),
+            PrimeStrategy::Repeated(n) => write!(f, "repeat:{}", n),
+            PrimeStrategy::ZigZag(n) => write!(f, "zigzag:{}", n),
+            PrimeStrategy::Random(n) => write!(f, "random:{}", n),
+        }
+    }
+}
+
+impl FromStr for PrimeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = split_arg(s)?;
+
+        match (name, arg) {
+            ("seq", None) => Ok(PrimeStrategy::Sequential),
+            ("repeat", Some(n)) => Ok(PrimeStrategy::Repeated(n)),
+            ("zigzag", Some(n)) => Ok(PrimeStrategy::ZigZag(n)),
+            ("random", Some(n)) => Ok(PrimeStrategy::Random(n)),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown prime strategy `{}`. Expected seq, repeat:N, zigzag:N or random:N",
+                s
+            ))),
+        }
+    }
+}
+
+/// Describes, how the lines of an eviction set are timed while probing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStrategy {
+    /// Times each line of the set in order
+    Sequential,
+    /// Times the lines in the reverse of the last priming pass, so that probing
+    /// does not evict the lines, which are yet to be timed
+    ReversePrime,
+    /// Times the lines in a random order
+    Random,
+    /// Times only the first `k` lines of the set
+    FirstK(usize),
+}
+
+impl Default for ProbeStrategy {
+    fn default() -> Self {
+        ProbeStrategy::Sequential
+    }
+}
+
+impl ProbeStrategy {
+    /// Returns the addresses of the `set` in the order they should be timed.
+    /// `last_pass` is the order of the last priming pass of this set.
+    pub fn order<R: Rng + ?Sized>(
+        &self,
+        set: &[Address],
+        last_pass: &[Address],
+        rng: &mut R,
+    ) -> Vec<Address> {
+        match *self {
+            ProbeStrategy::Sequential => set.to_vec(),
+            ProbeStrategy::ReversePrime => last_pass.iter().rev().copied().collect(),
+            ProbeStrategy::Random => {
+                let mut order = set.to_vec();
+                order.shuffle(rng);
+                order
+            }
+            ProbeStrategy::FirstK(k) => set.iter().take(k).copied().collect(),
+        }
+    }
+
+    /// Tells, whether the order of the last priming pass is needed for probing
+    #[inline(always)]
+    pub fn needs_prime_order(&self) -> bool {
+        *self == ProbeStrategy::ReversePrime
+    }
+}
+
+impl fmt::Display for ProbeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeStrategy::Sequential => write!(f, "seq"),
+            ProbeStrategy::ReversePrime => write!(f, "reverse"),
+            ProbeStrategy::Random => write!(f, "random"),
+            ProbeStrategy::FirstK(k) => write!(f, "first:{}", k),
+        }
+    }
+}
+
+impl FromStr for ProbeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, arg) = split_arg(s)?;
+
+        match (name, arg) {
+            ("seq", None) => Ok(ProbeStrategy::Sequential),
+            ("reverse", None) => Ok(ProbeStrategy::ReversePrime),
+            ("random", None) => Ok(ProbeStrategy::Random),
+            ("first", Some(k)) => Ok(ProbeStrategy::FirstK(k)),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown probe strategy `{}`. Expected seq, reverse, random or first:K",
+                s
+            ))),
+        }
+    }
+}
+
+// Splits `name:N` into its parts. The number should be positive.
+pub(crate) fn split_arg(s: &str) -> Result<(&str, Option<usize>)> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next().unwrap_or_default();
+
+    let arg = match parts.next() {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                return Err(Error::InvalidConfig(format!("`{}` should be a positive number", n)))
+            }
+        },
+        None => None,
+    };
+
+    Ok((name, arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_orders() {
+        let set = [1, 2, 3];
+        let mut rng = rand::thread_rng();
+
+        assert_eq!(PrimeStrategy::Sequential.order(&set, &mut rng), vec![1, 2, 3]);
+        assert_eq!(
+            PrimeStrategy::Repeated(2).order(&set, &mut rng),
+            vec![1, 2, 3, 1, 2, 3]
+        );
+        assert_eq!(
+            PrimeStrategy::ZigZag(3).order(&set, &mut rng),
+            vec![1, 2, 3, 3, 2, 1, 1, 2, 3]
+        );
+
+        let random = PrimeStrategy::Random(2).order(&set, &mut rng);
+        assert_eq!(random.len(), 6, "Wrong number of accesses");
+        for pass in random.chunks(3) {
+            let mut pass = pass.to_vec();
+            pass.sort();
+            assert_eq!(pass, set, "Each pass should access every line once");
+        }
+
+        let last_pass = [3, 1, 2];
+        assert_eq!(
+            ProbeStrategy::ReversePrime.order(&set, &last_pass, &mut rng),
+            vec![2, 1, 3]
+        );
+        assert_eq!(ProbeStrategy::FirstK(2).order(&set, &set, &mut rng), vec![1, 2]);
+        assert_eq!(ProbeStrategy::FirstK(5).order(&set, &set, &mut rng), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn strategy_parsing() {
+        for s in &["seq", "repeat:3", "zigzag:2", "random:1"] {
+            let strategy: PrimeStrategy = s.parse().unwrap();
+            assert_eq!(&strategy.to_string(), s, "Prime strategy roundtrip failed");
+        }
+        for s in &["seq", "reverse", "random", "first:4"] {
+            let strategy: ProbeStrategy = s.parse().unwrap();
+            assert_eq!(&strategy.to_string(), s, "Probe strategy roundtrip failed");
+        }
+
+        assert!("zigzag".parse::<PrimeStrategy>().is_err(), "Missing passes accepted");
+        assert!("repeat:0".parse::<PrimeStrategy>().is_err(), "Zero passes accepted");
+        assert!(PrimeStrategy::ZigZag(0).validate().is_err(), "Zero passes are valid");
+        assert!(
+            serde_json::from_str::<PrimeStrategy>(r#"{"random":0}"#).is_err(),
+            "Zero passes deserialized"
+        );
+        let strategy: PrimeStrategy = serde_json::from_str(r#"{"zig_zag":2}"#).unwrap();
+        assert_eq!(strategy, PrimeStrategy::ZigZag(2), "Valid strategy is not deserialized");
+        assert!("first:x".parse::<ProbeStrategy>().is_err(), "Bad number accepted");
+        assert!("reverse:2".parse::<ProbeStrategy>().is_err(), "Extra argument accepted");
+    }
+}
@@ -1,16 +1,42 @@
 //! # Timing Classification
 //!
-//! Provides resources for distiguishing between a cache hit and a cache miss by access time
+//! Provides resources for distiguishing between a cache hit and a cache miss by access time.
+//!
+//! All classifiers are trained by recording timings of known class and implement
+//! `LatencyClassifier`. Which one to use is described by `ClassifierKind`:
+//!
+//! - `centroid` - a timing belongs to the class with the closer median
+//! - `threshold:N` - timings above `N` are misses
+//! - `gmm` - a two-component gaussian mixture, fitted with EM
+//! - `otsu` - a threshold, which best separates the combined histogram
+//! - `levels:N` - `N` latency levels (e.g. LLC, DDIO way, DRAM, retransmission), learnt
+//!   with k-means. Extreme outliers are classified as invalid.
+//!
+//! Network latency drifts during long measurements. `Aging` limits the influence of
+//! old timings, so that a classifier, which is periodically retrained, follows the drift.
 
 use super::Time;
+use crate::{Error, Result};
 use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 pub const PERCENTILE: f64 = 50.0;
 
+const EM_MAX_ITER: usize = 100;
+const EM_EPS: f64 = 1e-6;
+const MIN_STDEV: f64 = 1.0;
+// Timings further than this many standard deviations from the nearest level are invalid
+const OUTLIER_STDEVS: f64 = 6.0;
+
 /// Enum for distinguishing between cache hit and miss timings
 pub enum CacheTiming {
     Hit(Time),
     Miss(Time),
+    /// A timing, which is neither a typical hit nor a typical miss, e.g. caused by
+    /// a network retransmission. It should not be taken into account.
+    Invalid(Time),
 }
 
 impl CacheTiming {
@@ -18,6 +44,7 @@ impl CacheTiming {
         match self {
             Self::Hit(t) => t,
             Self::Miss(t) => t,
+            Self::Invalid(t) => t,
         }
     }
 
@@ -38,52 +65,514 @@ impl CacheTiming {
 
         false
     }
+
+    /// Tests, wheter the enum value is `Invalid`
+    pub fn is_invalid(&self) -> bool {
+        if let Self::Invalid(_) = self {
+            return true;
+        }
+
+        false
+    }
 }
 
 /// Classifier of access timing. First needs to be trained by recording known timings.
-/// Those are collected in two clusters.
+pub trait LatencyClassifier: Send {
+    /// Records a new timing of a known class
+    fn record(&mut self, timing: CacheTiming);
+
+    /// Refits the model to the recorded timings. Is called after each training round.
+    fn fit(&mut self) {}
+
+    /// Classifies the given timing. If undecisive, defaults to cache hit
+    fn classify(&self, t: Time) -> CacheTiming;
+
+    /// Tells, how certain the classification of the given timing is. Returns a value
+    /// from 0 (at the decision boundary) to 1 (a typical timing of its class or beyond).
+    fn certainty(&self, t: Time) -> f64;
+
+    /// Clears all the recorded data
+    fn clear(&mut self);
+
+    /// Share of the recorded timings, which the classifier puts into the wrong class.
+    /// It grows, when the hit and miss distributions start to overlap.
+    /// Returns `None` if not supported or if nothing is recorded.
+    fn overlap(&self) -> Option<f64> {
+        None
+    }
+
+    /// Typical hit and miss timings, the classifier relies on.
+    /// Returns `None` if not supported or if nothing is recorded.
+    fn centroids(&self) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Saves the recorded timings, from which the classifier can be restored.
+    /// Returns `None` if not supported.
+    fn state(&self) -> Option<ClassifierState> {
+        None
+    }
+
+    /// Replaces the recorded timings with the saved ones and refits the model to them
+    ///
+    /// # Fails
+    /// Fails if the classifier cannot be restored
+    fn restore(&mut self, _state: &ClassifierState) -> Result<()> {
+        Err(Error::InvalidConfig("the classifier cannot be restored".to_string()))
+    }
+
+    /// Tests whether a given timing is a hit
+    #[inline(always)]
+    fn is_hit(&self, t: Time) -> bool {
+        self.classify(t).is_hit()
+    }
+
+    /// Tests whether a given timing is a miss
+    #[inline(always)]
+    fn is_miss(&self, t: Time) -> bool {
+        self.classify(t).is_miss()
+    }
+
+    /// Tests whether a given timing is an outlier, which should be ignored
+    #[inline(always)]
+    fn is_invalid(&self, t: Time) -> bool {
+        self.classify(t).is_invalid()
+    }
+
+    /// Latency level of the given timing, from the fastest to the slowest.
+    /// Classifiers, which only tell hits from misses, have two levels.
+    /// Returns `None` for invalid timings.
+    fn level(&self, t: Time) -> Option<usize> {
+        match self.classify(t) {
+            CacheTiming::Hit(_) => Some(0),
+            CacheTiming::Miss(_) => Some(1),
+            CacheTiming::Invalid(_) => None,
+        }
+    }
+}
+
+/// Selects a `LatencyClassifier` implementation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassifierKind {
+    /// Nearest median of the hit and miss clusters
+    Centroid,
+    /// Fixed threshold, timings above it are misses
+    Threshold(Time),
+    /// Two-component gaussian mixture, fitted with EM
+    Gmm,
+    /// Otsu's threshold on the combined histogram
+    Otsu,
+    /// The given number of latency levels with outlier detection
+    Levels(usize),
+}
+
+impl Default for ClassifierKind {
+    fn default() -> Self {
+        ClassifierKind::Centroid
+    }
+}
+
+impl ClassifierKind {
+    /// Creates a new untrained classifier of this kind
+    pub fn build(&self) -> Box<dyn LatencyClassifier> {
+        self.build_aged(Aging::default())
+    }
+
+    /// Creates a new untrained classifier of this kind, which ages recorded timings
+    pub fn build_aged(&self, aging: Aging) -> Box<dyn LatencyClassifier> {
+        match *self {
+            ClassifierKind::Centroid => Box::new(TimingClassifier::new().aged(aging)),
+            ClassifierKind::Threshold(t) => Box::new(ThresholdClassifier::new(t).aged(aging)),
+            ClassifierKind::Gmm => Box::new(GmmClassifier::new().aged(aging)),
+            ClassifierKind::Otsu => Box::new(OtsuClassifier::new().aged(aging)),
+            ClassifierKind::Levels(n) => Box::new(LevelClassifier::new(n).aged(aging)),
+        }
+    }
+}
+
+impl fmt::Display for ClassifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassifierKind::Centroid => write!(f, "centroid"),
+            ClassifierKind::Threshold(t) => write!(f, "threshold:{}", t),
+            ClassifierKind::Gmm => write!(f, "gmm"),
+            ClassifierKind::Otsu => write!(f, "otsu"),
+            ClassifierKind::Levels(n) => write!(f, "levels:{}", n),
+        }
+    }
+}
+
+impl FromStr for ClassifierKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, ':');
+
+        match (parts.next(), parts.next()) {
+            (Some("centroid"), None) => Ok(ClassifierKind::Centroid),
+            (Some("gmm"), None) => Ok(ClassifierKind::Gmm),
+            (Some("otsu"), None) => Ok(ClassifierKind::Otsu),
+            (Some("threshold"), Some(t)) => t.parse().map(ClassifierKind::Threshold).map_err(|_| {
+                Error::InvalidConfig(format!("`{}` is not a valid threshold", t))
+            }),
+            (Some("levels"), Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n > 1 => Ok(ClassifierKind::Levels(n)),
+                _ => Err(Error::InvalidConfig(format!(
+                    "`{}` is not a valid number of levels. Expected at least 2",
+                    n
+                ))),
+            },
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown classifier `{}`. Expected centroid, threshold:N, gmm, otsu or levels:N",
+                s
+            ))),
+        }
+    }
+}
+
+/// Describes, how the influence of old timings is limited
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aging {
+    /// All recorded timings are kept
+    Unbounded,
+    /// Roughly the given number of the most recent timings per class are kept
+    Window(usize),
+    /// Counts are halved after the given number of timings per class
+    Decay(usize),
+}
+
+impl Default for Aging {
+    fn default() -> Self {
+        Aging::Unbounded
+    }
+}
+
+impl fmt::Display for Aging {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Aging::Unbounded => write!(f, "none"),
+            Aging::Window(n) => write!(f, "window:{}", n),
+            Aging::Decay(n) => write!(f, "decay:{}", n),
+        }
+    }
+}
+
+impl FromStr for Aging {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, ':');
+        let err = || {
+            Error::InvalidConfig(format!(
+                "unknown aging `{}`. Expected none, window:N or decay:N with N > 1",
+                s
+            ))
+        };
+
+        let (name, n) = match (parts.next(), parts.next()) {
+            (Some("none"), None) => return Ok(Aging::Unbounded),
+            (Some(name), Some(n)) => (name, n.parse::<usize>().map_err(|_| err())?),
+            _ => return Err(err()),
+        };
+
+        match name {
+            "window" if n > 1 => Ok(Aging::Window(n)),
+            "decay" if n > 1 => Ok(Aging::Decay(n)),
+            _ => Err(err()),
+        }
+    }
+}
+
+/// Describes, how the classifier follows the latency drift during tracking
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DriftConfig {
+    /// Aging of the recorded timings
+    pub aging: Aging,
+    /// Number of measurements between recalibrations. Zero disables recalibration.
+    pub recalibrate_every: usize,
+    /// Number of hit and miss pairs to record on each recalibration
+    pub samples: usize,
+    /// Raise the drift alarm, when the overlap of hit and miss timings exceeds this share
+    pub max_overlap: f64,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        DriftConfig {
+            aging: Aging::default(),
+            recalibrate_every: 0,
+            samples: 50,
+            max_overlap: 0.1,
+        }
+    }
+}
+
+/// Saved timings of a trained classifier
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClassifierState {
+    hits: HistogramState,
+    misses: HistogramState,
+}
+
+// Saved values of an `AgedHistogram` with their counts
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct HistogramState {
+    hist: Vec<(Time, u64)>,
+    cur: Vec<(Time, u64)>,
+    fresh: usize,
+}
+
+/// A histogram, which forgets old values according to `Aging`
+struct AgedHistogram {
+    // the effective histogram
+    hist: Histogram<u64>,
+    // the current generation of a window
+    cur: Histogram<u64>,
+    // values recorded since the last aging step
+    fresh: usize,
+    aging: Aging,
+}
+
+impl AgedHistogram {
+    fn new(aging: Aging) -> Self {
+        let hist = Histogram::new(5).expect("Could not create a histogram for timings"); // 5 sets the precision and it is the maximum possible
+        let cur = Histogram::new_from(&hist);
+
+        AgedHistogram {
+            hist,
+            cur,
+            fresh: 0,
+            aging,
+        }
+    }
+
+    #[inline(always)]
+    fn hist(&self) -> &Histogram<u64> {
+        &self.hist
+    }
+
+    fn record(&mut self, t: Time) {
+        self.hist.record(t).expect("Failed to record new timing");
+        self.fresh += 1;
+
+        match self.aging {
+            Aging::Unbounded => (),
+            Aging::Window(n) => {
+                // The window consists of two generations, each of a half of its size.
+                // When the current one is full, the previous one is dropped.
+                self.cur.record(t).expect("Failed to record new timing");
+                if self.fresh >= n / 2 {
+                    self.hist
+                        .set_to(&self.cur)
+                        .expect("Failed to drop old timings");
+                    self.cur.clear();
+                    self.fresh = 0;
+                }
+            }
+            Aging::Decay(n) => {
+                if self.fresh >= n {
+                    let mut halved = Histogram::new_from(&self.hist);
+                    for v in self.hist.iter_recorded() {
+                        // values with a single count are dropped
+                        halved
+                            .record_n(v.value_iterated_to(), v.count_at_value() / 2)
+                            .expect("Failed to decay timings");
+                    }
+                    self.hist = halved;
+                    self.fresh = 0;
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.hist.clear();
+        self.cur.clear();
+        self.fresh = 0;
+    }
+
+    fn state(&self) -> HistogramState {
+        let counts = |h: &Histogram<u64>| {
+            h.iter_recorded()
+                .map(|v| (v.value_iterated_to(), v.count_at_value()))
+                .collect()
+        };
+
+        HistogramState {
+            hist: counts(&self.hist),
+            cur: counts(&self.cur),
+            fresh: self.fresh,
+        }
+    }
+
+    fn restore(&mut self, state: &HistogramState) {
+        self.clear();
+        for &(t, cnt) in &state.hist {
+            self.hist.record_n(t, cnt).expect("Failed to restore timings");
+        }
+        for &(t, cnt) in &state.cur {
+            self.cur.record_n(t, cnt).expect("Failed to restore timings");
+        }
+        self.fresh = state.fresh;
+    }
+}
+
+/// Recorded hit and miss timings
+struct Clusters {
+    hits: AgedHistogram,
+    misses: AgedHistogram,
+}
+
+impl Clusters {
+    fn new(aging: Aging) -> Self {
+        Clusters {
+            hits: AgedHistogram::new(aging),
+            misses: AgedHistogram::new(aging),
+        }
+    }
+
+    #[inline(always)]
+    fn hits(&self) -> &Histogram<u64> {
+        self.hits.hist()
+    }
+
+    #[inline(always)]
+    fn misses(&self) -> &Histogram<u64> {
+        self.misses.hist()
+    }
+
+    fn record(&mut self, timing: CacheTiming) {
+        match timing {
+            CacheTiming::Hit(t) => self.hits.record(t),
+            CacheTiming::Miss(t) => self.misses.record(t),
+            CacheTiming::Invalid(_) => (),
+        }
+    }
+
+    /// Distinct recorded values of both clusters with their counts, sorted by value
+    fn combined(&self) -> Vec<(f64, f64)> {
+        let mut all = self.hits().clone();
+        all.add(self.misses())
+            .expect("Failed to combine hit and miss timings");
+
+        all.iter_recorded()
+            .map(|v| (v.value_iterated_to() as f64, v.count_at_value() as f64))
+            .collect()
+    }
+
+    /// Median hit and miss timings, if both clusters are not empty
+    fn medians(&self) -> Option<(f64, f64)> {
+        if self.hits().is_empty() || self.misses().is_empty() {
+            return None;
+        }
+
+        Some((
+            self.hits().value_at_percentile(PERCENTILE) as f64,
+            self.misses().value_at_percentile(PERCENTILE) as f64,
+        ))
+    }
+
+    /// Share of the recorded timings, for which `is_miss` gives the wrong class
+    fn overlap<F: Fn(Time) -> bool>(&self, is_miss: F) -> Option<f64> {
+        let total = self.hits().len() + self.misses().len();
+        if total == 0 {
+            return None;
+        }
+
+        let wrong = |h: &Histogram<u64>, miss: bool| -> u64 {
+            h.iter_recorded()
+                .filter(|v| is_miss(v.value_iterated_to()) != miss)
+                .map(|v| v.count_at_value())
+                .sum()
+        };
+        let wrong = wrong(self.hits(), false) + wrong(self.misses(), true);
+
+        Some(wrong as f64 / total as f64)
+    }
+
+    fn clear(&mut self) {
+        self.hits.clear();
+        self.misses.clear();
+    }
+
+    fn state(&self) -> ClassifierState {
+        ClassifierState {
+            hits: self.hits.state(),
+            misses: self.misses.state(),
+        }
+    }
+
+    fn restore(&mut self, state: &ClassifierState) {
+        self.hits.restore(&state.hits);
+        self.misses.restore(&state.misses);
+    }
+}
+
+// Certainty of a threshold decision: grows linearly from 0 at the threshold
+// to 1 at the typical timing of the chosen class.
+fn threshold_certainty(t: Time, threshold: f64, hit_centroid: f64, miss_centroid: f64) -> f64 {
+    let t = t as f64;
+    let (dist, span) = if t > threshold {
+        (t - threshold, miss_centroid - threshold)
+    } else {
+        (threshold - t, threshold - hit_centroid)
+    };
+
+    if span <= 0.0 {
+        // untrained or degenerate clusters, we cannot tell anything from the distance
+        return 0.0;
+    }
+
+    (dist / span).clamp(0.0, 1.0)
+}
+
+/// Classifier, which picks the class with the closer median.
+/// The timings are collected in two clusters.
 pub struct TimingClassifier {
-    hits: Histogram<u64>,
-    misses: Histogram<u64>,
+    clusters: Clusters,
     hit_centroid: i128,
     miss_centroid: i128,
 }
 
 impl TimingClassifier {
     pub fn new() -> Self {
-        let hits = Histogram::new(5).expect("Could not create a histogram for hit timings"); // 5 sets the precision and it is the maximum possible
-        let misses = Histogram::new(5).expect("Could not create a histogram for miss timings");
-
         TimingClassifier {
-            hits,
-            misses,
+            clusters: Clusters::new(Aging::default()),
             hit_centroid: 0,
             miss_centroid: 0,
         }
     }
 
+    /// Ages the recorded timings. Drops anything recorded before.
+    pub fn aged(mut self, aging: Aging) -> Self {
+        self.clusters = Clusters::new(aging);
+        self
+    }
+}
+
+impl Default for TimingClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyClassifier for TimingClassifier {
     /// Records a new timing
     // And updates centroids
-    pub fn record(&mut self, timing: CacheTiming) {
-        match timing {
-            CacheTiming::Hit(t) => {
-                self.hits
-                    .record(t)
-                    .expect("Failed to record new hit timing");
-                self.hit_centroid = self.hits.value_at_percentile(PERCENTILE) as i128;
-            }
-            CacheTiming::Miss(t) => {
-                self.misses
-                    .record(t)
-                    .expect("Failed to record new miss timing");
-                self.miss_centroid = self.misses.value_at_percentile(PERCENTILE) as i128;
-            }
-        }
+    fn record(&mut self, timing: CacheTiming) {
+        self.clusters.record(timing);
+        self.fit();
+    }
+
+    fn fit(&mut self) {
+        self.hit_centroid = self.clusters.hits().value_at_percentile(PERCENTILE) as i128;
+        self.miss_centroid = self.clusters.misses().value_at_percentile(PERCENTILE) as i128;
     }
 
-    /// Classifies the given timing. If undecisive (which should not generally occur), defaults to cache hit
     #[inline(always)]
-    pub fn classify(&self, t: Time) -> CacheTiming {
+    fn classify(&self, t: Time) -> CacheTiming {
         let t1 = t as i128;
         if (self.miss_centroid - t1).abs() < (self.hit_centroid - t1).abs() {
             // the time is closer to miss timings
@@ -94,23 +583,802 @@ impl TimingClassifier {
         }
     }
 
-    /// Tests whether a given timing is a hit
-    #[inline(always)]
-    pub fn is_hit(&self, t: Time) -> bool {
-        self.classify(t).is_hit()
+    fn certainty(&self, t: Time) -> f64 {
+        let (hit, miss) = (self.hit_centroid as f64, self.miss_centroid as f64);
+        threshold_certainty(t, (hit + miss) / 2.0, hit, miss)
     }
 
-    /// Tests whether a given timing is a miss
-    #[inline(always)]
-    pub fn is_miss(&self, t: Time) -> bool {
-        self.classify(t).is_miss()
+    fn overlap(&self) -> Option<f64> {
+        self.clusters.overlap(|t| self.is_miss(t))
     }
 
-    /// Clears all the recorded data
-    pub fn clear(&mut self) {
+    fn centroids(&self) -> Option<(f64, f64)> {
+        self.clusters.medians()
+    }
+
+    fn state(&self) -> Option<ClassifierState> {
+        Some(self.clusters.state())
+    }
+
+    fn restore(&mut self, state: &ClassifierState) -> Result<()> {
+        self.clusters.restore(state);
+        self.fit();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
         self.hit_centroid = 0;
         self.miss_centroid = 0;
-        self.hits.clear();
-        self.misses.clear();
+        self.clusters.clear();
+    }
+}
+
+/// Classifier with a fixed threshold. Timings above it are misses.
+/// Recorded timings are only used for certainty estimation.
+pub struct ThresholdClassifier {
+    clusters: Clusters,
+    threshold: Time,
+}
+
+impl ThresholdClassifier {
+    pub fn new(threshold: Time) -> Self {
+        ThresholdClassifier {
+            clusters: Clusters::new(Aging::default()),
+            threshold,
+        }
+    }
+
+    /// Ages the recorded timings. Drops anything recorded before.
+    pub fn aged(mut self, aging: Aging) -> Self {
+        self.clusters = Clusters::new(aging);
+        self
+    }
+}
+
+impl LatencyClassifier for ThresholdClassifier {
+    fn record(&mut self, timing: CacheTiming) {
+        self.clusters.record(timing);
+    }
+
+    #[inline(always)]
+    fn classify(&self, t: Time) -> CacheTiming {
+        if t > self.threshold {
+            CacheTiming::Miss(t)
+        } else {
+            CacheTiming::Hit(t)
+        }
+    }
+
+    fn certainty(&self, t: Time) -> f64 {
+        threshold_certainty(
+            t,
+            self.threshold as f64,
+            self.clusters.hits().value_at_percentile(PERCENTILE) as f64,
+            self.clusters.misses().value_at_percentile(PERCENTILE) as f64,
+        )
+    }
+
+    fn overlap(&self) -> Option<f64> {
+        self.clusters.overlap(|t| self.is_miss(t))
+    }
+
+    fn centroids(&self) -> Option<(f64, f64)> {
+        self.clusters.medians()
+    }
+
+    fn state(&self) -> Option<ClassifierState> {
+        Some(self.clusters.state())
+    }
+
+    fn restore(&mut self, state: &ClassifierState) -> Result<()> {
+        self.clusters.restore(state);
+        self.fit();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.clusters.clear();
+    }
+}
+
+// One component of a gaussian mixture
+#[derive(Clone, Copy, Debug, Default)]
+struct Gaussian {
+    weight: f64,
+    mean: f64,
+    stdev: f64,
+}
+
+impl Gaussian {
+    // weighted density at `x`
+    fn density(&self, x: f64) -> f64 {
+        let z = (x - self.mean) / self.stdev;
+        self.weight * (-0.5 * z * z).exp() / (self.stdev * (2.0 * std::f64::consts::PI).sqrt())
+    }
+}
+
+/// Classifier, which fits a two-component gaussian mixture to all recorded timings
+/// with expectation maximization. Handles overlapping clusters with different spread,
+/// which is typical for remote timings.
+pub struct GmmClassifier {
+    clusters: Clusters,
+    hit: Gaussian,
+    miss: Gaussian,
+}
+
+impl GmmClassifier {
+    pub fn new() -> Self {
+        GmmClassifier {
+            clusters: Clusters::new(Aging::default()),
+            hit: Default::default(),
+            miss: Default::default(),
+        }
+    }
+
+    /// Ages the recorded timings. Drops anything recorded before.
+    pub fn aged(mut self, aging: Aging) -> Self {
+        self.clusters = Clusters::new(aging);
+        self
+    }
+
+    fn is_fitted(&self) -> bool {
+        self.hit.stdev > 0.0 && self.miss.stdev > 0.0
+    }
+
+    /// Posterior probability of the timing being a miss
+    pub fn miss_probability(&self, t: Time) -> f64 {
+        let x = t as f64;
+        if !self.is_fitted() {
+            return 0.5;
+        }
+        // The tails of a wider component may dominate far away from both means,
+        // but the order of the means should always hold
+        if x <= self.hit.mean {
+            return 0.0;
+        }
+        if x >= self.miss.mean {
+            return 1.0;
+        }
+
+        let (h, m) = (self.hit.density(x), self.miss.density(x));
+        if h + m == 0.0 {
+            return 0.5;
+        }
+
+        m / (h + m)
+    }
+}
+
+impl Default for GmmClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyClassifier for GmmClassifier {
+    fn record(&mut self, timing: CacheTiming) {
+        self.clusters.record(timing);
+    }
+
+    fn fit(&mut self) {
+        let (hits, misses) = (self.clusters.hits(), self.clusters.misses());
+        if hits.is_empty() || misses.is_empty() {
+            return;
+        }
+
+        // Labeled clusters give a good initial guess
+        let total = (hits.len() + misses.len()) as f64;
+        let init = |h: &Histogram<u64>| Gaussian {
+            weight: h.len() as f64 / total,
+            mean: h.mean(),
+            stdev: h.stdev().max(MIN_STDEV),
+        };
+        let mut comps = [init(hits), init(misses)];
+        let data = self.clusters.combined();
+
+        let mut prev_ll = f64::NEG_INFINITY;
+        for _ in 0..EM_MAX_ITER {
+            // E step: responsibilities of each component, accumulated per component
+            let mut acc = [(0.0, 0.0, 0.0); 2]; // (weight, sum, sum of squares)
+            let mut ll = 0.0;
+
+            for &(x, cnt) in &data {
+                let dens = [comps[0].density(x), comps[1].density(x)];
+                let sum = dens[0] + dens[1];
+                if sum <= 0.0 {
+                    continue;
+                }
+                ll += cnt * sum.ln();
+
+                for (a, d) in acc.iter_mut().zip(dens.iter()) {
+                    let r = cnt * d / sum;
+                    a.0 += r;
+                    a.1 += r * x;
+                    a.2 += r * x * x;
+                }
+            }
+
+            // M step
+            for (c, a) in comps.iter_mut().zip(acc.iter()) {
+                if a.0 <= 0.0 {
+                    continue;
+                }
+                c.weight = a.0 / total;
+                c.mean = a.1 / a.0;
+                c.stdev = (a.2 / a.0 - c.mean * c.mean).max(0.0).sqrt().max(MIN_STDEV);
+            }
+
+            if (ll - prev_ll).abs() < EM_EPS * ll.abs() {
+                break;
+            }
+            prev_ll = ll;
+        }
+
+        comps.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+        self.hit = comps[0];
+        self.miss = comps[1];
+    }
+
+    #[inline(always)]
+    fn classify(&self, t: Time) -> CacheTiming {
+        if self.miss_probability(t) > 0.5 {
+            CacheTiming::Miss(t)
+        } else {
+            CacheTiming::Hit(t)
+        }
+    }
+
+    fn certainty(&self, t: Time) -> f64 {
+        (2.0 * self.miss_probability(t) - 1.0).abs()
+    }
+
+    fn overlap(&self) -> Option<f64> {
+        self.clusters.overlap(|t| self.is_miss(t))
+    }
+
+    fn centroids(&self) -> Option<(f64, f64)> {
+        if self.is_fitted() {
+            Some((self.hit.mean, self.miss.mean))
+        } else {
+            None
+        }
+    }
+
+    fn state(&self) -> Option<ClassifierState> {
+        Some(self.clusters.state())
+    }
+
+    fn restore(&mut self, state: &ClassifierState) -> Result<()> {
+        self.clusters.restore(state);
+        self.fit();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.clusters.clear();
+        self.hit = Default::default();
+        self.miss = Default::default();
+    }
+}
+
+/// Classifier, which chooses the threshold, maximizing the between-class
+/// variance of the combined histogram (Otsu's method).
+pub struct OtsuClassifier {
+    clusters: Clusters,
+    threshold: f64,
+    hit_mean: f64,
+    miss_mean: f64,
+}
+
+impl OtsuClassifier {
+    pub fn new() -> Self {
+        OtsuClassifier {
+            clusters: Clusters::new(Aging::default()),
+            threshold: f64::INFINITY,
+            hit_mean: 0.0,
+            miss_mean: 0.0,
+        }
+    }
+
+    /// Ages the recorded timings. Drops anything recorded before.
+    pub fn aged(mut self, aging: Aging) -> Self {
+        self.clusters = Clusters::new(aging);
+        self
+    }
+
+    /// The fitted threshold. Timings above it are misses.
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+}
+
+impl Default for OtsuClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyClassifier for OtsuClassifier {
+    fn record(&mut self, timing: CacheTiming) {
+        self.clusters.record(timing);
+    }
+
+    fn fit(&mut self) {
+        let data = self.clusters.combined();
+        if data.len() < 2 {
+            return;
+        }
+
+        let total: f64 = data.iter().map(|(_, cnt)| cnt).sum();
+        let total_sum: f64 = data.iter().map(|(x, cnt)| x * cnt).sum();
+
+        let mut best = f64::NEG_INFINITY;
+        let (mut w0, mut sum0) = (0.0, 0.0);
+
+        // the split is after the i-th distinct value
+        for i in 0..data.len() - 1 {
+            let (x, cnt) = data[i];
+            w0 += cnt;
+            sum0 += x * cnt;
+
+            let w1 = total - w0;
+            let (mean0, mean1) = (sum0 / w0, (total_sum - sum0) / w1);
+            let between = w0 * w1 * (mean0 - mean1) * (mean0 - mean1);
+
+            if between > best {
+                best = between;
+                self.threshold = (x + data[i + 1].0) / 2.0;
+                self.hit_mean = mean0;
+                self.miss_mean = mean1;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn classify(&self, t: Time) -> CacheTiming {
+        if t as f64 > self.threshold {
+            CacheTiming::Miss(t)
+        } else {
+            CacheTiming::Hit(t)
+        }
+    }
+
+    fn certainty(&self, t: Time) -> f64 {
+        threshold_certainty(t, self.threshold, self.hit_mean, self.miss_mean)
+    }
+
+    fn overlap(&self) -> Option<f64> {
+        self.clusters.overlap(|t| self.is_miss(t))
+    }
+
+    fn centroids(&self) -> Option<(f64, f64)> {
+        if self.threshold.is_finite() {
+            Some((self.hit_mean, self.miss_mean))
+        } else {
+            None
+        }
+    }
+
+    fn state(&self) -> Option<ClassifierState> {
+        Some(self.clusters.state())
+    }
+
+    fn restore(&mut self, state: &ClassifierState) -> Result<()> {
+        self.clusters.restore(state);
+        self.fit();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.clusters.clear();
+        self.threshold = f64::INFINITY;
+        self.hit_mean = 0.0;
+        self.miss_mean = 0.0;
+    }
+}
+
+/// One latency level, learnt by `LevelClassifier`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    /// Mean timing of the level
+    pub center: f64,
+    pub stdev: f64,
+    /// Share of the recorded timings, which belong to the level
+    pub weight: f64,
+    /// Whether most of the timings of the level were recorded as misses
+    pub is_miss: bool,
+}
+
+/// Classifier, which learns several latency levels with k-means on the combined
+/// histogram. Each level is a hit or a miss level by the majority of its recorded
+/// timings. Timings far from every level are invalid instead of being forced into a class.
+pub struct LevelClassifier {
+    clusters: Clusters,
+    n: usize,
+    levels: Vec<Level>,
+}
+
+impl LevelClassifier {
+    /// Learns at most `n` levels. Fewer levels are learnt, if there are fewer distinct timings.
+    pub fn new(n: usize) -> Self {
+        LevelClassifier {
+            clusters: Clusters::new(Aging::default()),
+            n,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Ages the recorded timings. Drops anything recorded before.
+    pub fn aged(mut self, aging: Aging) -> Self {
+        self.clusters = Clusters::new(aging);
+        self
+    }
+
+    /// The learnt levels, from the fastest to the slowest
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    // Index of the closest level
+    fn nearest(&self, x: f64) -> Option<usize> {
+        nearest(self.levels.iter().map(|l| l.center), x)
+    }
+
+    // Distance to the closest level of the given class
+    fn distance_to(&self, x: f64, is_miss: bool) -> Option<f64> {
+        self.levels
+            .iter()
+            .filter(|l| l.is_miss == is_miss)
+            .map(|l| (l.center - x).abs())
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+
+    // The center of the heaviest level of the given class
+    fn main_center(&self, is_miss: bool) -> Option<f64> {
+        self.levels
+            .iter()
+            .filter(|l| l.is_miss == is_miss)
+            .max_by(|a, b| a.weight.partial_cmp(&b.weight).unwrap())
+            .map(|l| l.center)
+    }
+}
+
+// Index of the center, closest to `x`
+fn nearest<I: IntoIterator<Item = f64>>(centers: I, x: f64) -> Option<usize> {
+    centers
+        .into_iter()
+        .map(|c| (c - x).abs())
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(i, _)| i)
+}
+
+impl LatencyClassifier for LevelClassifier {
+    fn record(&mut self, timing: CacheTiming) {
+        self.clusters.record(timing);
+    }
+
+    fn fit(&mut self) {
+        let data = self.clusters.combined();
+        if data.is_empty() {
+            return;
+        }
+        let total: f64 = data.iter().map(|(_, cnt)| cnt).sum();
+
+        // Initial centers are evenly spread quantiles
+        let k = std::cmp::min(self.n, data.len());
+        let mut centers: Vec<f64> = (0..k)
+            .map(|i| {
+                let q = (i as f64 + 0.5) / k as f64 * total;
+                let mut acc = 0.0;
+                data.iter()
+                    .find(|(_, cnt)| {
+                        acc += cnt;
+                        acc >= q
+                    })
+                    .map_or(data[data.len() - 1].0, |(x, _)| *x)
+            })
+            .collect();
+        centers.dedup();
+
+        for _ in 0..EM_MAX_ITER {
+            let mut acc = vec![(0.0, 0.0); centers.len()]; // (weight, sum)
+            for &(x, cnt) in &data {
+                let a = &mut acc[nearest(centers.iter().copied(), x).unwrap()];
+                a.0 += cnt;
+                a.1 += cnt * x;
+            }
+
+            let moved: Vec<f64> = centers
+                .iter()
+                .zip(acc.iter())
+                .map(|(&c, &(w, sum))| if w > 0.0 { sum / w } else { c })
+                .collect();
+            let converged = moved
+                .iter()
+                .zip(centers.iter())
+                .all(|(a, b)| (a - b).abs() < EM_EPS);
+            centers = moved;
+
+            if converged {
+                break;
+            }
+        }
+
+        // (weight, sum of squared deviations, hits, misses) per level
+        let mut acc = vec![(0.0, 0.0, 0, 0); centers.len()];
+        for (h, is_miss) in &[(self.clusters.hits(), false), (self.clusters.misses(), true)] {
+            for v in h.iter_recorded() {
+                let (x, cnt) = (v.value_iterated_to() as f64, v.count_at_value());
+                let i = nearest(centers.iter().copied(), x).unwrap();
+                let a = &mut acc[i];
+                a.0 += cnt as f64;
+                a.1 += cnt as f64 * (x - centers[i]) * (x - centers[i]);
+                if *is_miss {
+                    a.3 += cnt;
+                } else {
+                    a.2 += cnt;
+                }
+            }
+        }
+
+        self.levels = centers
+            .iter()
+            .zip(acc.iter())
+            .filter(|(_, a)| a.0 > 0.0)
+            .map(|(&center, &(w, sq, hits, misses))| Level {
+                center,
+                stdev: (sq / w).sqrt().max(MIN_STDEV),
+                weight: w / total,
+                is_miss: misses > hits,
+            })
+            .collect();
+        self.levels
+            .sort_by(|a, b| a.center.partial_cmp(&b.center).unwrap());
+    }
+
+    /// Classifies by the closest level. Untrained classifier defaults to cache hit.
+    fn classify(&self, t: Time) -> CacheTiming {
+        let x = t as f64;
+        match self.nearest(x).map(|i| &self.levels[i]) {
+            None => CacheTiming::Hit(t),
+            Some(l) if (x - l.center).abs() > OUTLIER_STDEVS * l.stdev => CacheTiming::Invalid(t),
+            Some(l) if l.is_miss => CacheTiming::Miss(t),
+            Some(_) => CacheTiming::Hit(t),
+        }
+    }
+
+    fn certainty(&self, t: Time) -> f64 {
+        let x = t as f64;
+        let is_miss = match self.classify(t) {
+            CacheTiming::Invalid(_) => return 0.0,
+            timing => timing.is_miss(),
+        };
+
+        match (self.distance_to(x, is_miss), self.distance_to(x, !is_miss)) {
+            (Some(own), Some(other)) if own + other > 0.0 => {
+                ((other - own) / (other + own)).clamp(0.0, 1.0)
+            }
+            (Some(_), None) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn overlap(&self) -> Option<f64> {
+        self.clusters.overlap(|t| self.is_miss(t))
+    }
+
+    fn centroids(&self) -> Option<(f64, f64)> {
+        Some((self.main_center(false)?, self.main_center(true)?))
+    }
+
+    fn level(&self, t: Time) -> Option<usize> {
+        if self.is_invalid(t) {
+            return None;
+        }
+
+        Some(self.nearest(t as f64).unwrap_or(0))
+    }
+
+    fn state(&self) -> Option<ClassifierState> {
+        Some(self.clusters.state())
+    }
+
+    fn restore(&mut self, state: &ClassifierState) -> Result<()> {
+        self.clusters.restore(state);
+        self.fit();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.clusters.clear();
+        self.levels.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Hits around 100, misses around 300 with a long tail towards hits,
+    // as often observed remotely
+    fn train(classifier: &mut dyn LatencyClassifier) {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..2000 {
+            classifier.record(CacheTiming::Hit(rng.gen_range(90, 111)));
+            let miss = if rng.gen_bool(0.2) {
+                rng.gen_range(160, 260)
+            } else {
+                rng.gen_range(280, 321)
+            };
+            classifier.record(CacheTiming::Miss(miss));
+        }
+        classifier.fit();
+    }
+
+    #[test]
+    fn classifiers_separate() {
+        for kind in &[
+            ClassifierKind::Centroid,
+            ClassifierKind::Threshold(150),
+            ClassifierKind::Gmm,
+            ClassifierKind::Otsu,
+        ] {
+            let mut classifier = kind.build();
+            train(classifier.as_mut());
+
+            assert!(classifier.is_hit(100), "{}: hit misclassified", kind);
+            assert!(classifier.is_miss(300), "{}: miss misclassified", kind);
+            assert!(classifier.is_miss(1000), "{}: slow miss misclassified", kind);
+            assert!(classifier.is_hit(50), "{}: fast hit misclassified", kind);
+            assert!(
+                classifier.certainty(300) >= classifier.certainty(200),
+                "{}: certainty does not grow towards the miss cluster",
+                kind
+            );
+            assert!(classifier.certainty(300) > 0.9, "{}: uncertain miss", kind);
+        }
+    }
+
+    #[test]
+    fn tail_classification() {
+        // the median rule puts the boundary at 200, which misses a part of the tail
+        let mut centroid = ClassifierKind::Centroid.build();
+        let mut gmm = ClassifierKind::Gmm.build();
+        train(centroid.as_mut());
+        train(gmm.as_mut());
+
+        assert!(centroid.is_hit(180), "Expected the median rule to miss the tail");
+        assert!(gmm.is_miss(180), "GMM misclassified the tail");
+        assert!(gmm.is_hit(115), "GMM misclassified a slow hit");
+    }
+
+    #[test]
+    fn state_restoration() {
+        for kind in &[
+            ClassifierKind::Centroid,
+            ClassifierKind::Threshold(150),
+            ClassifierKind::Gmm,
+            ClassifierKind::Otsu,
+            ClassifierKind::Levels(3),
+        ] {
+            let mut classifier = kind.build_aged(Aging::Window(1000));
+            train(classifier.as_mut());
+            let state = classifier.state().expect("State is not saved");
+
+            let mut restored = kind.build_aged(Aging::Window(1000));
+            restored.restore(&state).expect("State is not restored");
+            assert_eq!(restored.state().as_ref(), Some(&state), "{}: state differs", kind);
+            for t in (0..500).step_by(10) {
+                assert_eq!(
+                    restored.level(t),
+                    classifier.level(t),
+                    "{}: {} is classified differently",
+                    kind,
+                    t
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kind_parsing() {
+        for s in &["centroid", "threshold:250", "gmm", "otsu", "levels:3"] {
+            let kind: ClassifierKind = s.parse().unwrap();
+            assert_eq!(&kind.to_string(), s, "Classifier kind roundtrip failed");
+        }
+        assert!("threshold".parse::<ClassifierKind>().is_err(), "Missing threshold accepted");
+        assert!("median".parse::<ClassifierKind>().is_err(), "Unknown kind accepted");
+        assert!("levels:1".parse::<ClassifierKind>().is_err(), "Single level accepted");
+    }
+
+    #[test]
+    fn multi_level_classification() {
+        // LLC hits, DDIO-way hits and DRAM misses
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut classifier = LevelClassifier::new(3);
+        for _ in 0..1000 {
+            classifier.record(CacheTiming::Hit(rng.gen_range(90, 111)));
+            classifier.record(CacheTiming::Hit(rng.gen_range(180, 201)));
+            classifier.record(CacheTiming::Miss(rng.gen_range(290, 311)));
+        }
+        classifier.fit();
+
+        let levels: Vec<bool> = classifier.levels().iter().map(|l| l.is_miss).collect();
+        assert_eq!(levels, vec![false, false, true], "Wrong levels learnt");
+        assert_eq!(classifier.level(100), Some(0), "Wrong level of LLC hit");
+        assert_eq!(classifier.level(190), Some(1), "Wrong level of DDIO hit");
+        assert_eq!(classifier.level(300), Some(2), "Wrong level of miss");
+        assert!(classifier.is_hit(195), "DDIO hit misclassified");
+        assert!(classifier.is_miss(305), "Miss misclassified");
+
+        // A retransmission is neither a hit nor a miss
+        assert!(classifier.is_invalid(5000), "Outlier is not invalid");
+        assert_eq!(classifier.level(5000), None, "Outlier has a level");
+        assert_eq!(classifier.certainty(5000), 0.0, "Outlier is certain");
+        assert!(!classifier.is_miss(5000), "Outlier is a miss");
+
+        // Two-class classifiers have two levels
+        let mut centroid = TimingClassifier::new();
+        centroid.record(CacheTiming::Hit(100));
+        centroid.record(CacheTiming::Miss(300));
+        assert_eq!(centroid.level(5000), Some(1), "Wrong level of a slow miss");
+    }
+
+    #[test]
+    fn aging_follows_drift() {
+        for &aging in &[Aging::Window(50), Aging::Decay(50)] {
+            let mut classifier = TimingClassifier::new().aged(aging);
+            for _ in 0..200 {
+                classifier.record(CacheTiming::Hit(100));
+                classifier.record(CacheTiming::Miss(300));
+            }
+            // the latency of the network grows
+            for _ in 0..200 {
+                classifier.record(CacheTiming::Hit(250));
+                classifier.record(CacheTiming::Miss(450));
+            }
+
+            assert!(classifier.is_hit(250), "{}: old hits are not forgotten", aging);
+            assert!(classifier.is_miss(400), "{}: old misses are not forgotten", aging);
+        }
+
+        let mut unbounded = TimingClassifier::new();
+        for _ in 0..200 {
+            unbounded.record(CacheTiming::Hit(100));
+            unbounded.record(CacheTiming::Miss(300));
+        }
+        unbounded.record(CacheTiming::Hit(250));
+        assert!(unbounded.is_miss(250), "Unbounded histograms should keep old timings");
+    }
+
+    #[test]
+    fn overlap_detection() {
+        let mut classifier = ClassifierKind::Centroid.build();
+        assert_eq!(classifier.overlap(), None, "Overlap of no timings");
+
+        for _ in 0..100 {
+            classifier.record(CacheTiming::Hit(100));
+            classifier.record(CacheTiming::Miss(300));
+        }
+        assert_eq!(classifier.overlap(), Some(0.0), "Separated clusters overlap");
+
+        for _ in 0..100 {
+            classifier.record(CacheTiming::Hit(280));
+            classifier.record(CacheTiming::Miss(120));
+        }
+        assert!(classifier.overlap().unwrap() > 0.2, "Overlap is not detected");
+    }
+
+    #[test]
+    fn aging_parsing() {
+        for s in &["none", "window:1000", "decay:500"] {
+            let aging: Aging = s.parse().unwrap();
+            assert_eq!(&aging.to_string(), s, "Aging roundtrip failed");
+        }
+        assert!("window:1".parse::<Aging>().is_err(), "Too small window accepted");
+        assert!("decay".parse::<Aging>().is_err(), "Missing half-life accepted");
     }
 }